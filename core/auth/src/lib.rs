@@ -1,30 +1,74 @@
 use std::{future::Future, sync::Arc};
 
-use proto::{Message, MessageBus};
+use ethereum::{EnvelopedDecodable, TransactionV2};
+use proto::{AuthRequest, Message, MessageBus};
 use tokio::sync::mpsc::Receiver;
+use txpool::{check_chain_id, recover_sender};
+
+/// Rules transactions are authenticated against.
+#[derive(Clone, Debug)]
+pub struct AuthConfig {
+    pub chain_id: u64,
+    /// Accept legacy transactions signed without EIP-155 replay protection.
+    pub allow_unprotected_txs: bool,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            chain_id: 1,
+            allow_unprotected_txs: false,
+        }
+    }
+}
 
 pub struct Server {
-    tx: Receiver<Message>,
+    tx: Receiver<Message<AuthRequest>>,
     bus: Arc<MessageBus>,
+    config: AuthConfig,
 }
 
 impl Server {
-    pub fn new(tx: Receiver<Message>, bus: Arc<MessageBus>) -> Self {
-        Self { tx, bus }
+    pub fn new(
+        tx: Receiver<Message<AuthRequest>>,
+        bus: Arc<MessageBus>,
+        config: AuthConfig,
+    ) -> Self {
+        Self { tx, bus, config }
     }
 
     pub async fn run(&mut self) -> result::Result<()> {
         while let Some(msg) = self.tx.recv().await {
+            if let Message::Close = msg {
+                break;
+            }
             self.process(msg).await?;
         }
 
         Ok(())
     }
 
-    async fn process(&mut self, msg: Message) -> result::Result<()> {
-        println!("auth msg {:?}", msg);
+    async fn process(&mut self, msg: Message<AuthRequest>) -> result::Result<()> {
+        if let Message::Request(request) = msg {
+            match request {
+                AuthRequest::VerifyTransaction(raw, reply) => {
+                    // The requester may have stopped waiting.
+                    let _ = reply.send(self.verify(&raw));
+                }
+            }
+        }
         Ok(())
     }
+
+    /// Decodes an EIP-2718 encoded transaction and checks it is signed for
+    /// this chain by a recoverable sender.
+    fn verify(&self, raw: &[u8]) -> Result<TransactionV2, String> {
+        let tx = TransactionV2::decode(raw).map_err(|e| format!("invalid transaction: {:?}", e))?;
+        check_chain_id(&tx, self.config.chain_id, self.config.allow_unprotected_txs)
+            .map_err(|e| e.to_string())?;
+        recover_sender(&tx).map_err(|e| e.to_string())?;
+        Ok(tx)
+    }
 }
 impl Future for Server {
     type Output = result::Result<()>;
@@ -36,3 +80,74 @@ impl Future for Server {
         Box::pin(self.run()).as_mut().poll(cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethereum::{
+        EIP1559Transaction, EIP1559TransactionMessage, EnvelopedEncodable, TransactionAction,
+    };
+    use ethereum_types::{H160, H256, U256};
+    use tokio::sync::mpsc::channel;
+
+    fn transfer(chain_id: u64) -> EIP1559Transaction {
+        let message = EIP1559TransactionMessage {
+            chain_id,
+            nonce: U256::zero(),
+            max_priority_fee_per_gas: U256::one(),
+            max_fee_per_gas: U256::one(),
+            gas_limit: U256::from(21000),
+            action: TransactionAction::Call(H160::repeat_byte(0xaa)),
+            value: U256::zero(),
+            input: vec![],
+            access_list: vec![],
+        };
+        let secret = libsecp256k1::SecretKey::parse(&[1; 32]).unwrap();
+        let (signature, recid) =
+            libsecp256k1::sign(&libsecp256k1::Message::parse(&message.hash().0), &secret);
+        let rs = signature.serialize();
+        EIP1559Transaction {
+            chain_id,
+            nonce: message.nonce,
+            max_priority_fee_per_gas: message.max_priority_fee_per_gas,
+            max_fee_per_gas: message.max_fee_per_gas,
+            gas_limit: message.gas_limit,
+            action: message.action,
+            value: message.value,
+            input: message.input,
+            access_list: message.access_list,
+            odd_y_parity: recid.serialize() != 0,
+            r: H256::from_slice(&rs[0..32]),
+            s: H256::from_slice(&rs[32..64]),
+        }
+    }
+
+    /// Sends `tx` to an auth server of chain 1 through the bus.
+    async fn verify(tx: EIP1559Transaction) -> Result<TransactionV2, String> {
+        let (auth_sender, rx) = channel(1);
+        let bus = Arc::new(MessageBus {
+            jsonrpc_sender: channel(1).0,
+            auth_sender,
+            chain_sender: channel(1).0,
+        });
+        tokio::spawn(Server::new(rx, bus.clone(), AuthConfig::default()));
+        let raw = TransactionV2::EIP1559(tx).encode().to_vec();
+        bus.auth(|reply| AuthRequest::VerifyTransaction(raw, reply))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn verifies_transactions() {
+        let tx = transfer(1);
+        assert_eq!(verify(tx.clone()).await, Ok(TransactionV2::EIP1559(tx)));
+
+        let err = verify(transfer(2)).await.unwrap_err();
+        assert!(err.starts_with("invalid chain id"), "{}", err);
+
+        let mut forged = transfer(1);
+        forged.s = H256::zero();
+        let err = verify(forged).await.unwrap_err();
+        assert!(err.starts_with("invalid signature"), "{}", err);
+    }
+}
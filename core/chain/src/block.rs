@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+
+use ethereum::TransactionAction;
+use ethereum_types::{Bloom, H160, H256, U256};
+pub use proto::block_hash as hash;
+use proto::Block;
+use protobuf::Message;
+use rpc_core::types::{BlockTransactions, Header, Rich, RichBlock};
+
+use crate::receipt::Receipt;
+use crate::transaction::from_signed;
+
+/// RPC representation of `block`, with its full transactions if `full` or
+/// only their hashes otherwise.
+pub fn rich_block(block: &Block, receipts: &[Receipt], full: bool) -> result::Result<RichBlock> {
+    let header = block.header.get_or_default();
+    let block_hash = hash(header);
+    let proposer = h160(&header.proposer);
+    let mut logs_bloom = Bloom::default();
+    for receipt in receipts {
+        logs_bloom.accrue_bloom(&receipt.data().logs_bloom);
+    }
+
+    let signed = &block.body.transactions;
+    let transactions = if full {
+        let mut transactions = Vec::with_capacity(signed.len());
+        for (index, signed) in signed.iter().enumerate() {
+            let tx = from_signed(signed)?;
+            let action = match &tx {
+                ethereum::TransactionV2::Legacy(t) => t.action,
+                ethereum::TransactionV2::EIP2930(t) => t.action,
+                ethereum::TransactionV2::EIP1559(t) => t.action,
+            };
+            let mut transaction = rpc_core::types::Transaction::from(tx);
+            transaction.block_hash = Some(block_hash);
+            transaction.block_number = Some(U256::from(header.height));
+            transaction.transaction_index = Some(U256::from(index));
+            transaction.from = h160(&signed.signer);
+            match action {
+                TransactionAction::Call(to) => transaction.to = Some(to),
+                TransactionAction::Create => {
+                    transaction.creates = receipts
+                        .get(index)
+                        .and_then(|receipt| receipt.contract_address)
+                }
+            }
+            transactions.push(transaction);
+        }
+        BlockTransactions::Full(transactions)
+    } else {
+        BlockTransactions::Hashes(signed.iter().map(|signed| h256(&signed.tx_hash)).collect())
+    };
+
+    let size = Some(U256::from(block.compute_size()));
+    Ok(Rich {
+        inner: rpc_core::types::Block {
+            header: Header {
+                hash: Some(block_hash),
+                parent_hash: h256(&header.prevhash),
+                uncles_hash: H256::from(hashing::keccak_256(&rlp::EMPTY_LIST_RLP)),
+                author: proposer,
+                miner: proposer,
+                state_root: h256(&header.state_root),
+                transactions_root: h256(&header.transactions_root),
+                receipts_root: h256(&header.receipts_root),
+                number: Some(U256::from(header.height)),
+                gas_used: U256::from(header.quota_used),
+                gas_limit: U256::from(header.quota_limit),
+                extra_data: Default::default(),
+                logs_bloom,
+                timestamp: U256::from(header.timestamp),
+                difficulty: U256::zero(),
+                nonce: None,
+                size,
+            },
+            total_difficulty: U256::zero(),
+            uncles: vec![],
+            transactions,
+            size,
+            base_fee_per_gas: None,
+        },
+        extra_info: BTreeMap::new(),
+    })
+}
+
+/// Hash of a header field, zero when the field is unset.
+fn h256(bytes: &[u8]) -> H256 {
+    match bytes.len() {
+        32 => H256::from_slice(bytes),
+        _ => H256::zero(),
+    }
+}
+
+fn h160(bytes: &[u8]) -> H160 {
+    match bytes.len() {
+        20 => H160::from_slice(bytes),
+        _ => H160::zero(),
+    }
+}
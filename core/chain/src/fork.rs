@@ -0,0 +1,69 @@
+use ethereum_types::H256;
+use kvdb::KeyValueDB;
+
+use crate::store::ChainStore;
+
+/// Whether the stored block `hash` should replace the best block as head.
+///
+/// A block finalized by consensus always does. Other blocks must descend
+/// from the finalized block and be higher than the best block, so the first
+/// block seen wins among blocks of equal height.
+pub fn is_better<T: KeyValueDB>(
+    store: &ChainStore<T>,
+    hash: &H256,
+    finalized: bool,
+) -> result::Result<bool> {
+    if finalized {
+        return Ok(true);
+    }
+    let best = match store.best_header()? {
+        Some(best) => best,
+        None => return Ok(true),
+    };
+    if let Some(finalized) = store.finalized_hash()? {
+        if !store.is_descendant(hash, &finalized)? {
+            return Ok(false);
+        }
+    }
+    Ok(store.header(hash)?.map(|header| header.height) > Some(best.height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::{Block, BlockHeader};
+    use std::sync::Arc;
+
+    fn block(parent: &BlockHeader, timestamp: u64) -> Block {
+        let mut header = BlockHeader::new();
+        header.prevhash = crate::block::hash(parent).as_bytes().to_vec();
+        header.height = parent.height + 1;
+        header.timestamp = timestamp;
+        let mut block = Block::new();
+        block.header = Some(header).into();
+        block
+    }
+
+    #[test]
+    fn prefers_finalized_then_higher_blocks() {
+        let store = ChainStore::new(Arc::new(kvdb_memorydb::create(crate::NUM_COLUMNS)));
+        let genesis = block(&BlockHeader::new(), 0);
+        let a1 = block(genesis.header.get_or_default(), 1);
+        let b1 = block(genesis.header.get_or_default(), 2);
+        let b2 = block(b1.header.get_or_default(), 3);
+        store.commit(&genesis, &[]).unwrap();
+        let a1 = store.insert(&a1, &[]).unwrap();
+        assert!(is_better(&store, &a1, false).unwrap());
+        store.set_head(&a1).unwrap();
+
+        let b1 = store.insert(&b1, &[]).unwrap();
+        let b2 = store.insert(&b2, &[]).unwrap();
+        assert!(!is_better(&store, &b1, false).unwrap());
+        assert!(is_better(&store, &b2, false).unwrap());
+        assert!(is_better(&store, &b1, true).unwrap());
+
+        // Once a1 is final, the longer branch no longer qualifies.
+        store.set_finalized(&a1).unwrap();
+        assert!(!is_better(&store, &b2, false).unwrap());
+    }
+}
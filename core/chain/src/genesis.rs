@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::anyhow;
+use ethereum_types::{H160, H256, U256};
+use evm::{Executive, MemoryAccount};
+use kvdb::KeyValueDB;
+use proto::{Block, BlockBody, BlockHeader};
+use serde::{Deserialize, Deserializer};
+
+use crate::store::ChainStore;
+
+/// Genesis specification, loaded from JSON.
+///
+/// Hashes, addresses and quantities are `0x` prefixed hex strings.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Genesis {
+    pub chain_id: u64,
+    /// Timestamp of the genesis block in seconds.
+    #[serde(default)]
+    pub timestamp: u64,
+    pub quota_limit: u64,
+    /// Addresses allowed to propose and vote on blocks.
+    #[serde(default)]
+    pub validators: Vec<H160>,
+    /// Accounts present in the genesis state.
+    #[serde(default)]
+    pub alloc: BTreeMap<H160, GenesisAccount>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenesisAccount {
+    pub balance: U256,
+    pub nonce: U256,
+    #[serde(deserialize_with = "deserialize_hex")]
+    pub code: Vec<u8>,
+    pub storage: BTreeMap<H256, H256>,
+}
+
+impl Default for Genesis {
+    fn default() -> Self {
+        Self {
+            chain_id: 1,
+            timestamp: 0,
+            quota_limit: 1_073_741_824,
+            validators: vec![],
+            alloc: BTreeMap::new(),
+        }
+    }
+}
+
+impl Genesis {
+    pub fn from_file(path: impl AsRef<Path>) -> result::Result<Self> {
+        let file = std::fs::File::open(path.as_ref())?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    /// Root of the Ethereum state trie holding the genesis accounts.
+    pub fn state_root(&self) -> H256 {
+        ethereum::util::sec_trie_root(self.alloc.iter().map(|(address, account)| {
+            let storage_root = ethereum::util::sec_trie_root(
+                account
+                    .storage
+                    .iter()
+                    .filter(|(_, value)| !value.is_zero())
+                    .map(|(key, value)| {
+                        (key, rlp::encode(&U256::from_big_endian(value.as_bytes())))
+                    }),
+            );
+            let account = ethereum::Account {
+                nonce: account.nonce,
+                balance: account.balance,
+                storage_root,
+                code_hash: H256::from(hashing::keccak_256(&account.code)),
+            };
+            (address, rlp::encode(&account))
+        }))
+    }
+
+    /// The height 0 block committing to the genesis state.
+    pub fn block(&self) -> Block {
+        let mut header = BlockHeader::new();
+        header.prevhash = H256::zero().as_bytes().to_vec();
+        header.timestamp = self.timestamp;
+        header.height = 0;
+        header.state_root = self.state_root().as_bytes().to_vec();
+        header.transactions_root = trie::EMPTY_ROOT.as_bytes().to_vec();
+        header.receipts_root = trie::EMPTY_ROOT.as_bytes().to_vec();
+        header.quota_limit = self.quota_limit;
+
+        let mut block = Block::new();
+        block.header = Some(header).into();
+        block.body = Some(BlockBody::new()).into();
+        block
+    }
+
+    /// Writes the genesis state and block on first start. On later starts,
+    /// checks the stored chain was created from this genesis.
+    pub fn init<T: KeyValueDB>(
+        &self,
+        store: &ChainStore<T>,
+        executive: &Executive<T>,
+    ) -> result::Result<H256> {
+        let block = self.block();
+        let hash = crate::block::hash(block.header.get_or_default());
+
+        match store.block_hash(0)? {
+            Some(stored) if stored == hash => Ok(hash),
+            Some(stored) => Err(anyhow!(
+                "genesis mismatch: database has {:?}, spec gives {:?}",
+                stored,
+                hash
+            )),
+            None => {
+                let mut state = executive.begin_block();
+                state.insert_accounts(
+                    self.alloc
+                        .iter()
+                        .map(|(address, account)| {
+                            let account = MemoryAccount {
+                                nonce: account.nonce,
+                                balance: account.balance,
+                                storage: account.storage.clone(),
+                                code: account.code.clone(),
+                            };
+                            (*address, account)
+                        })
+                        .collect(),
+                )?;
+                let state_root = state.state_root();
+                if state_root.as_bytes() != block.header.state_root {
+                    return Err(anyhow!(
+                        "genesis state root mismatch: state has {:?}",
+                        state_root
+                    ));
+                }
+                state.commit()?;
+                store.commit(&block, &[])
+            }
+        }
+    }
+}
+
+fn deserialize_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    hex::decode(s.trim_start_matches("0x")).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use txpool::StateReader;
+
+    const SPEC: &str = r#"{
+        "chainId": 1337,
+        "timestamp": 1000,
+        "quotaLimit": 30000000,
+        "validators": ["0x0000000000000000000000000000000000000007"],
+        "alloc": {
+            "0x0000000000000000000000000000000000000001": {
+                "balance": "0xde0b6b3a7640000",
+                "nonce": "0x2"
+            },
+            "0x0000000000000000000000000000000000000002": {
+                "code": "0x600160005500",
+                "storage": {
+                    "0x0000000000000000000000000000000000000000000000000000000000000000":
+                    "0x0000000000000000000000000000000000000000000000000000000000000001"
+                }
+            }
+        }
+    }"#;
+
+    #[test]
+    fn empty_state_root() {
+        // Root of an empty trie.
+        assert_eq!(
+            format!("{:?}", Genesis::default().state_root()),
+            "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+        );
+    }
+
+    #[test]
+    fn writes_genesis_state_and_block() {
+        let genesis = serde_json::from_str::<Genesis>(SPEC).unwrap();
+        assert_eq!(genesis.validators, vec![H160::from_low_u64_be(7)]);
+
+        let db = Arc::new(kvdb_memorydb::create(crate::store::NUM_COLUMNS));
+        let store = ChainStore::new(db.clone());
+        let executive = Executive::new(db);
+        let hash = genesis.init(&store, &executive).unwrap();
+
+        let (funded, contract) = (H160::from_low_u64_be(1), H160::from_low_u64_be(2));
+        assert_eq!(
+            executive.basic(funded).balance,
+            U256::from(1_000_000_000_000_000_000u64)
+        );
+        assert_eq!(executive.nonce(&funded), U256::from(2));
+        assert_eq!(
+            executive.code(contract),
+            vec![0x60, 0x01, 0x60, 0x00, 0x55, 0x00]
+        );
+        assert_eq!(
+            executive.storage(contract, H256::zero()),
+            H256::from_low_u64_be(1)
+        );
+
+        let header = store.best_header().unwrap().unwrap();
+        assert_eq!(store.best_hash().unwrap(), Some(hash));
+        assert_eq!(header.height, 0);
+        assert_eq!(header.timestamp, 1000);
+        assert_eq!(header.state_root, genesis.state_root().as_bytes());
+        assert_eq!(executive.state_root(), genesis.state_root());
+
+        // Restarting with the same spec is fine, another one is refused.
+        assert_eq!(genesis.init(&store, &executive).unwrap(), hash);
+        let other = Genesis {
+            timestamp: 2000,
+            ..genesis
+        };
+        assert!(other.init(&store, &executive).is_err());
+    }
+}
@@ -0,0 +1,315 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use ethereum_types::{H160, H256, U256};
+use evm::{BlockExecution, Executive};
+use kvdb::KeyValueDB;
+use proto::{Block, BlockHeader};
+use txpool::{PoolConfig, PoolTransaction};
+
+use crate::producer::{execute, vicinity};
+use crate::receipt::{receipts_root, Receipt};
+use crate::store::ChainStore;
+use crate::transaction::{from_signed, transactions_root};
+
+/// Rules blocks received from peers are checked against.
+#[derive(Clone, Debug)]
+pub struct ImportConfig {
+    pub chain_id: u64,
+    /// Quota limit every block must declare.
+    pub quota_limit: u64,
+    /// Addresses allowed to propose blocks, any proposer if empty.
+    pub validators: Vec<H160>,
+}
+
+impl Default for ImportConfig {
+    fn default() -> Self {
+        Self {
+            chain_id: 1,
+            quota_limit: 1_073_741_824,
+            validators: vec![],
+        }
+    }
+}
+
+/// Validates blocks produced by other nodes before they are committed.
+pub struct BlockImporter<T> {
+    config: ImportConfig,
+    store: ChainStore<T>,
+    executive: Arc<Executive<T>>,
+}
+
+impl<T: KeyValueDB> BlockImporter<T> {
+    pub fn new(config: ImportConfig, store: ChainStore<T>, executive: Arc<Executive<T>>) -> Self {
+        Self {
+            config,
+            store,
+            executive,
+        }
+    }
+
+    pub fn config(&self) -> &ImportConfig {
+        &self.config
+    }
+
+    /// Checks `block` extends a known block, re-executes its transactions on
+    /// top of the parent state and compares the resulting roots with the
+    /// header.
+    ///
+    /// Returns the receipts and the state changes of the block, which are
+    /// left uncommitted for the caller to write along with the block.
+    pub fn verify(&self, block: &Block) -> result::Result<(Vec<Receipt>, BlockExecution<'_, T>)> {
+        let header = block
+            .header
+            .as_ref()
+            .ok_or_else(|| anyhow!("block without header"))?;
+        let hash = crate::block::hash(header);
+        if self.store.header(&hash)?.is_some() {
+            return Err(anyhow!("block {:?} already imported", hash));
+        }
+        let parent_hash = H256::from_slice(check_len(&header.prevhash, 32, "parent hash")?);
+        let parent = match self.store.header(&parent_hash)? {
+            Some(parent) => parent,
+            None => return Err(anyhow!("unknown parent {:?} of {:?}", parent_hash, hash)),
+        };
+        self.verify_header(header, &parent)?;
+
+        let body = block.body.get_or_default();
+        if transactions_root(&body.transactions).as_bytes() != header.transactions_root {
+            return Err(anyhow!("transactions root mismatch"));
+        }
+
+        let vicinity = vicinity(
+            self.config.chain_id,
+            parent_hash,
+            header.height,
+            header.timestamp,
+            H160::from_slice(&header.proposer),
+            header.quota_limit,
+        );
+        let pool_config = PoolConfig {
+            chain_id: self.config.chain_id,
+            ..Default::default()
+        };
+        let mut state = self
+            .executive
+            .begin_block_at(H256::from_slice(&parent.state_root))?;
+        let mut quota_used = 0u64;
+        let mut receipts = Vec::with_capacity(body.transactions.len());
+        for (index, signed) in body.transactions.iter().enumerate() {
+            let tx = PoolTransaction::new(from_signed(signed)?, &pool_config)?;
+            if tx.hash.as_bytes() != signed.tx_hash {
+                return Err(anyhow!("transaction {} hash mismatch", index));
+            }
+            if tx.sender.as_bytes() != signed.signer {
+                return Err(anyhow!("transaction {} signer mismatch", index));
+            }
+
+            let outcome = execute(&mut state, &tx, vicinity.clone())?;
+            quota_used = quota_used
+                .checked_add(outcome.used_gas.low_u64())
+                .filter(|used| *used <= header.quota_limit)
+                .ok_or_else(|| anyhow!("transaction {} exceeds the quota limit", index))?;
+            receipts.push(Receipt::new(
+                &tx.transaction,
+                tx.hash,
+                index as u32,
+                header.height,
+                tx.sender,
+                &outcome.exit_reason,
+                outcome.created,
+                outcome.used_gas,
+                U256::from(quota_used),
+                outcome.logs,
+            ));
+        }
+
+        if quota_used != header.quota_used {
+            return Err(anyhow!(
+                "quota used mismatch: header has {}, execution used {}",
+                header.quota_used,
+                quota_used
+            ));
+        }
+        if state.state_root().as_bytes() != header.state_root {
+            return Err(anyhow!(
+                "state root mismatch: execution gives {:?}",
+                state.state_root()
+            ));
+        }
+        if receipts_root(&receipts).as_bytes() != header.receipts_root {
+            return Err(anyhow!("receipts root mismatch"));
+        }
+
+        for receipt in &mut receipts {
+            receipt.block_hash = hash;
+        }
+        Ok((receipts, state))
+    }
+
+    /// Checks the header fields that don't depend on execution.
+    fn verify_header(&self, header: &BlockHeader, parent: &BlockHeader) -> result::Result<()> {
+        if header.height != parent.height + 1 {
+            return Err(anyhow!(
+                "height {} doesn't follow parent height {}",
+                header.height,
+                parent.height
+            ));
+        }
+        if header.timestamp <= parent.timestamp {
+            return Err(anyhow!(
+                "timestamp {} not after parent timestamp {}",
+                header.timestamp,
+                parent.timestamp
+            ));
+        }
+
+        let proposer = H160::from_slice(check_len(&header.proposer, 20, "proposer")?);
+        if !self.config.validators.is_empty() && !self.config.validators.contains(&proposer) {
+            return Err(anyhow!("proposer {:?} is not a validator", proposer));
+        }
+
+        if header.quota_limit != self.config.quota_limit {
+            return Err(anyhow!(
+                "quota limit {} differs from {}",
+                header.quota_limit,
+                self.config.quota_limit
+            ));
+        }
+        if header.quota_used > header.quota_limit {
+            return Err(anyhow!(
+                "quota used {} above the limit {}",
+                header.quota_used,
+                header.quota_limit
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn check_len<'a>(bytes: &'a [u8], len: usize, field: &str) -> result::Result<&'a [u8]> {
+    if bytes.len() != len {
+        return Err(anyhow!("invalid {} length {}", field, bytes.len()));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::producer::tests::{setup, transfer, Db};
+    use crate::{BlockProducer, Genesis, ProducerConfig};
+    use txpool::TransactionPool;
+
+    type Node = (ChainStore<Db>, Arc<Executive<Db>>);
+
+    fn node() -> Node {
+        let db = Arc::new(kvdb_memorydb::create(crate::store::NUM_COLUMNS));
+        let store = ChainStore::new(db.clone());
+        let executive = Arc::new(Executive::new(db));
+        Genesis::default().init(&store, &executive).unwrap();
+        (store, executive)
+    }
+
+    /// Produces block 1 with two transfers on a separate node.
+    fn produce(proposer: H160) -> Block {
+        let (pool, executive) = setup();
+        let secret = libsecp256k1::SecretKey::parse(&[1; 32]).unwrap();
+        for nonce in 0..2 {
+            pool.submit_one(transfer(&secret, nonce, 21000)).unwrap();
+        }
+        let producer = BlockProducer::new(
+            ProducerConfig {
+                proposer,
+                ..Default::default()
+            },
+            pool,
+            executive,
+        );
+        let genesis = Genesis::default().block();
+        producer
+            .produce(genesis.header.get_or_default(), 10)
+            .unwrap()
+            .0
+    }
+
+    fn importer((store, executive): &Node, validators: Vec<H160>) -> BlockImporter<Db> {
+        let config = ImportConfig {
+            validators,
+            ..Default::default()
+        };
+        BlockImporter::new(config, store.clone(), executive.clone())
+    }
+
+    #[test]
+    fn imports_valid_block() {
+        let node = node();
+        let block = produce(H160::repeat_byte(7));
+        let importer = importer(&node, vec![H160::repeat_byte(7)]);
+        let (receipts, state) = importer.verify(&block).unwrap();
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(state.state_root().as_bytes(), block.header.state_root);
+        assert_eq!(
+            receipts[0].block_hash,
+            crate::block::hash(block.header.get_or_default())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_blocks() {
+        let node = node();
+        let importer = importer(&node, vec![H160::repeat_byte(7)]);
+        let block = produce(H160::repeat_byte(7));
+        let tampered = |change: &dyn Fn(&mut Block)| {
+            let mut block = block.clone();
+            change(&mut block);
+            importer.verify(&block).map(|_| ())
+        };
+
+        assert!(tampered(&|b| b.header.mut_or_insert_default().height = 2).is_err());
+        assert!(tampered(&|b| b.header.mut_or_insert_default().timestamp = 0).is_err());
+        assert!(tampered(&|b| b.header.mut_or_insert_default().prevhash = vec![1; 32]).is_err());
+        assert!(tampered(&|b| b.header.mut_or_insert_default().proposer = vec![8; 20]).is_err());
+        assert!(tampered(&|b| b.header.mut_or_insert_default().quota_limit = 1).is_err());
+        assert!(tampered(&|b| b.header.mut_or_insert_default().quota_used += 1).is_err());
+        assert!(tampered(&|b| b.header.mut_or_insert_default().state_root = vec![0; 32]).is_err());
+        assert!(
+            tampered(&|b| b.header.mut_or_insert_default().receipts_root = vec![0; 32]).is_err()
+        );
+        assert!(tampered(&|b| {
+            b.body.mut_or_insert_default().transactions.pop();
+        })
+        .is_err());
+        assert!(tampered(&|_| {}).is_ok());
+
+        // Rejected blocks leave the state untouched.
+        let (_, executive) = &node;
+        assert_eq!(executive.state_root(), Genesis::default().state_root());
+    }
+
+    #[test]
+    fn rejects_forged_transactions() {
+        let node = node();
+        let importer = importer(&node, vec![H160::repeat_byte(7)]);
+        let block = produce(H160::repeat_byte(7));
+        let tampered = |change: &dyn Fn(&mut Block)| {
+            let mut block = block.clone();
+            change(&mut block);
+            importer.verify(&block).map(|_| ()).unwrap_err().to_string()
+        };
+
+        // A transaction mined earlier in the block can't be replayed.
+        let replayed = tampered(&|b| {
+            let transactions = &mut b.body.mut_or_insert_default().transactions;
+            transactions.push(transactions[0].clone());
+            let root = transactions_root(transactions);
+            b.header.mut_or_insert_default().transactions_root = root.as_bytes().to_vec();
+        });
+        assert!(replayed.contains("invalid nonce"), "{}", replayed);
+
+        let forged = tampered(&|b| {
+            b.body.mut_or_insert_default().transactions[0].signer = vec![9; 20];
+        });
+        assert!(forged.contains("signer mismatch"), "{}", forged);
+    }
+}
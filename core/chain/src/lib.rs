@@ -1,32 +1,455 @@
+mod block;
+mod fork;
+mod genesis;
+mod import;
+mod producer;
+mod receipt;
+mod state;
+mod store;
+mod transaction;
+
+use std::collections::{HashMap, HashSet};
 use std::{future::Future, sync::Arc};
 
-use proto::{Message, MessageBus};
-use tokio::sync::mpsc::Receiver;
+use anyhow::anyhow;
+use ethereum_types::H256;
+use kvdb::KeyValueDB;
+use proto::communication::inner_message::Content;
+use proto::{
+    Block, BlockHeader, BlockWithProof, ChainRequest, InnerMessage, Message, MessageBus, RichStatus,
+};
+use rpc_core::types::RichBlock;
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tracing::{debug, info, warn};
+use txpool::TransactionPool;
+
+pub use block::{hash as block_hash, rich_block};
+pub use fork::is_better;
+pub use genesis::{Genesis, GenesisAccount};
+pub use import::{BlockImporter, ImportConfig};
+pub use producer::{BlockProducer, ProducerConfig};
+pub use receipt::{logs_bloom, receipts_root, rpc_logs, rpc_receipt, Receipt};
+pub use state::{Pruning, StateProvider};
+pub use store::{ChainStore, TransactionLocation, TreeRoute, COL_STATE, NUM_COLUMNS};
+pub use transaction::{from_signed, to_signed, transactions_root, TransactionInfo};
+
+/// Capacity of the chain event channel.
+const EVENT_CAPACITY: usize = 1024;
 
-pub struct Server {
-    tx: Receiver<Message>,
+/// Change of the canonical chain.
+#[derive(Clone, Debug)]
+pub enum ChainEvent {
+    /// The block `hash` became the head.
+    NewHead { hash: H256, height: u64 },
+    /// Logs of the blocks that left the canonical chain, flagged `removed`,
+    /// followed by those of the blocks that joined it.
+    Logs(Vec<rpc_core::types::Log>),
+}
+
+pub struct Server<P, T> {
+    tx: Receiver<Message<ChainRequest>>,
     bus: Arc<MessageBus>,
+    pool: Arc<P>,
+    producer: BlockProducer<P, T>,
+    importer: BlockImporter<T>,
+    store: ChainStore<T>,
+    state: Arc<StateProvider<T>>,
+    /// Header of the best block.
+    head: BlockHeader,
+    events: broadcast::Sender<ChainEvent>,
+    /// Blocks verified or produced for consensus, with their state written,
+    /// until one of their height is committed.
+    candidates: HashMap<H256, (Block, Vec<Receipt>)>,
+    /// Consensus service checking the seal of the blocks of other nodes
+    /// before adding them.
+    consensus: Option<Sender<Content>>,
 }
 
-impl Server {
-    pub fn new(tx: Receiver<Message>, bus: Arc<MessageBus>) -> Self {
-        Self { tx, bus }
+impl<P: TransactionPool, T: KeyValueDB> Server<P, T> {
+    pub fn new(
+        tx: Receiver<Message<ChainRequest>>,
+        bus: Arc<MessageBus>,
+        pool: Arc<P>,
+        producer: BlockProducer<P, T>,
+        importer: BlockImporter<T>,
+        store: ChainStore<T>,
+        state: Arc<StateProvider<T>>,
+    ) -> result::Result<Self> {
+        let head = store.best_header()?.unwrap_or_default();
+        Ok(Self {
+            tx,
+            bus,
+            pool,
+            producer,
+            importer,
+            store,
+            state,
+            head,
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            candidates: HashMap::new(),
+            consensus: None,
+        })
+    }
+
+    /// Passes the blocks received from other nodes to `consensus`, which
+    /// checks their seal before submitting them back. Without consensus
+    /// they are dropped, their seal being unchecked.
+    pub fn forward_blocks(&mut self, consensus: Sender<Content>) {
+        self.consensus = Some(consensus);
     }
 
+    /// Serves requests until closed. Blocks are produced and added on the
+    /// requests of the consensus engine.
     pub async fn run(&mut self) -> result::Result<()> {
-        while let Some(msg) = self.tx.recv().await {
-            self.process(msg).await?;
+        loop {
+            match self.tx.recv().await {
+                Some(Message::Close) | None => break,
+                Some(msg) => self.process(msg).await?,
+            }
         }
 
         Ok(())
     }
 
-    async fn process(&mut self, msg: Message) -> result::Result<()> {
-        println!("chain msg {:?}", msg);
+    /// Subscribes to changes of the canonical chain.
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+        self.events.subscribe()
+    }
+
+    /// Produces a block on top of the head for a consensus engine to propose,
+    /// keeping it as a candidate until it is committed.
+    fn propose(&mut self) -> result::Result<Block> {
+        let (block, receipts, state) = self
+            .producer
+            .produce(&self.head, self.producer.timestamp())?;
+        let hash = block_hash(block.header.get_or_default());
+        self.state.write(state, block.header.height, hash)?;
+        self.candidates.insert(hash, (block.clone(), receipts));
+        Ok(block)
+    }
+
+    /// Verifies a block produced by another node and keeps it as a candidate.
+    ///
+    /// Returns the hash of the block, or the reason it was rejected.
+    fn verify(&mut self, block: &Block) -> result::Result<Result<H256, String>> {
+        let hash = block_hash(block.header.get_or_default());
+        if self.candidates.contains_key(&hash) {
+            return Ok(Ok(hash));
+        }
+        match self.importer.verify(block) {
+            Ok((receipts, state)) => {
+                self.state.write(state, block.header.height, hash)?;
+                self.candidates.insert(hash, (block.clone(), receipts));
+                Ok(Ok(hash))
+            }
+            Err(e) => {
+                warn!("rejected block {}: {}", block.header.height, e);
+                Ok(Err(e.to_string()))
+            }
+        }
+    }
+
+    /// Verifies a block received from a peer and adds it if it is valid.
+    /// `finalized` blocks come with a consensus proof of their finality.
+    ///
+    /// Returns the hash of the block, or the reason it was rejected.
+    fn import(&mut self, block: &Block, finalized: bool) -> result::Result<Result<H256, String>> {
+        let hash = match self.verify(block)? {
+            Ok(hash) => hash,
+            Err(e) => return Ok(Err(e)),
+        };
+        // Candidates lack the proof the block may come with.
+        let (_, receipts) = self
+            .candidates
+            .remove(&hash)
+            .expect("verified blocks are candidates");
+        if let Err(e) = self.add_block(block, &receipts, finalized)? {
+            warn!("rejected block {}: {}", block.header.height, e);
+            return Ok(Err(e));
+        }
+        info!(
+            "imported block {} {:?} with {} transactions",
+            block.header.height,
+            hash,
+            block.body.transactions.len()
+        );
+        Ok(Ok(hash))
+    }
+
+    /// Adds a block finalized by consensus along with its proof, verifying
+    /// it first unless it is a candidate already.
+    ///
+    /// Returns the hash of the block, or the reason it was rejected.
+    fn commit(&mut self, sealed: &BlockWithProof) -> result::Result<Result<H256, String>> {
+        let mut block = sealed.blk.get_or_default().clone();
+        block.header.mut_or_insert_default().proof = sealed.proof.clone();
+        let hash = block_hash(block.header.get_or_default());
+        if let Some(stored) = self.store.block(&hash)? {
+            // A block added before its proof arrived.
+            let receipts = self.store.block_receipts(&stored)?;
+            self.store.insert(&block, &receipts)?;
+            if let Err(e) = self.set_head(&hash)? {
+                warn!("rejected block {}: {}", block.header.height, e);
+                return Ok(Err(e));
+            }
+            self.store.set_finalized(&hash)?;
+            return Ok(Ok(hash));
+        }
+        if let Err(e) = self.verify(&block)? {
+            return Ok(Err(e));
+        }
+        let (_, receipts) = self
+            .candidates
+            .remove(&hash)
+            .expect("verified blocks are candidates");
+        if let Err(e) = self.add_block(&block, &receipts, true)? {
+            warn!("rejected block {}: {}", block.header.height, e);
+            return Ok(Err(e));
+        }
+        info!(
+            "committed block {} {:?} with {} transactions",
+            block.header.height,
+            hash,
+            block.body.transactions.len()
+        );
+        Ok(Ok(hash))
+    }
+
+    /// Stores a block whose state was written and makes it the head if the
+    /// fork choice prefers it.
+    ///
+    /// Returns the hash of the block, or why it can't be finalized.
+    fn add_block(
+        &mut self,
+        block: &Block,
+        receipts: &[Receipt],
+        finalized: bool,
+    ) -> result::Result<Result<H256, String>> {
+        let hash = self.store.insert(block, receipts)?;
+        if fork::is_better(&self.store, &hash, finalized)? {
+            // Only finalized blocks may conflict with the finalized block.
+            if let Err(e) = self.set_head(&hash)? {
+                return Ok(Err(e));
+            }
+        }
+        if finalized {
+            self.store.set_finalized(&hash)?;
+        }
+        Ok(Ok(hash))
+    }
+
+    /// Moves the head to the block `hash`, reverting the state to the one it
+    /// committed to. Transactions of blocks leaving the canonical chain go
+    /// back to the pool unless the new chain includes them too.
+    ///
+    /// Returns why the head can't move to the block, leaving it unchanged.
+    fn set_head(&mut self, hash: &H256) -> result::Result<Result<(), String>> {
+        let header = self
+            .store
+            .header(hash)?
+            .ok_or_else(|| anyhow!("unknown block {:?}", hash))?;
+        if let Err(e) = self.store.head_route(hash)? {
+            return Ok(Err(e));
+        }
+        self.state.reset(&header)?;
+        let route = self.store.set_head(hash)?;
+        if !route.retracted.is_empty() {
+            info!(
+                "reorganized to {:?}: {} blocks retracted, {} enacted",
+                hash,
+                route.retracted.len(),
+                route.enacted.len()
+            );
+        }
+
+        let mut logs = Vec::new();
+        let mut retracted = Vec::with_capacity(route.retracted.len());
+        for hash in &route.retracted {
+            let block = self.block(hash)?;
+            logs.extend(rpc_logs(&self.store.block_receipts(&block)?, true));
+            retracted.push(block);
+        }
+        let mut enacted = Vec::with_capacity(route.enacted.len());
+        for hash in &route.enacted {
+            let block = self.block(hash)?;
+            logs.extend(rpc_logs(&self.store.block_receipts(&block)?, false));
+            enacted.push(block);
+        }
+
+        let included = enacted
+            .iter()
+            .flat_map(|block| block.body.transactions.iter())
+            .map(|signed| &signed.tx_hash)
+            .collect::<HashSet<_>>();
+        for signed in retracted
+            .iter()
+            .rev()
+            .flat_map(|block| block.body.transactions.iter())
+            .filter(|signed| !included.contains(&signed.tx_hash))
+        {
+            let tx = from_signed(signed)?;
+            if let Err(e) = self.pool.submit_one(tx) {
+                debug!("dropped orphaned transaction {:?}: {}", signed.tx_hash, e);
+            }
+        }
+        for block in &enacted {
+            self.pool.maintain(block);
+        }
+
+        self.head = header;
+        let height = self.head.height;
+        self.candidates
+            .retain(|_, (block, _)| block.header.height > height);
+        // Sending only fails when nobody is subscribed.
+        let _ = self.events.send(ChainEvent::NewHead {
+            hash: *hash,
+            height: self.head.height,
+        });
+        if !logs.is_empty() {
+            let _ = self.events.send(ChainEvent::Logs(logs));
+        }
+        Ok(Ok(()))
+    }
+
+    fn block(&self, hash: &H256) -> result::Result<Block> {
+        self.store
+            .block(hash)?
+            .ok_or_else(|| anyhow!("unknown block {:?}", hash))
+    }
+
+    async fn process(&mut self, msg: Message<ChainRequest>) -> result::Result<()> {
+        match msg {
+            Message::Inner(InnerMessage {
+                content: Some(content),
+                ..
+            }) => match content {
+                Content::Block(_) | Content::BlockWithProof(_) => self.forward(content),
+                _ => {}
+            },
+            Message::Request(request) => self.serve(request)?,
+            _ => {}
+        }
         Ok(())
     }
+
+    /// Passes a block of another node to consensus. Consensus requests the
+    /// chain while adding blocks, so blocks are dropped rather than waited
+    /// on when it lags behind.
+    fn forward(&self, content: Content) {
+        let consensus = match &self.consensus {
+            Some(consensus) => consensus,
+            None => {
+                debug!("dropped block of a peer without consensus to check it");
+                return;
+            }
+        };
+        if let Err(e) = consensus.try_send(content) {
+            warn!("dropped block of a peer: {}", e);
+        }
+    }
+
+    /// Answers a request from another service. Replies to requesters that
+    /// stopped waiting are dropped.
+    fn serve(&mut self, request: ChainRequest) -> result::Result<()> {
+        match request {
+            ChainRequest::BlockNumber(reply) => {
+                let _ = reply.send(self.head.height);
+            }
+            ChainRequest::Status(reply) => {
+                let _ = reply.send(self.status(&self.head));
+            }
+            ChainRequest::BlockStatus(hash, reply) => {
+                let header = self.store.header(&hash)?;
+                let _ = reply.send(header.map(|header| self.status(&header)));
+            }
+            ChainRequest::BlockByNumber(height, full, reply) => {
+                let block = self.store.block_by_height(height)?;
+                let _ = reply.send(self.rich_block(block, full)?);
+            }
+            ChainRequest::BlockByHash(hash, full, reply) => {
+                let block = self.store.block(&hash)?;
+                let _ = reply.send(self.rich_block(block, full)?);
+            }
+            ChainRequest::Receipt(hash, reply) => {
+                let _ = reply.send(self.receipt(&hash)?);
+            }
+            ChainRequest::SubmitBlock(block, reply) => {
+                let _ = reply.send(self.import(&block, false)?);
+            }
+            ChainRequest::ProposeBlock(reply) => {
+                let _ = reply.send(self.propose()?);
+            }
+            ChainRequest::VerifyBlock(block, reply) => {
+                let _ = reply.send(self.verify(&block)?);
+            }
+            ChainRequest::CommitBlock(sealed, reply) => {
+                let _ = reply.send(self.commit(&sealed)?);
+            }
+            ChainRequest::IncreaseTime(seconds, reply) => {
+                let _ = reply.send(self.producer.increase_time(seconds));
+            }
+            ChainRequest::SetTime(timestamp, reply) => {
+                self.producer.set_time(timestamp);
+                let _ = reply.send(());
+            }
+            ChainRequest::ConsensusState(reply) => {
+                let _ = reply.send(self.store.consensus_state()?);
+            }
+            ChainRequest::SetConsensusState(state, reply) => {
+                self.store.set_consensus_state(&state)?;
+                let _ = reply.send(());
+            }
+        }
+        Ok(())
+    }
+
+    /// Status of the chain as of `header`.
+    fn status(&self, header: &BlockHeader) -> RichStatus {
+        let mut status = RichStatus::new();
+        status.hash = block_hash(header).as_bytes().to_vec();
+        status.height = header.height;
+        status.timestamp = header.timestamp;
+        status.interval = self.producer.config().interval.as_secs();
+        status.validators = self
+            .importer
+            .config()
+            .validators
+            .iter()
+            .map(|validator| validator.as_bytes().to_vec())
+            .collect();
+        status
+    }
+
+    fn rich_block(&self, block: Option<Block>, full: bool) -> result::Result<Option<RichBlock>> {
+        match block {
+            Some(block) => {
+                let receipts = self.store.block_receipts(&block)?;
+                Ok(Some(block::rich_block(&block, &receipts, full)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn receipt(&self, tx_hash: &H256) -> result::Result<Option<rpc_core::types::Receipt>> {
+        let location = match self.store.transaction_location(tx_hash)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        let block = self.block(&location.block_hash)?;
+        let index = location.index as usize;
+        let signed = block
+            .body
+            .transactions
+            .get(index)
+            .ok_or_else(|| anyhow!("missing transaction {:?}", tx_hash))?;
+        let gas_price = TransactionInfo::from(&from_signed(signed)?).gas_price;
+        let receipts = self.store.block_receipts(&block)?;
+        Ok(Some(rpc_receipt(&receipts, index, gas_price)))
+    }
 }
-impl Future for Server {
+
+impl<P: TransactionPool, T: KeyValueDB> Future for Server<P, T> {
     type Output = result::Result<()>;
 
     fn poll(
@@ -36,3 +459,203 @@ impl Future for Server {
         Box::pin(self.run()).as_mut().poll(cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::producer::tests::{transfer, Db};
+    use ethereum_types::{H160, U256};
+    use evm::Executive;
+    use proto::Reply;
+    use rpc_core::types::BlockTransactions;
+    use tokio::sync::mpsc::channel;
+    use tokio::sync::oneshot;
+    use txpool::{BasicPool, PoolConfig};
+
+    type Node = Server<BasicPool<Executive<Db>>, Db>;
+
+    fn server() -> Node {
+        let db = Arc::new(kvdb_memorydb::create(NUM_COLUMNS));
+        let store = ChainStore::new(db.clone());
+        let executive = Arc::new(Executive::new(db));
+        Genesis::default().init(&store, &executive).unwrap();
+
+        let pool = Arc::new(BasicPool::new(PoolConfig::default(), executive.clone()));
+        let producer =
+            BlockProducer::new(ProducerConfig::default(), pool.clone(), executive.clone());
+        let importer =
+            BlockImporter::new(ImportConfig::default(), store.clone(), executive.clone());
+        let state = Arc::new(StateProvider::new(
+            store.clone(),
+            executive,
+            Pruning::default(),
+        ));
+        let (chain_sender, rx) = channel(1);
+        let bus = Arc::new(MessageBus {
+            jsonrpc_sender: channel(1).0,
+            auth_sender: channel(1).0,
+            chain_sender,
+        });
+        Server::new(rx, bus, pool, producer, importer, store, state).unwrap()
+    }
+
+    /// Produces a block on top of the head and adds it.
+    fn produce(node: &mut Node) {
+        let block = node.propose().unwrap();
+        node.import(&block, false).unwrap().unwrap();
+    }
+
+    fn nonce(node: &Node, address: &H160) -> U256 {
+        let account = node.state.latest().account(*address).unwrap();
+        account.map(|account| account.nonce).unwrap_or_default()
+    }
+
+    #[test]
+    fn serves_requests() {
+        let mut node = server();
+        let secret = libsecp256k1::SecretKey::parse(&[1; 32]).unwrap();
+        node.pool.submit_one(transfer(&secret, 0, 21000)).unwrap();
+        let tx = node.pool.ready()[0].clone();
+        produce(&mut node);
+
+        let mut serve = |build: &dyn Fn(Reply<_>) -> ChainRequest| {
+            let (reply, mut response) = oneshot::channel();
+            node.serve(build(reply)).unwrap();
+            response.try_recv().unwrap()
+        };
+        let block = serve(&|reply| ChainRequest::BlockByNumber(1, true, reply)).unwrap();
+        assert_eq!(block.header.number, Some(U256::one()));
+        match &block.transactions {
+            BlockTransactions::Full(transactions) => {
+                assert_eq!(transactions[0].hash, tx.hash);
+                assert_eq!(transactions[0].from, tx.sender);
+            }
+            BlockTransactions::Hashes(_) => panic!("expected full transactions"),
+        }
+        let hash = block.header.hash.unwrap();
+        assert!(serve(&|reply| ChainRequest::BlockByHash(hash, false, reply)).is_some());
+        assert!(serve(&|reply| ChainRequest::BlockByNumber(2, false, reply)).is_none());
+
+        let (reply, mut response) = oneshot::channel();
+        node.serve(ChainRequest::Status(reply)).unwrap();
+        let status = response.try_recv().unwrap();
+        assert_eq!((status.height, status.interval), (1, 3));
+        assert_eq!(status.hash, hash.as_bytes());
+
+        let (reply, mut response) = oneshot::channel();
+        node.serve(ChainRequest::Receipt(tx.hash, reply)).unwrap();
+        let receipt = response.try_recv().unwrap().unwrap();
+        assert_eq!(receipt.block_hash, Some(hash));
+        assert_eq!(receipt.status_code, Some(1.into()));
+
+        let (reply, mut response) = oneshot::channel();
+        node.serve(ChainRequest::IncreaseTime(3600, reply)).unwrap();
+        assert_eq!(response.try_recv().unwrap(), 3600);
+        let block = node.propose().unwrap();
+        assert!(block.header.timestamp >= producer::now() + 3600);
+
+        let (reply, mut response) = oneshot::channel();
+        node.serve(ChainRequest::ConsensusState(reply)).unwrap();
+        assert_eq!(response.try_recv().unwrap(), None);
+        let (reply, _) = oneshot::channel();
+        node.serve(ChainRequest::SetConsensusState(vec![1, 2], reply))
+            .unwrap();
+        let (reply, mut response) = oneshot::channel();
+        node.serve(ChainRequest::ConsensusState(reply)).unwrap();
+        assert_eq!(response.try_recv().unwrap(), Some(vec![1, 2]));
+    }
+
+    #[test]
+    fn rejects_blocks_conflicting_with_the_finalized_block() {
+        let mut node = server();
+        let secret = libsecp256k1::SecretKey::parse(&[1; 32]).unwrap();
+        node.pool.submit_one(transfer(&secret, 0, 21000)).unwrap();
+        let tx = node.pool.ready()[0].clone();
+        let block = node.propose().unwrap();
+        let mut sealed = BlockWithProof::new();
+        sealed.blk = Some(block).into();
+        let finalized = node.commit(&sealed).unwrap().unwrap();
+
+        let mut other = server();
+        let mut conflicting = BlockWithProof::new();
+        conflicting.blk = Some(other.propose().unwrap()).into();
+        assert!(node.commit(&conflicting).unwrap().is_err());
+        assert_eq!(node.store.best_hash().unwrap(), Some(finalized));
+        assert_eq!(block_hash(&node.head), finalized);
+        assert_eq!(nonce(&node, &tx.sender), U256::one());
+    }
+
+    #[test]
+    fn reorganizes_to_longer_branch() {
+        let mut node = server();
+        let mut events = node.subscribe();
+        let secret = libsecp256k1::SecretKey::parse(&[1; 32]).unwrap();
+        node.pool.submit_one(transfer(&secret, 0, 21000)).unwrap();
+        let tx = node.pool.ready()[0].clone();
+        produce(&mut node);
+        assert_eq!(node.head.height, 1);
+        assert!(node.pool.transaction(&tx.hash).is_none());
+        assert_eq!(nonce(&node, &tx.sender), U256::one());
+
+        // Another producer builds a longer branch without the transaction.
+        let mut other = server();
+        produce(&mut other);
+        produce(&mut other);
+        let b1 = other.store.block_by_height(1).unwrap().unwrap();
+        let b2 = other.store.block_by_height(2).unwrap().unwrap();
+
+        node.import(&b1, false).unwrap().unwrap();
+        assert_eq!(node.head.height, 1);
+        node.import(&b2, false).unwrap().unwrap();
+        assert_eq!(node.head, *b2.header.get_or_default());
+        assert_eq!(
+            node.store.block_hash(1).unwrap(),
+            Some(block_hash(b1.header.get_or_default()))
+        );
+        assert!(node.pool.transaction(&tx.hash).is_some());
+        assert_eq!(nonce(&node, &tx.sender), U256::zero());
+
+        let heights = std::iter::from_fn(|| events.try_recv().ok())
+            .filter_map(|event| match event {
+                ChainEvent::NewHead { height, .. } => Some(height),
+                ChainEvent::Logs(_) => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(heights, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn forwards_peer_blocks_to_consensus() {
+        let mut node = server();
+        let block = node.propose().unwrap();
+        let relayed = |content: Content| {
+            let mut msg = InnerMessage::new();
+            msg.content = Some(content);
+            Message::Inner(msg)
+        };
+
+        // Blocks whose seal nobody checks are dropped.
+        node.process(relayed(Content::Block(block.clone())))
+            .await
+            .unwrap();
+        assert_eq!(node.head.height, 0);
+
+        let (consensus, mut blocks) = channel(1);
+        node.forward_blocks(consensus);
+        node.process(relayed(Content::Block(block.clone())))
+            .await
+            .unwrap();
+        assert_eq!(node.head.height, 0);
+        match blocks.try_recv().unwrap() {
+            Content::Block(forwarded) => assert_eq!(forwarded, block),
+            content => panic!("unexpected {:?}", content),
+        }
+
+        // A full channel drops blocks rather than waiting on consensus.
+        node.process(relayed(Content::Block(block.clone())))
+            .await
+            .unwrap();
+        node.process(relayed(Content::Block(block))).await.unwrap();
+        assert!(blocks.try_recv().is_ok() && blocks.try_recv().is_err());
+    }
+}
@@ -0,0 +1,431 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ethereum::TransactionAction;
+use ethereum_types::{H160, H256, U256};
+use evm::{BlockExecution, Executive, ExitReason, Log, TransactionArgs, Vicinity};
+use kvdb::KeyValueDB;
+use proto::{Block, BlockBody, BlockHeader};
+use tracing::warn;
+use txpool::{PoolTransaction, TransactionPool};
+
+use crate::receipt::{receipts_root, Receipt};
+use crate::transaction::{to_signed, transactions_root, TransactionInfo};
+
+/// Block production parameters.
+#[derive(Clone, Debug)]
+pub struct ProducerConfig {
+    /// Time between two blocks, as paced by the consensus engine.
+    pub interval: Duration,
+    /// Maximum quota a block may use.
+    pub quota_limit: u64,
+    /// Address recorded as the proposer of produced blocks.
+    pub proposer: H160,
+    pub chain_id: u64,
+}
+
+impl Default for ProducerConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(3),
+            quota_limit: 1_073_741_824,
+            proposer: H160::zero(),
+            chain_id: 1,
+        }
+    }
+}
+
+/// Builds blocks from the ready transactions of the pool.
+pub struct BlockProducer<P, T> {
+    config: ProducerConfig,
+    pool: Arc<P>,
+    executive: Arc<Executive<T>>,
+    /// Seconds the clock of produced blocks is ahead of the system time.
+    offset: u64,
+}
+
+impl<P: TransactionPool, T: KeyValueDB> BlockProducer<P, T> {
+    pub fn new(config: ProducerConfig, pool: Arc<P>, executive: Arc<Executive<T>>) -> Self {
+        Self {
+            config,
+            pool,
+            executive,
+            offset: 0,
+        }
+    }
+
+    pub fn config(&self) -> &ProducerConfig {
+        &self.config
+    }
+
+    /// Time in seconds to produce a block at.
+    pub fn timestamp(&self) -> u64 {
+        now() + self.offset
+    }
+
+    /// Moves the clock forward by `seconds`, returning how far it is ahead
+    /// of the system time.
+    pub fn increase_time(&mut self, seconds: u64) -> u64 {
+        self.offset = self.offset.saturating_add(seconds);
+        self.offset
+    }
+
+    /// Moves the clock to `timestamp`, or back to the system time if that
+    /// is earlier.
+    pub fn set_time(&mut self, timestamp: u64) {
+        self.offset = timestamp.saturating_sub(now());
+    }
+
+    /// Executes ready transactions on top of `parent` and assembles the block.
+    ///
+    /// Transactions are taken in pool order until the quota limit is reached.
+    /// Once a transaction of a sender is left out, the sender's later
+    /// transactions are skipped too as their nonces would no longer follow.
+    ///
+    /// A transaction failing to execute leaves its sender out, and the block
+    /// is executed again from the state of `parent` without it.
+    ///
+    /// The state changes of the block are returned uncommitted, to be written
+    /// once the block is accepted.
+    pub fn produce(
+        &self,
+        parent: &BlockHeader,
+        timestamp: u64,
+    ) -> result::Result<(Block, Vec<Receipt>, BlockExecution<'_, T>)> {
+        let parent_hash = crate::block::hash(parent);
+        let height = parent.height + 1;
+        let timestamp = timestamp.max(parent.timestamp + 1);
+        let vicinity = vicinity(
+            self.config.chain_id,
+            parent_hash,
+            height,
+            timestamp,
+            self.config.proposer,
+            self.config.quota_limit,
+        );
+
+        let ready = self.pool.ready();
+        let mut skipped = HashSet::new();
+        let (state, body, mut receipts, quota_used) = 'block: loop {
+            let mut state = self
+                .executive
+                .begin_block_at(H256::from_slice(&parent.state_root))?;
+            let mut quota_used = 0u64;
+            let mut body = BlockBody::new();
+            let mut receipts = Vec::new();
+            for tx in &ready {
+                if skipped.contains(&tx.sender) {
+                    continue;
+                }
+
+                let gas_limit = TransactionInfo::from(&tx.transaction).gas_limit;
+                if gas_limit > U256::from(self.config.quota_limit - quota_used) {
+                    skipped.insert(tx.sender);
+                    continue;
+                }
+
+                match execute(&mut state, tx, vicinity.clone()) {
+                    Ok(outcome) => {
+                        quota_used += outcome.used_gas.low_u64();
+                        receipts.push(Receipt::new(
+                            &tx.transaction,
+                            tx.hash,
+                            body.transactions.len() as u32,
+                            height,
+                            tx.sender,
+                            &outcome.exit_reason,
+                            outcome.created,
+                            outcome.used_gas,
+                            U256::from(quota_used),
+                            outcome.logs,
+                        ));
+                        body.transactions.push(to_signed(tx));
+                    }
+                    Err(e) => {
+                        // The failed transaction may have left partial
+                        // writes, so the block is executed again without it.
+                        warn!("failed to execute transaction {:?}: {}", tx.hash, e);
+                        skipped.insert(tx.sender);
+                        continue 'block;
+                    }
+                }
+            }
+            break (state, body, receipts, quota_used);
+        };
+
+        let mut header = BlockHeader::new();
+        header.prevhash = parent_hash.as_bytes().to_vec();
+        header.timestamp = timestamp;
+        header.height = height;
+        header.quota_used = quota_used;
+        header.quota_limit = self.config.quota_limit;
+        header.state_root = state.state_root().as_bytes().to_vec();
+        header.proposer = self.config.proposer.as_bytes().to_vec();
+        header.transactions_root = transactions_root(&body.transactions).as_bytes().to_vec();
+        header.receipts_root = receipts_root(&receipts).as_bytes().to_vec();
+
+        let hash = crate::block::hash(&header);
+        for receipt in &mut receipts {
+            receipt.block_hash = hash;
+        }
+
+        let mut block = Block::new();
+        block.header = Some(header).into();
+        block.body = Some(body).into();
+        Ok((block, receipts, state))
+    }
+}
+
+/// Environment the transactions of the block at `height` execute in.
+pub(crate) fn vicinity(
+    chain_id: u64,
+    parent_hash: H256,
+    height: u64,
+    timestamp: u64,
+    proposer: H160,
+    quota_limit: u64,
+) -> Vicinity {
+    Vicinity {
+        chain_id: U256::from(chain_id),
+        block_hashes: vec![parent_hash],
+        block_number: U256::from(height),
+        block_coinbase: proposer,
+        block_timestamp: U256::from(timestamp),
+        block_gas_limit: U256::from(quota_limit),
+        ..Default::default()
+    }
+}
+
+/// Executes one transaction on top of the block's previous ones.
+pub(crate) fn execute<T: KeyValueDB>(
+    state: &mut BlockExecution<T>,
+    tx: &PoolTransaction,
+    mut vicinity: Vicinity,
+) -> result::Result<Outcome> {
+    let info = TransactionInfo::from(&tx.transaction);
+    vicinity.origin = tx.sender;
+    vicinity.gas_price = info.gas_price;
+    let args = TransactionArgs {
+        caller: tx.sender,
+        value: info.value,
+        data: info.input,
+        gas_limit: info.gas_limit.low_u64(),
+        max_fee_per_gas: info.max_fee_per_gas,
+        max_priority_fee_per_gas: info.max_priority_fee_per_gas,
+        nonce: Some(tx.nonce),
+        access_list: info.access_list,
+    };
+
+    let outcome = match info.action {
+        TransactionAction::Call(target) => {
+            let result = state.call(target, args, true, true, vicinity)?;
+            Outcome {
+                exit_reason: result.exit_reason,
+                used_gas: result.used_gas,
+                logs: result.logs,
+                created: None,
+            }
+        }
+        TransactionAction::Create => {
+            let result = state.create(args, true, true, vicinity)?;
+            Outcome {
+                exit_reason: result.exit_reason,
+                used_gas: result.used_gas,
+                logs: result.logs,
+                created: Some(result.value),
+            }
+        }
+    };
+    Ok(outcome)
+}
+
+/// Result of executing one transaction.
+pub(crate) struct Outcome {
+    pub exit_reason: ExitReason,
+    pub used_gas: U256,
+    pub logs: Vec<Log>,
+    /// Address of the contract a create deploys to.
+    pub created: Option<H160>,
+}
+
+/// Current time in seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::Genesis;
+    use ethereum::{
+        LegacyTransaction, LegacyTransactionMessage, TransactionSignature, TransactionV2,
+    };
+    use txpool::{BasicPool, PoolConfig, StateReader};
+
+    pub(crate) type Db = kvdb_memorydb::InMemory;
+
+    pub(crate) fn setup() -> (Arc<BasicPool<Executive<Db>>>, Arc<Executive<Db>>) {
+        let executive = Arc::new(Executive::new(Arc::new(kvdb_memorydb::create(1))));
+        let pool = Arc::new(BasicPool::new(PoolConfig::default(), executive.clone()));
+        (pool, executive)
+    }
+
+    fn genesis() -> BlockHeader {
+        Genesis::default().block().header.unwrap()
+    }
+
+    pub(crate) fn transfer(
+        secret: &libsecp256k1::SecretKey,
+        nonce: u64,
+        gas_limit: u64,
+    ) -> TransactionV2 {
+        let message = LegacyTransactionMessage {
+            nonce: U256::from(nonce),
+            gas_price: U256::zero(),
+            gas_limit: U256::from(gas_limit),
+            action: TransactionAction::Call(H160::repeat_byte(0xaa)),
+            value: U256::zero(),
+            input: vec![],
+            chain_id: Some(1),
+        };
+        let (signature, recid) =
+            libsecp256k1::sign(&libsecp256k1::Message::parse(&message.hash().0), secret);
+        let rs = signature.serialize();
+        TransactionV2::Legacy(LegacyTransaction {
+            nonce: message.nonce,
+            gas_price: message.gas_price,
+            gas_limit: message.gas_limit,
+            action: message.action,
+            value: message.value,
+            input: message.input,
+            signature: TransactionSignature::new(
+                37 + recid.serialize() as u64,
+                H256::from_slice(&rs[0..32]),
+                H256::from_slice(&rs[32..64]),
+            )
+            .unwrap(),
+        })
+    }
+
+    #[test]
+    fn produces_block_from_ready_transactions() {
+        let (pool, executive) = setup();
+        let secret = libsecp256k1::SecretKey::parse(&[1; 32]).unwrap();
+        for nonce in 0..2 {
+            pool.submit_one(transfer(&secret, nonce, 21000)).unwrap();
+        }
+        let sender = pool.ready()[0].sender;
+
+        let producer = BlockProducer::new(
+            ProducerConfig {
+                proposer: H160::repeat_byte(7),
+                ..Default::default()
+            },
+            pool,
+            executive.clone(),
+        );
+        let parent = genesis();
+        let (block, receipts, state) = producer.produce(&parent, 10).unwrap();
+        assert_eq!(block.header.state_root, state.state_root().as_bytes());
+        assert_eq!(executive.nonce(&sender), U256::zero());
+        state.commit().unwrap();
+
+        assert_eq!(block.header.height, 1);
+        assert_eq!(block.header.timestamp, 10);
+        assert_eq!(
+            block.header.prevhash,
+            crate::block::hash(&parent).as_bytes()
+        );
+        assert_eq!(block.header.proposer, H160::repeat_byte(7).as_bytes());
+        assert_eq!(block.header.quota_used, 2 * 21000);
+        assert_eq!(block.body.transactions.len(), 2);
+        assert_eq!(executive.nonce(&sender), U256::from(2));
+        assert_eq!(block.header.state_root, executive.state_root().as_bytes());
+
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(receipts[1].transaction_index, 1);
+        assert_eq!(receipts[1].quota_used, U256::from(21000));
+        assert_eq!(receipts[1].data().used_gas, U256::from(2 * 21000));
+        assert_eq!(receipts[1].data().status_code, 1);
+        assert_eq!(
+            receipts[0].block_hash,
+            crate::block::hash(block.header.get_or_default())
+        );
+        assert_eq!(
+            block.header.transactions_root,
+            transactions_root(&block.body.transactions).as_bytes()
+        );
+        assert_eq!(
+            block.header.receipts_root,
+            receipts_root(&receipts).as_bytes()
+        );
+    }
+
+    #[test]
+    fn respects_quota_limit() {
+        let (pool, executive) = setup();
+        let (alice, bob) = (
+            libsecp256k1::SecretKey::parse(&[1; 32]).unwrap(),
+            libsecp256k1::SecretKey::parse(&[2; 32]).unwrap(),
+        );
+        pool.submit_one(transfer(&alice, 0, 30000)).unwrap();
+        pool.submit_one(transfer(&alice, 1, 21000)).unwrap();
+        pool.submit_one(transfer(&bob, 0, 21000)).unwrap();
+
+        let producer = BlockProducer::new(
+            ProducerConfig {
+                quota_limit: 25000,
+                ..Default::default()
+            },
+            pool,
+            executive,
+        );
+        let (block, _, _) = producer.produce(&genesis(), 10).unwrap();
+
+        // Alice's first transaction doesn't fit, which holds back her second.
+        assert_eq!(block.body.transactions.len(), 1);
+        assert_eq!(block.header.quota_used, 21000);
+    }
+
+    #[test]
+    fn leaves_out_senders_failing_on_the_parent_state() {
+        let (pool, executive) = setup();
+        let (alice, bob) = (
+            libsecp256k1::SecretKey::parse(&[1; 32]).unwrap(),
+            libsecp256k1::SecretKey::parse(&[2; 32]).unwrap(),
+        );
+        // Alice's first transaction is only in the latest state, not in the
+        // state of the parent the block is produced on.
+        let mined = PoolTransaction::new(transfer(&alice, 0, 21000), &PoolConfig::default());
+        let mut state = executive.begin_block();
+        execute(&mut state, &mined.unwrap(), Vicinity::default()).unwrap();
+        let latest = state.commit().unwrap();
+        pool.submit_one(transfer(&alice, 1, 21000)).unwrap();
+        pool.submit_one(transfer(&bob, 0, 21000)).unwrap();
+
+        let producer = BlockProducer::new(ProducerConfig::default(), pool, executive.clone());
+        let (block, receipts, state) = producer.produce(&genesis(), 10).unwrap();
+        assert_eq!(block.body.transactions.len(), 1);
+        assert_eq!(receipts[0].quota_used, U256::from(21000));
+        assert_eq!(block.header.state_root, state.state_root().as_bytes());
+        assert_eq!(executive.state_root(), latest);
+    }
+
+    #[test]
+    fn moves_the_clock_forward() {
+        let (pool, executive) = setup();
+        let mut producer = BlockProducer::new(ProducerConfig::default(), pool, executive);
+        assert_eq!(producer.increase_time(60), 60);
+        assert_eq!(producer.increase_time(40), 100);
+        assert!(producer.timestamp() >= now() + 100);
+
+        producer.set_time(now() + 3600);
+        assert!(producer.timestamp() >= now() + 3600);
+        producer.set_time(0);
+        assert_eq!(producer.increase_time(0), 0);
+    }
+}
@@ -0,0 +1,164 @@
+use codec::{Decode, Encode};
+use ethereum::{
+    EIP658ReceiptData, EnvelopedEncodable, ReceiptV3, TransactionAction, TransactionV2,
+};
+use ethereum_types::{Bloom, BloomInput, H160, H256, U256, U64};
+use evm::{ExitReason, Log};
+
+/// Receipt of an executed transaction together with its position in the chain.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct Receipt {
+    /// Consensus receipt committed to by the block `receipts_root`. Its
+    /// `used_gas` is the quota used by the block up to this transaction.
+    pub receipt: ReceiptV3,
+    pub transaction_hash: H256,
+    pub transaction_index: u32,
+    pub block_hash: H256,
+    pub block_number: u64,
+    pub from: H160,
+    pub to: Option<H160>,
+    /// Address of the contract deployed by a successful create.
+    pub contract_address: Option<H160>,
+    /// Quota used by this transaction alone.
+    pub quota_used: U256,
+}
+
+impl Receipt {
+    /// Builds the receipt of `tx` from its execution outcome. The block hash
+    /// is filled in once the block is sealed.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        tx: &TransactionV2,
+        transaction_hash: H256,
+        transaction_index: u32,
+        block_number: u64,
+        from: H160,
+        exit_reason: &ExitReason,
+        created: Option<H160>,
+        quota_used: U256,
+        cumulative_quota_used: U256,
+        logs: Vec<Log>,
+    ) -> Self {
+        let logs = logs
+            .into_iter()
+            .map(|log| ethereum::Log {
+                address: log.address,
+                topics: log.topics,
+                data: log.data,
+            })
+            .collect::<Vec<_>>();
+        let data = EIP658ReceiptData {
+            status_code: exit_reason.is_succeed() as u8,
+            used_gas: cumulative_quota_used,
+            logs_bloom: logs_bloom(&logs),
+            logs,
+        };
+        let (receipt, to) = match tx {
+            TransactionV2::Legacy(t) => (ReceiptV3::Legacy(data), t.action),
+            TransactionV2::EIP2930(t) => (ReceiptV3::EIP2930(data), t.action),
+            TransactionV2::EIP1559(t) => (ReceiptV3::EIP1559(data), t.action),
+        };
+        let to = match to {
+            TransactionAction::Call(to) => Some(to),
+            TransactionAction::Create => None,
+        };
+
+        Self {
+            receipt,
+            transaction_hash,
+            transaction_index,
+            block_hash: H256::zero(),
+            block_number,
+            from,
+            to,
+            contract_address: created.filter(|_| exit_reason.is_succeed()),
+            quota_used,
+        }
+    }
+
+    pub fn data(&self) -> &EIP658ReceiptData {
+        match &self.receipt {
+            ReceiptV3::Legacy(data) | ReceiptV3::EIP2930(data) | ReceiptV3::EIP1559(data) => data,
+        }
+    }
+}
+
+/// Bloom filter over the addresses and topics of `logs`.
+pub fn logs_bloom(logs: &[ethereum::Log]) -> Bloom {
+    let mut bloom = Bloom::default();
+    for log in logs {
+        bloom.accrue(BloomInput::Raw(log.address.as_bytes()));
+        for topic in &log.topics {
+            bloom.accrue(BloomInput::Raw(topic.as_bytes()));
+        }
+    }
+    bloom
+}
+
+/// Root of the trie of the EIP-2718 encoded receipts keyed by their index.
+pub fn receipts_root(receipts: &[Receipt]) -> H256 {
+    trie::ordered_root(
+        receipts
+            .iter()
+            .map(|r| EnvelopedEncodable::encode(&r.receipt)),
+    )
+}
+
+/// Logs of the receipts of one block in the RPC representation, flagged
+/// `removed` when the block left the canonical chain.
+pub fn rpc_logs(receipts: &[Receipt], removed: bool) -> Vec<rpc_core::types::Log> {
+    let mut logs = Vec::new();
+    for receipt in receipts {
+        for (index, log) in receipt.data().logs.iter().enumerate() {
+            logs.push(rpc_core::types::Log {
+                address: log.address,
+                topics: log.topics.clone(),
+                data: log.data.clone().into(),
+                block_hash: Some(receipt.block_hash),
+                block_number: Some(U256::from(receipt.block_number)),
+                transaction_hash: Some(receipt.transaction_hash),
+                transaction_index: Some(U256::from(receipt.transaction_index)),
+                log_index: Some(U256::from(logs.len())),
+                transaction_log_index: Some(U256::from(index)),
+                removed,
+            });
+        }
+    }
+    logs
+}
+
+/// RPC representation of the receipt at `index` of a block's `receipts`.
+pub fn rpc_receipt(
+    receipts: &[Receipt],
+    index: usize,
+    effective_gas_price: U256,
+) -> rpc_core::types::Receipt {
+    let receipt = &receipts[index];
+    let data = receipt.data();
+    let first_log = receipts[..index]
+        .iter()
+        .map(|receipt| receipt.data().logs.len())
+        .sum::<usize>();
+    let transaction_type = match receipt.receipt {
+        ReceiptV3::Legacy(_) => 0,
+        ReceiptV3::EIP2930(_) => 1,
+        ReceiptV3::EIP1559(_) => 2,
+    };
+    rpc_core::types::Receipt {
+        transaction_hash: Some(receipt.transaction_hash),
+        transaction_index: Some(U256::from(receipt.transaction_index)),
+        block_hash: Some(receipt.block_hash),
+        from: Some(receipt.from),
+        to: receipt.to,
+        block_number: Some(U256::from(receipt.block_number)),
+        cumulative_gas_used: data.used_gas,
+        gas_used: Some(receipt.quota_used),
+        contract_address: receipt.contract_address,
+        logs: rpc_logs(&receipts[..=index], false).split_off(first_log),
+        state_root: None,
+        logs_bloom: data.logs_bloom,
+        status_code: Some(U64::from(data.status_code)),
+        effective_gas_price,
+        transaction_type: U256::from(transaction_type),
+    }
+}
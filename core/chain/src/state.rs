@@ -0,0 +1,235 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use ethereum_types::H256;
+use evm::{BlockExecution, Executive, PruneStats, StateView};
+use kvdb::KeyValueDB;
+use proto::BlockHeader;
+
+use crate::store::ChainStore;
+
+/// How much of the state history the node keeps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pruning {
+    /// Keep the state of every block.
+    Archive,
+    /// Keep the state of the best block and of this many blocks below it.
+    KeepRecent(u64),
+}
+
+impl Default for Pruning {
+    fn default() -> Self {
+        Pruning::KeepRecent(128)
+    }
+}
+
+impl Pruning {
+    /// Lowest height whose state is kept while `best` is the best height.
+    pub fn oldest_retained(&self, best: u64) -> u64 {
+        match self {
+            Pruning::Archive => 0,
+            Pruning::KeepRecent(depth) => best.saturating_sub(*depth),
+        }
+    }
+}
+
+/// Resolves blocks to the state they committed to, and prunes the state of
+/// the blocks no longer retained.
+pub struct StateProvider<T> {
+    store: ChainStore<T>,
+    executive: Arc<Executive<T>>,
+    pruning: Pruning,
+    /// Data deleted by pruning since the node started.
+    pruned: Mutex<PruneStats>,
+}
+
+impl<T: KeyValueDB> StateProvider<T> {
+    pub fn new(store: ChainStore<T>, executive: Arc<Executive<T>>, pruning: Pruning) -> Self {
+        Self {
+            store,
+            executive,
+            pruning,
+            pruned: Mutex::new(PruneStats::default()),
+        }
+    }
+
+    pub fn store(&self) -> &ChainStore<T> {
+        &self.store
+    }
+
+    pub fn pruning(&self) -> Pruning {
+        self.pruning
+    }
+
+    /// State after the best block.
+    pub fn latest(&self) -> StateView<T> {
+        self.executive.latest()
+    }
+
+    /// State after the canonical block at `height`.
+    pub fn at_height(&self, height: u64) -> result::Result<StateView<T>> {
+        match self.store.block_hash(height)? {
+            Some(hash) => self.at_hash(&hash),
+            None => Err(anyhow!("unknown block {}", height)),
+        }
+    }
+
+    /// State after the block `hash`, as long as its height is retained.
+    pub fn at_hash(&self, hash: &H256) -> result::Result<StateView<T>> {
+        let header = match self.store.header(hash)? {
+            Some(header) => header,
+            None => return Err(anyhow!("unknown block {:?}", hash)),
+        };
+        let best = self
+            .store
+            .best_header()?
+            .map(|header| header.height)
+            .unwrap_or_default();
+        if header.height < self.pruning.oldest_retained(best) {
+            return Err(anyhow!("state of block {} is pruned", header.height));
+        }
+
+        Ok(self
+            .executive
+            .state_at(H256::from_slice(&header.state_root)))
+    }
+
+    /// Makes the state `header` committed to the latest one.
+    pub fn reset(&self, header: &BlockHeader) -> result::Result<()> {
+        self.executive.reset(H256::from_slice(&header.state_root))
+    }
+
+    /// Writes the state of the block `hash` at `height`, leaving the latest
+    /// state as it is. Unless archiving, the state is journaled for pruning
+    /// to release it once the block is no longer retained.
+    pub fn write(
+        &self,
+        mut state: BlockExecution<'_, T>,
+        height: u64,
+        hash: H256,
+    ) -> result::Result<()> {
+        if self.pruning != Pruning::Archive {
+            state.journal(height, hash);
+        }
+        state.write()?;
+        Ok(())
+    }
+
+    /// Deletes the state released before the oldest retained block.
+    pub fn prune(&self) -> result::Result<PruneStats> {
+        if self.pruning == Pruning::Archive {
+            return Ok(PruneStats::default());
+        }
+        let best = match self.store.best_header()? {
+            Some(header) => header.height,
+            None => return Ok(PruneStats::default()),
+        };
+        let stats = self
+            .executive
+            .prune(self.pruning.oldest_retained(best), |height| {
+                self.store.block_hash(height)
+            })?;
+        *self.pruned.lock().unwrap() += stats;
+        Ok(stats)
+    }
+
+    /// Data deleted by pruning since the provider was created.
+    pub fn pruned(&self) -> PruneStats {
+        *self.pruned.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethereum_types::{H160, U256};
+    use evm::MemoryAccount;
+    use proto::Block;
+    use std::collections::BTreeMap;
+
+    /// Commits blocks 0 to 4, each crediting `address` with its height.
+    fn chain(pruning: Pruning) -> (StateProvider<kvdb_memorydb::InMemory>, H160) {
+        let db = Arc::new(kvdb_memorydb::create(crate::store::NUM_COLUMNS));
+        let store = ChainStore::new(db.clone());
+        let executive = Arc::new(Executive::new(db));
+        let provider = StateProvider::new(store, executive, pruning);
+        let address = H160::repeat_byte(1);
+
+        let mut prevhash = H256::zero();
+        for height in 0..5u64 {
+            let account = MemoryAccount {
+                nonce: U256::zero(),
+                balance: U256::from(height),
+                storage: BTreeMap::new(),
+                code: vec![],
+            };
+            let mut state = provider.executive.begin_block();
+            state
+                .insert_accounts(BTreeMap::from([(address, account)]))
+                .unwrap();
+
+            let mut header = BlockHeader::new();
+            header.height = height;
+            header.prevhash = prevhash.as_bytes().to_vec();
+            header.state_root = state.state_root().as_bytes().to_vec();
+            provider
+                .write(state, height, crate::block::hash(&header))
+                .unwrap();
+            provider.reset(&header).unwrap();
+            let mut block = Block::new();
+            block.header = Some(header).into();
+            prevhash = provider.store().commit(&block, &[]).unwrap();
+        }
+
+        (provider, address)
+    }
+
+    fn balance(state: result::Result<StateView<kvdb_memorydb::InMemory>>, address: H160) -> U256 {
+        state.unwrap().account(address).unwrap().unwrap().balance
+    }
+
+    #[test]
+    fn reads_state_of_past_blocks() {
+        let (provider, address) = chain(Pruning::Archive);
+        assert_eq!(balance(provider.at_height(0), address), U256::zero());
+        assert_eq!(balance(provider.at_height(2), address), U256::from(2));
+        assert_eq!(balance(Ok(provider.latest()), address), U256::from(4));
+
+        let hash = provider.store().block_hash(3).unwrap().unwrap();
+        assert_eq!(balance(provider.at_hash(&hash), address), U256::from(3));
+        assert!(provider.at_height(5).is_err());
+    }
+
+    #[test]
+    fn refuses_pruned_heights() {
+        let (provider, address) = chain(Pruning::KeepRecent(2));
+        assert!(provider.at_height(1).is_err());
+        assert_eq!(balance(provider.at_height(2), address), U256::from(2));
+        assert_eq!(Pruning::KeepRecent(2).oldest_retained(1), 0);
+    }
+
+    #[test]
+    fn prunes_released_state() {
+        let (provider, address) = chain(Pruning::KeepRecent(2));
+        let pruned = |height: u64| {
+            let hash = provider.store().block_hash(height).unwrap().unwrap();
+            let header = provider.store().header(&hash).unwrap().unwrap();
+            provider
+                .executive
+                .state_at(H256::from_slice(&header.state_root))
+                .account(address)
+                .is_err()
+        };
+
+        let stats = provider.prune().unwrap();
+        assert_eq!(stats.blocks, 3);
+        assert!(stats.bytes > 0);
+        assert_eq!(provider.pruned(), stats);
+        assert!(pruned(0) && pruned(1));
+        assert_eq!(balance(provider.at_height(2), address), U256::from(2));
+        assert_eq!(balance(Ok(provider.latest()), address), U256::from(4));
+
+        let (archive, _) = chain(Pruning::Archive);
+        assert_eq!(archive.prune().unwrap(), PruneStats::default());
+    }
+}
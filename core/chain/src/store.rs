@@ -0,0 +1,617 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use codec::{Decode, Encode};
+use ethereum::EnvelopedEncodable;
+use ethereum_types::H256;
+use kvdb::{DBTransaction, KeyValueDB};
+use proto::{Block, BlockBody, BlockHeader, SignedTransaction};
+use protobuf::Message;
+
+use crate::receipt::Receipt;
+
+/// Column of the state trie written by `evm::Executive`.
+pub const COL_STATE: u32 = evm::COL_STATE;
+/// Block hash -> protobuf encoded `BlockHeader`.
+pub const COL_HEADERS: u32 = 1;
+/// Block hash -> protobuf encoded `BlockBody`.
+pub const COL_BODIES: u32 = 2;
+/// Big endian height -> hash of the canonical block at that height.
+pub const COL_HEIGHTS: u32 = 3;
+/// Transaction hash -> block hash followed by the big endian `u32` index.
+pub const COL_TRANSACTIONS: u32 = 4;
+/// Head pointers and the state of consensus.
+pub const COL_META: u32 = 5;
+/// Block hash -> SCALE encoded receipts of its transactions.
+pub const COL_RECEIPTS: u32 = 6;
+/// Number of columns of the node database.
+pub const NUM_COLUMNS: u32 = 7;
+
+const BEST_KEY: &[u8] = b"best";
+const FINALIZED_KEY: &[u8] = b"finalized";
+const CONSENSUS_KEY: &[u8] = b"consensus";
+
+/// Position of a transaction in the chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransactionLocation {
+    pub block_hash: H256,
+    pub index: u32,
+}
+
+/// Blocks leaving and joining the canonical chain when its head moves.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TreeRoute {
+    /// Latest block both heads descend from, if they share one.
+    pub ancestor: Option<H256>,
+    /// Blocks leaving the canonical chain, from the old head down.
+    pub retracted: Vec<H256>,
+    /// Blocks joining the canonical chain, up to the new head.
+    pub enacted: Vec<H256>,
+}
+
+/// Persistent store of the blocks of the chain, canonical or not.
+pub struct ChainStore<T> {
+    db: Arc<T>,
+}
+
+impl<T> Clone for ChainStore<T> {
+    fn clone(&self) -> Self {
+        Self {
+            db: self.db.clone(),
+        }
+    }
+}
+
+impl<T: KeyValueDB> ChainStore<T> {
+    pub fn new(db: Arc<T>) -> Self {
+        Self { db }
+    }
+
+    /// Writes a block and the receipts of its transactions, indexes them and
+    /// makes the block the best one, returning its hash.
+    pub fn commit(&self, block: &Block, receipts: &[Receipt]) -> result::Result<H256> {
+        let hash = self.insert(block, receipts)?;
+        self.set_head(&hash)?;
+        Ok(hash)
+    }
+
+    /// Writes a block and the receipts of its transactions without making
+    /// it canonical, returning its hash.
+    pub fn insert(&self, block: &Block, receipts: &[Receipt]) -> result::Result<H256> {
+        let header = block.header.get_or_default();
+        let hash = crate::block::hash(header);
+
+        let mut tx = DBTransaction::new();
+        tx.put(COL_HEADERS, hash.as_bytes(), &header.write_to_bytes()?);
+        tx.put(
+            COL_BODIES,
+            hash.as_bytes(),
+            &block.body.get_or_default().write_to_bytes()?,
+        );
+        tx.put(COL_RECEIPTS, hash.as_bytes(), &receipts.encode());
+        self.db.write(tx)?;
+
+        Ok(hash)
+    }
+
+    /// Makes the stored block `hash` the best one, moving the height and
+    /// transaction indexes from the blocks leaving the canonical chain to the
+    /// ones joining it.
+    ///
+    /// Fails if the finalized block would leave the canonical chain.
+    pub fn set_head(&self, hash: &H256) -> result::Result<TreeRoute> {
+        let route = self.head_route(hash)?.map_err(|e| anyhow!(e))?;
+
+        let mut tx = DBTransaction::new();
+        for retracted in &route.retracted {
+            let block = self.expect_block(retracted)?;
+            tx.delete(COL_HEIGHTS, &block.header.height.to_be_bytes());
+            for signed in &block.body.transactions {
+                tx.delete(COL_TRANSACTIONS, &signed.tx_hash);
+            }
+        }
+        for enacted in &route.enacted {
+            let block = self.expect_block(enacted)?;
+            tx.put(
+                COL_HEIGHTS,
+                &block.header.height.to_be_bytes(),
+                enacted.as_bytes(),
+            );
+            for (index, signed) in block.body.transactions.iter().enumerate() {
+                let mut location = enacted.as_bytes().to_vec();
+                location.extend_from_slice(&(index as u32).to_be_bytes());
+                tx.put(COL_TRANSACTIONS, &signed.tx_hash, &location);
+            }
+        }
+        tx.put(COL_META, BEST_KEY, hash.as_bytes());
+        self.db.write(tx)?;
+
+        Ok(route)
+    }
+
+    /// Path from the best block to the block `hash`, or why the head can't
+    /// move there.
+    pub fn head_route(&self, hash: &H256) -> result::Result<Result<TreeRoute, String>> {
+        let route = match self.best_hash()? {
+            Some(best) => self.tree_route(&best, hash)?,
+            None => TreeRoute {
+                ancestor: None,
+                retracted: vec![],
+                enacted: self.ancestry(hash)?,
+            },
+        };
+        if let Some(finalized) = self.finalized_hash()? {
+            if route.retracted.contains(&finalized) {
+                return Ok(Err(format!(
+                    "{:?} is not a descendant of the finalized block",
+                    hash
+                )));
+            }
+        }
+        Ok(Ok(route))
+    }
+
+    /// Path from the block `from` to the block `to` through their latest
+    /// common ancestor.
+    pub fn tree_route(&self, from: &H256, to: &H256) -> result::Result<TreeRoute> {
+        let mut from = (*from, self.expect_header(from)?);
+        let mut to = (*to, self.expect_header(to)?);
+        let mut retracted = vec![];
+        let mut enacted = vec![];
+        while from.0 != to.0 {
+            let height = from.1.height.max(to.1.height);
+            if from.1.height == height {
+                retracted.push(from.0);
+                from = self.parent(&from.1)?;
+            }
+            if to.1.height == height {
+                enacted.push(to.0);
+                to = self.parent(&to.1)?;
+            }
+        }
+        enacted.reverse();
+
+        Ok(TreeRoute {
+            ancestor: Some(from.0),
+            retracted,
+            enacted,
+        })
+    }
+
+    /// Whether the block `hash` is `ancestor` or one of its descendants.
+    pub fn is_descendant(&self, hash: &H256, ancestor: &H256) -> result::Result<bool> {
+        let ancestor_height = self.expect_header(ancestor)?.height;
+        let mut current = (*hash, self.expect_header(hash)?);
+        while current.1.height > ancestor_height {
+            current = self.parent(&current.1)?;
+        }
+        Ok(current.0 == *ancestor)
+    }
+
+    pub fn header(&self, hash: &H256) -> result::Result<Option<BlockHeader>> {
+        match self.db.get(COL_HEADERS, hash.as_bytes())? {
+            Some(bytes) => Ok(Some(BlockHeader::parse_from_bytes(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn block(&self, hash: &H256) -> result::Result<Option<Block>> {
+        let header = match self.header(hash)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        let body = match self.db.get(COL_BODIES, hash.as_bytes())? {
+            Some(bytes) => BlockBody::parse_from_bytes(&bytes)?,
+            None => return Err(anyhow!("missing body of block {:?}", hash)),
+        };
+
+        let mut block = Block::new();
+        block.header = Some(header).into();
+        block.body = Some(body).into();
+        Ok(Some(block))
+    }
+
+    /// Hash of the canonical block at `height`.
+    pub fn block_hash(&self, height: u64) -> result::Result<Option<H256>> {
+        self.read_hash(COL_HEIGHTS, &height.to_be_bytes())
+    }
+
+    pub fn block_by_height(&self, height: u64) -> result::Result<Option<Block>> {
+        match self.block_hash(height)? {
+            Some(hash) => self.block(&hash),
+            None => Ok(None),
+        }
+    }
+
+    pub fn transaction_location(
+        &self,
+        tx_hash: &H256,
+    ) -> result::Result<Option<TransactionLocation>> {
+        match self.db.get(COL_TRANSACTIONS, tx_hash.as_bytes())? {
+            Some(bytes) if bytes.len() == 36 => Ok(Some(TransactionLocation {
+                block_hash: H256::from_slice(&bytes[..32]),
+                index: u32::from_be_bytes(bytes[32..].try_into()?),
+            })),
+            Some(_) => Err(anyhow!("corrupted location of transaction {:?}", tx_hash)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn transaction(&self, tx_hash: &H256) -> result::Result<Option<SignedTransaction>> {
+        let location = match self.transaction_location(tx_hash)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        Ok(self.block(&location.block_hash)?.and_then(|block| {
+            block
+                .body
+                .transactions
+                .get(location.index as usize)
+                .cloned()
+        }))
+    }
+
+    pub fn receipt(&self, tx_hash: &H256) -> result::Result<Option<Receipt>> {
+        let location = match self.transaction_location(tx_hash)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        Ok(self
+            .receipts(&location.block_hash)?
+            .into_iter()
+            .nth(location.index as usize))
+    }
+
+    /// Receipts of the transactions of `block`, in block order.
+    pub fn block_receipts(&self, block: &Block) -> result::Result<Vec<Receipt>> {
+        let hash = crate::block::hash(block.header.get_or_default());
+        let receipts = self.receipts(&hash)?;
+        if receipts.len() != block.body.transactions.len() {
+            return Err(anyhow!("missing receipts of block {:?}", hash));
+        }
+        Ok(receipts)
+    }
+
+    /// Proves the inclusion of a transaction against the `transactions_root`
+    /// of its block.
+    pub fn transaction_proof(&self, tx_hash: &H256) -> result::Result<Option<Vec<Vec<u8>>>> {
+        let (block, index) = match self.located_block(tx_hash)? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        let transactions = &block.body.transactions;
+        let data = transactions
+            .iter()
+            .map(|signed| &signed.transaction_with_sig.transaction.data);
+        proof(data, index, &block.header.transactions_root).map(Some)
+    }
+
+    /// Proves the receipt of a transaction against the `receipts_root` of its
+    /// block.
+    pub fn receipt_proof(&self, tx_hash: &H256) -> result::Result<Option<Vec<Vec<u8>>>> {
+        let (block, index) = match self.located_block(tx_hash)? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        let receipts = self
+            .block_receipts(&block)?
+            .iter()
+            .map(|r| EnvelopedEncodable::encode(&r.receipt))
+            .collect::<Vec<_>>();
+        proof(receipts, index, &block.header.receipts_root).map(Some)
+    }
+
+    pub fn best_hash(&self) -> result::Result<Option<H256>> {
+        self.read_hash(COL_META, BEST_KEY)
+    }
+
+    pub fn best_header(&self) -> result::Result<Option<BlockHeader>> {
+        match self.best_hash()? {
+            Some(hash) => self.header(&hash),
+            None => Ok(None),
+        }
+    }
+
+    pub fn finalized_hash(&self) -> result::Result<Option<H256>> {
+        self.read_hash(COL_META, FINALIZED_KEY)
+    }
+
+    /// Marks a stored block as final.
+    pub fn set_finalized(&self, hash: &H256) -> result::Result<()> {
+        if self.header(hash)?.is_none() {
+            return Err(anyhow!("unknown block {:?}", hash));
+        }
+
+        let mut tx = DBTransaction::new();
+        tx.put(COL_META, FINALIZED_KEY, hash.as_bytes());
+        Ok(self.db.write(tx)?)
+    }
+
+    /// State the consensus engine persisted last.
+    pub fn consensus_state(&self) -> result::Result<Option<Vec<u8>>> {
+        Ok(self.db.get(COL_META, CONSENSUS_KEY)?)
+    }
+
+    pub fn set_consensus_state(&self, state: &[u8]) -> result::Result<()> {
+        let mut tx = DBTransaction::new();
+        tx.put(COL_META, CONSENSUS_KEY, state);
+        Ok(self.db.write(tx)?)
+    }
+
+    fn located_block(&self, tx_hash: &H256) -> result::Result<Option<(Block, u32)>> {
+        let location = match self.transaction_location(tx_hash)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        match self.block(&location.block_hash)? {
+            Some(block) => Ok(Some((block, location.index))),
+            None => Err(anyhow!("missing block {:?}", location.block_hash)),
+        }
+    }
+
+    fn receipts(&self, block_hash: &H256) -> result::Result<Vec<Receipt>> {
+        match self.db.get(COL_RECEIPTS, block_hash.as_bytes())? {
+            Some(bytes) => Ok(Vec::<Receipt>::decode(&mut &bytes[..])?),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn expect_header(&self, hash: &H256) -> result::Result<BlockHeader> {
+        self.header(hash)?
+            .ok_or_else(|| anyhow!("unknown block {:?}", hash))
+    }
+
+    fn expect_block(&self, hash: &H256) -> result::Result<Block> {
+        self.block(hash)?
+            .ok_or_else(|| anyhow!("unknown block {:?}", hash))
+    }
+
+    fn parent(&self, header: &BlockHeader) -> result::Result<(H256, BlockHeader)> {
+        let hash = H256::from_slice(&header.prevhash);
+        Ok((hash, self.expect_header(&hash)?))
+    }
+
+    /// The block `hash` and its stored ancestors, lowest first.
+    fn ancestry(&self, hash: &H256) -> result::Result<Vec<H256>> {
+        let mut ancestry = vec![*hash];
+        let mut header = self.expect_header(hash)?;
+        while header.height > 0 {
+            let parent = H256::from_slice(&header.prevhash);
+            header = match self.header(&parent)? {
+                Some(header) => header,
+                None => break,
+            };
+            ancestry.push(parent);
+        }
+        ancestry.reverse();
+        Ok(ancestry)
+    }
+
+    fn read_hash(&self, col: u32, key: &[u8]) -> result::Result<Option<H256>> {
+        match self.db.get(col, key)? {
+            Some(bytes) if bytes.len() == 32 => Ok(Some(H256::from_slice(&bytes))),
+            Some(_) => Err(anyhow!("corrupted hash in column {}", col)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Rebuilds the ordered trie of a block's `items` and proves the one at
+/// `index`, checking the trie matches the `root` of the header.
+fn proof<I, V>(items: I, index: u32, root: &[u8]) -> result::Result<Vec<Vec<u8>>>
+where
+    I: IntoIterator<Item = V>,
+    V: AsRef<[u8]>,
+{
+    let (computed, db) = trie::ordered_trie(items);
+    if computed.as_bytes() != root {
+        return Err(anyhow!("block root mismatch: computed {:?}", computed));
+    }
+    Ok(trie::generate_proof(
+        &db,
+        &computed,
+        &trie::index_key(index as usize),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethereum_types::{H160, U256};
+
+    fn block(parent: &BlockHeader, txs: &[H256]) -> Block {
+        let mut header = BlockHeader::new();
+        header.prevhash = crate::block::hash(parent).as_bytes().to_vec();
+        header.height = parent.height + 1;
+
+        let mut body = BlockBody::new();
+        body.transactions = txs
+            .iter()
+            .map(|hash| {
+                let mut signed = SignedTransaction::new();
+                signed.tx_hash = hash.as_bytes().to_vec();
+                signed
+            })
+            .collect();
+
+        let mut block = Block::new();
+        block.header = Some(header).into();
+        block.body = Some(body).into();
+        block
+    }
+
+    fn receipt(tx_hash: H256) -> Receipt {
+        let tx = ethereum::TransactionV2::Legacy(ethereum::LegacyTransaction {
+            nonce: U256::zero(),
+            gas_price: U256::zero(),
+            gas_limit: U256::from(21000),
+            action: ethereum::TransactionAction::Create,
+            value: U256::zero(),
+            input: vec![],
+            signature: ethereum::TransactionSignature::new(
+                27,
+                H256::repeat_byte(1),
+                H256::repeat_byte(1),
+            )
+            .unwrap(),
+        });
+        let log = evm::Log {
+            address: H160::repeat_byte(5),
+            topics: vec![H256::repeat_byte(6)],
+            data: vec![1, 2, 3],
+        };
+        Receipt::new(
+            &tx,
+            tx_hash,
+            0,
+            2,
+            H160::repeat_byte(4),
+            &evm::ExitReason::Succeed(evm::ExitSucceed::Returned),
+            Some(H160::repeat_byte(7)),
+            U256::from(21000),
+            U256::from(21000),
+            vec![log],
+        )
+    }
+
+    #[test]
+    fn indexes_committed_blocks() {
+        let db = Arc::new(kvdb_memorydb::create(NUM_COLUMNS));
+        let store = ChainStore::new(db.clone());
+        assert_eq!(store.best_hash().unwrap(), None);
+
+        let first = block(&BlockHeader::new(), &[H256::repeat_byte(1)]);
+        let second = block(
+            first.header.get_or_default(),
+            &[H256::repeat_byte(2), H256::repeat_byte(3)],
+        );
+        let first_hash = store.commit(&first, &[]).unwrap();
+        let receipt = receipt(H256::repeat_byte(2));
+        let second_hash = store
+            .commit(&second, std::slice::from_ref(&receipt))
+            .unwrap();
+
+        assert_eq!(store.block(&first_hash).unwrap(), Some(first.clone()));
+        assert_eq!(store.block_hash(2).unwrap(), Some(second_hash));
+        assert_eq!(store.block_by_height(1).unwrap(), Some(first));
+        assert_eq!(
+            store.transaction_location(&H256::repeat_byte(3)).unwrap(),
+            Some(TransactionLocation {
+                block_hash: second_hash,
+                index: 1
+            })
+        );
+        assert_eq!(
+            store.transaction(&H256::repeat_byte(2)).unwrap(),
+            Some(second.body.transactions[0].clone())
+        );
+        assert_eq!(store.transaction(&H256::repeat_byte(4)).unwrap(), None);
+        assert_eq!(store.receipt(&H256::repeat_byte(2)).unwrap(), Some(receipt));
+        assert_eq!(store.receipt(&H256::repeat_byte(3)).unwrap(), None);
+
+        assert!(store.set_finalized(&H256::repeat_byte(9)).is_err());
+        store.set_finalized(&first_hash).unwrap();
+
+        // Head pointers are read back from the database.
+        let store = ChainStore::new(db);
+        assert_eq!(store.best_hash().unwrap(), Some(second_hash));
+        assert_eq!(store.best_header().unwrap().unwrap().height, 2);
+        assert_eq!(store.finalized_hash().unwrap(), Some(first_hash));
+    }
+
+    #[test]
+    fn proves_transactions_and_receipts() {
+        let store = ChainStore::new(Arc::new(kvdb_memorydb::create(NUM_COLUMNS)));
+        let hashes = (1..=20).map(H256::repeat_byte).collect::<Vec<_>>();
+        let mut block = block(&BlockHeader::new(), &hashes);
+        for (index, signed) in block
+            .body
+            .mut_or_insert_default()
+            .transactions
+            .iter_mut()
+            .enumerate()
+        {
+            signed
+                .transaction_with_sig
+                .mut_or_insert_default()
+                .transaction
+                .mut_or_insert_default()
+                .data = vec![index as u8; 50];
+        }
+        let receipts = hashes
+            .iter()
+            .enumerate()
+            .map(|(index, hash)| Receipt {
+                transaction_index: index as u32,
+                ..receipt(*hash)
+            })
+            .collect::<Vec<_>>();
+        let header = block.header.mut_or_insert_default();
+        header.transactions_root = crate::transaction::transactions_root(&block.body.transactions)
+            .as_bytes()
+            .to_vec();
+        header.receipts_root = crate::receipt::receipts_root(&receipts).as_bytes().to_vec();
+        store.commit(&block, &receipts).unwrap();
+
+        let key = trie::index_key(13);
+        let proof = store.transaction_proof(&hashes[13]).unwrap().unwrap();
+        let root = H256::from_slice(&block.header.transactions_root);
+        assert_eq!(
+            trie::verify_proof(&root, &key, &proof).unwrap(),
+            Some(vec![13; 50])
+        );
+
+        let proof = store.receipt_proof(&hashes[13]).unwrap().unwrap();
+        let root = H256::from_slice(&block.header.receipts_root);
+        assert_eq!(
+            trie::verify_proof(&root, &key, &proof).unwrap(),
+            Some(EnvelopedEncodable::encode(&receipts[13].receipt).to_vec())
+        );
+
+        assert_eq!(store.transaction_proof(&H256::zero()).unwrap(), None);
+    }
+
+    #[test]
+    fn reorganizes_to_another_branch() {
+        let store = ChainStore::new(Arc::new(kvdb_memorydb::create(NUM_COLUMNS)));
+        let (x, y) = (H256::repeat_byte(1), H256::repeat_byte(2));
+        let a1 = block(&BlockHeader::new(), &[]);
+        let a2 = block(a1.header.get_or_default(), &[x]);
+        let mut b2 = block(a1.header.get_or_default(), &[y]);
+        b2.header.mut_or_insert_default().timestamp = 1;
+        let b3 = block(b2.header.get_or_default(), &[]);
+
+        let a1_hash = store.commit(&a1, &[]).unwrap();
+        let a2_hash = store.commit(&a2, &[receipt(x)]).unwrap();
+        let b2_hash = store.insert(&b2, &[receipt(y)]).unwrap();
+        let b3_hash = store.insert(&b3, &[]).unwrap();
+
+        // Side blocks are stored without being indexed.
+        assert_eq!(store.best_hash().unwrap(), Some(a2_hash));
+        assert_eq!(store.block(&b2_hash).unwrap(), Some(b2.clone()));
+        assert_eq!(store.transaction_location(&y).unwrap(), None);
+        assert!(store.is_descendant(&b3_hash, &a1_hash).unwrap());
+        assert!(!store.is_descendant(&b3_hash, &a2_hash).unwrap());
+
+        let route = store.set_head(&b3_hash).unwrap();
+        assert_eq!(
+            route,
+            TreeRoute {
+                ancestor: Some(a1_hash),
+                retracted: vec![a2_hash],
+                enacted: vec![b2_hash, b3_hash],
+            }
+        );
+        assert_eq!(store.best_hash().unwrap(), Some(b3_hash));
+        assert_eq!(store.block_hash(2).unwrap(), Some(b2_hash));
+        assert_eq!(store.block_hash(3).unwrap(), Some(b3_hash));
+        assert_eq!(store.transaction_location(&x).unwrap(), None);
+        assert_eq!(store.receipt(&y).unwrap(), Some(receipt(y)));
+
+        // The finalized block never leaves the canonical chain.
+        store.set_finalized(&b2_hash).unwrap();
+        assert!(store.head_route(&a2_hash).unwrap().is_err());
+        assert!(store.set_head(&a2_hash).is_err());
+        assert_eq!(store.best_hash().unwrap(), Some(b3_hash));
+    }
+}
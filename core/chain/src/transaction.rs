@@ -0,0 +1,136 @@
+use anyhow::anyhow;
+use ethereum::{EnvelopedDecodable, EnvelopedEncodable, TransactionAction, TransactionV2};
+use ethereum_types::{H160, H256, U256};
+use proto::{SignedTransaction, Transaction, UnverifiedTransaction};
+use txpool::PoolTransaction;
+
+/// Fields of an Ethereum transaction the executor needs.
+pub struct TransactionInfo {
+    pub action: TransactionAction,
+    pub input: Vec<u8>,
+    pub value: U256,
+    pub gas_limit: U256,
+    pub gas_price: U256,
+    pub max_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub access_list: Vec<(H160, Vec<H256>)>,
+}
+
+impl From<&TransactionV2> for TransactionInfo {
+    fn from(tx: &TransactionV2) -> Self {
+        let access_list = |list: &ethereum::AccessList| {
+            list.iter()
+                .map(|item| (item.address, item.storage_keys.clone()))
+                .collect()
+        };
+
+        match tx {
+            TransactionV2::Legacy(t) => Self {
+                action: t.action,
+                input: t.input.clone(),
+                value: t.value,
+                gas_limit: t.gas_limit,
+                gas_price: t.gas_price,
+                max_fee_per_gas: None,
+                max_priority_fee_per_gas: None,
+                access_list: vec![],
+            },
+            TransactionV2::EIP2930(t) => Self {
+                action: t.action,
+                input: t.input.clone(),
+                value: t.value,
+                gas_limit: t.gas_limit,
+                gas_price: t.gas_price,
+                max_fee_per_gas: None,
+                max_priority_fee_per_gas: None,
+                access_list: access_list(&t.access_list),
+            },
+            TransactionV2::EIP1559(t) => Self {
+                action: t.action,
+                input: t.input.clone(),
+                value: t.value,
+                gas_limit: t.gas_limit,
+                gas_price: t.max_fee_per_gas,
+                max_fee_per_gas: Some(t.max_fee_per_gas),
+                max_priority_fee_per_gas: Some(t.max_priority_fee_per_gas),
+                access_list: access_list(&t.access_list),
+            },
+        }
+    }
+}
+
+/// Wraps a pooled transaction into its block representation.
+///
+/// `data` carries the EIP-2718 encoded transaction, which is authoritative;
+/// the other `Transaction` fields mirror it for consumers of the proto types.
+/// `signer` holds the recovered sender address.
+pub fn to_signed(tx: &PoolTransaction) -> SignedTransaction {
+    let info = TransactionInfo::from(&tx.transaction);
+    let (version, chain_id, signature) = match &tx.transaction {
+        TransactionV2::Legacy(t) => (
+            0,
+            t.signature.chain_id(),
+            signature(t.signature.r(), t.signature.s(), t.signature.standard_v()),
+        ),
+        TransactionV2::EIP2930(t) => (
+            1,
+            Some(t.chain_id),
+            signature(&t.r, &t.s, t.odd_y_parity as u8),
+        ),
+        TransactionV2::EIP1559(t) => (
+            2,
+            Some(t.chain_id),
+            signature(&t.r, &t.s, t.odd_y_parity as u8),
+        ),
+    };
+
+    let mut value = [0u8; 32];
+    info.value.to_big_endian(&mut value);
+
+    let mut transaction = Transaction::new();
+    transaction.to = match info.action {
+        TransactionAction::Call(to) => format!("{:x}", to),
+        TransactionAction::Create => String::new(),
+    };
+    transaction.nonce = tx.nonce.to_string();
+    transaction.quota = info.gas_limit.low_u64();
+    transaction.valid_until_block = tx.valid_until_block;
+    transaction.data = tx.transaction.encode().to_vec();
+    transaction.value = value.to_vec();
+    transaction.chain_id = chain_id.unwrap_or_default() as u32;
+    transaction.version = version;
+
+    let mut unverified = UnverifiedTransaction::new();
+    unverified.transaction = Some(transaction).into();
+    unverified.signature = signature;
+
+    let mut signed = SignedTransaction::new();
+    signed.transaction_with_sig = Some(unverified).into();
+    signed.tx_hash = tx.hash.as_bytes().to_vec();
+    signed.signer = tx.sender.as_bytes().to_vec();
+    signed
+}
+
+/// Decodes the Ethereum transaction carried by a block transaction.
+pub fn from_signed(signed: &SignedTransaction) -> result::Result<TransactionV2> {
+    TransactionV2::decode(&signed.transaction_with_sig.transaction.data)
+        .map_err(|e| anyhow!("invalid transaction {:?}: {:?}", signed.tx_hash, e))
+}
+
+/// Root of the trie of the EIP-2718 encoded transactions keyed by their
+/// index.
+pub fn transactions_root(transactions: &[SignedTransaction]) -> H256 {
+    trie::ordered_root(
+        transactions
+            .iter()
+            .map(|signed| &signed.transaction_with_sig.transaction.data),
+    )
+}
+
+fn signature(r: &H256, s: &H256, v: u8) -> Vec<u8> {
+    let mut signature = Vec::with_capacity(65);
+    signature.extend_from_slice(r.as_bytes());
+    signature.extend_from_slice(s.as_bytes());
+    signature.push(v);
+    signature
+}
@@ -0,0 +1,324 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use codec::{Decode, Encode};
+use ethereum_types::H160;
+use proto::{block_hash, Block, BlockHeader, BlockWithProof, Proof, ProofType, RichStatus};
+use tracing::warn;
+
+use crate::engine::{validators, Actions, ConsensusEngine, EngineAction};
+use crate::signer::{recover, Signer};
+
+/// Content of a `Proof` of type `AuthorityRound`: the signature of the
+/// block hash by the validator owning the slot of the block.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct AuraProof {
+    pub slot: u64,
+    pub signature: Vec<u8>,
+}
+
+impl AuraProof {
+    /// Checks `header` was sealed on top of the block of `parent`, in a
+    /// later slot, by the owner of its slot among the validators of
+    /// `parent`.
+    pub fn verify(header: &BlockHeader, parent: &RichStatus) -> result::Result<AuraProof> {
+        if header.prevhash != parent.hash {
+            return Err(anyhow!(
+                "block of height {} on another parent",
+                header.height
+            ));
+        }
+        let proof = header.proof.get_or_default();
+        if proof.type_.enum_value() != Ok(ProofType::AuthorityRound) {
+            return Err(anyhow!("not an AuRa proof"));
+        }
+        let decoded = AuraProof::decode(&mut &proof.content[..])
+            .map_err(|e| anyhow!("invalid AuRa proof: {}", e))?;
+        if decoded.slot != slot(header.timestamp, parent.interval) {
+            return Err(anyhow!(
+                "slot {} doesn't match timestamp {}",
+                decoded.slot,
+                header.timestamp
+            ));
+        }
+        let parent_slot = slot(parent.timestamp, parent.interval);
+        if decoded.slot <= parent_slot {
+            return Err(anyhow!(
+                "slot {} not after parent slot {}",
+                decoded.slot,
+                parent_slot
+            ));
+        }
+
+        let validators = validators(parent)?;
+        let owner = slot_owner(decoded.slot, &validators)?;
+        if header.proposer != owner.as_bytes() {
+            return Err(anyhow!("slot {} belongs to {:?}", decoded.slot, owner));
+        }
+        if recover(block_hash(header).as_bytes(), &decoded.signature)? != owner {
+            return Err(anyhow!("block not signed by {:?}", owner));
+        }
+        Ok(decoded)
+    }
+}
+
+/// Slot of a block with `timestamp`, for blocks every `interval` seconds.
+pub fn slot(timestamp: u64, interval: u64) -> u64 {
+    timestamp / interval.max(1)
+}
+
+/// Validator sealing the blocks of `slot`, taking turns in order.
+pub fn slot_owner(slot: u64, validators: &[H160]) -> result::Result<H160> {
+    if validators.is_empty() {
+        return Err(anyhow!("no validators"));
+    }
+    Ok(validators[(slot % validators.len() as u64) as usize])
+}
+
+/// Milliseconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Round-robin authority engine: time is split in slots of the block
+/// interval, and the validator owning a slot seals at most one block in it.
+///
+/// Sealed blocks carry no finality: they are imported as any block, the
+/// fork choice preferring the longest chain.
+pub struct Aura {
+    signer: Signer,
+    /// Status of the chain the engine seals on.
+    status: RichStatus,
+}
+
+impl Aura {
+    pub fn new(signer: Signer) -> Self {
+        Self {
+            signer,
+            status: RichStatus::new(),
+        }
+    }
+
+    pub fn address(&self) -> H160 {
+        self.signer.address()
+    }
+
+    /// Whether the validator owns `slot` and may seal a block in it on top
+    /// of the best block of `status`.
+    pub fn is_turn(&self, slot: u64, status: &RichStatus) -> result::Result<bool> {
+        Ok(slot > self::slot(status.timestamp, status.interval)
+            && slot_owner(slot, &validators(status)?)? == self.address())
+    }
+
+    /// Signs `block`, which must have been produced by the validator in its
+    /// own slot.
+    pub fn seal(&self, block: Block, status: &RichStatus) -> result::Result<BlockWithProof> {
+        let header = block.header.get_or_default();
+        let slot = slot(header.timestamp, status.interval);
+        if header.proposer != self.address().as_bytes() || !self.is_turn(slot, status)? {
+            return Err(anyhow!("slot {} is not ours", slot));
+        }
+        let signature = self.signer.sign(block_hash(header).as_bytes());
+
+        let mut proof = Proof::new();
+        proof.content = AuraProof { slot, signature }.encode();
+        proof.type_ = ProofType::AuthorityRound.into();
+        let mut sealed = BlockWithProof::new();
+        sealed.blk = Some(block).into();
+        sealed.proof = Some(proof).into();
+        Ok(sealed)
+    }
+}
+
+impl Aura {
+    /// Waits for the start of the slot after the current one.
+    fn schedule(&self) -> EngineAction<BlockWithProof, u64> {
+        let interval = self.status.interval.max(1) * 1000;
+        let now = now();
+        let next = now / interval + 1;
+        EngineAction::Timeout {
+            timer: next,
+            after: Duration::from_millis(next * interval - now),
+        }
+    }
+}
+
+impl ConsensusEngine for Aura {
+    /// Blocks sealed by the other validators.
+    type Message = BlockWithProof;
+    /// Slot starting.
+    type Timer = u64;
+
+    fn proof_type(&self) -> Option<ProofType> {
+        Some(ProofType::AuthorityRound)
+    }
+
+    fn start(&mut self, status: &RichStatus) -> Actions<Self> {
+        self.status = status.clone();
+        vec![self.schedule()]
+    }
+
+    fn propose(&mut self, block: Block) -> Actions<Self> {
+        match self.seal(block, &self.status) {
+            Ok(sealed) => vec![
+                EngineAction::Send {
+                    to: None,
+                    msg: sealed.clone(),
+                },
+                EngineAction::Import(sealed),
+            ],
+            Err(e) => {
+                warn!("missed slot: {}", e);
+                vec![]
+            }
+        }
+    }
+
+    fn verify(&self, header: &BlockHeader, status: &RichStatus) -> result::Result<()> {
+        AuraProof::verify(header, status)?;
+        Ok(())
+    }
+
+    /// Blocks may extend any block rather than the best one, so their seal
+    /// is checked against their parent by `verify` before they are imported.
+    fn on_message(&mut self, sealed: BlockWithProof) -> Actions<Self> {
+        vec![EngineAction::Import(sealed)]
+    }
+
+    fn on_timeout(&mut self, slot: u64) -> Actions<Self> {
+        let mut actions = vec![self.schedule()];
+        match self.is_turn(slot, &self.status) {
+            Ok(true) => actions.push(EngineAction::Propose),
+            Ok(false) => {}
+            Err(e) => warn!("cannot seal slot {}: {}", slot, e),
+        }
+        actions
+    }
+
+    fn on_finalized(&mut self, status: &RichStatus) -> Actions<Self> {
+        self.status = status.clone();
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signers() -> Vec<Signer> {
+        (1..=3)
+            .map(|i| Signer::new(libsecp256k1::SecretKey::parse(&[i; 32]).unwrap()))
+            .collect()
+    }
+
+    fn status(validators: &[Signer]) -> RichStatus {
+        let mut status = RichStatus::new();
+        status.hash = vec![1; 32];
+        status.height = 1;
+        status.timestamp = 30;
+        status.interval = 3;
+        status.validators = validators
+            .iter()
+            .map(|signer| signer.address().as_bytes().to_vec())
+            .collect();
+        status
+    }
+
+    fn block(timestamp: u64, proposer: H160) -> Block {
+        let mut header = BlockHeader::new();
+        header.height = 2;
+        header.prevhash = vec![1; 32];
+        header.timestamp = timestamp;
+        header.proposer = proposer.as_bytes().to_vec();
+        let mut block = Block::new();
+        block.header = Some(header).into();
+        block
+    }
+
+    fn sealed_header(sealed: &BlockWithProof) -> BlockHeader {
+        let mut header = sealed.blk.header.get_or_default().clone();
+        header.proof = sealed.proof.clone();
+        header
+    }
+
+    #[test]
+    fn validators_take_turns() {
+        let signers = signers();
+        let status = status(&signers);
+        // The head is in slot 10, owned by the second validator.
+        let turns = (10..14)
+            .map(|slot| {
+                signers
+                    .iter()
+                    .position(|signer| Aura::new(signer.clone()).is_turn(slot, &status).unwrap())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(turns, vec![None, Some(2), Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn verifies_slot_ownership() {
+        let signers = signers();
+        let status = status(&signers);
+        let owner = Aura::new(signers[2].clone());
+
+        // Slot 11 starts at 33 and belongs to the third validator.
+        let sealed = owner.seal(block(34, owner.address()), &status).unwrap();
+        let proof = AuraProof::verify(&sealed_header(&sealed), &status).unwrap();
+        assert_eq!(proof.slot, 11);
+
+        let other = Aura::new(signers[0].clone());
+        assert!(other.seal(block(34, other.address()), &status).is_err());
+        assert!(owner.seal(block(36, owner.address()), &status).is_err());
+
+        let mut moved = sealed_header(&sealed);
+        moved.timestamp = 36;
+        assert!(AuraProof::verify(&moved, &status).is_err());
+
+        // A block of the slot signed by another validator.
+        let mut forged = sealed_header(&sealed);
+        let mut proof = forged.proof.get_or_default().clone();
+        let signature = signers[0].sign(block_hash(&forged).as_bytes());
+        proof.content = AuraProof {
+            slot: 11,
+            signature,
+        }
+        .encode();
+        forged.proof = Some(proof).into();
+        assert!(AuraProof::verify(&forged, &status).is_err());
+
+        let mut unsealed = sealed_header(&sealed);
+        unsealed.proof.mut_or_insert_default().type_ = ProofType::Bft.into();
+        assert!(AuraProof::verify(&unsealed, &status).is_err());
+    }
+
+    #[test]
+    fn verifies_against_the_parent() {
+        let signers = signers();
+        let parent = status(&signers);
+        let owner = Aura::new(signers[2].clone());
+        let sealed = owner.seal(block(34, owner.address()), &parent).unwrap();
+        let header = sealed_header(&sealed);
+        assert!(owner.verify(&header, &parent).is_ok());
+
+        // A parent in the same slot as the block.
+        let mut late = parent.clone();
+        late.timestamp = 33;
+        assert!(owner.verify(&header, &late).is_err());
+
+        let mut other = parent.clone();
+        other.hash = vec![2; 32];
+        assert!(owner.verify(&header, &other).is_err());
+
+        // Blocks of other nodes are left to `verify`, whatever the head.
+        let mut engine = Aura::new(signers[0].clone());
+        engine.on_finalized(&late);
+        assert!(matches!(
+            engine.on_message(sealed).as_slice(),
+            [EngineAction::Import(_)]
+        ));
+    }
+}
@@ -0,0 +1,1046 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use codec::{Decode, Encode};
+use ethereum_types::{H160, H256};
+use proto::{
+    block_hash, Block, BlockHeader, BlockWithProof, Proof, ProofType, Proposal, RichStatus,
+    SignedProposal, Vote,
+};
+use protobuf::Message;
+use tracing::debug;
+
+use crate::engine::{validators, Actions, ConsensusEngine, EngineAction};
+use crate::signer::{recover, Signer};
+
+/// Most messages of the next height kept per validator.
+const MAX_FUTURE: usize = 32;
+
+/// Validators and round timeouts of the BFT engine.
+#[derive(Clone, Debug)]
+pub struct BftConfig {
+    /// Validators taking turns to propose, in order.
+    pub validators: Vec<H160>,
+    /// How long to wait for the proposal of a round.
+    pub propose: Duration,
+    /// How long to wait for more prevotes once 2/3 of them disagree.
+    pub prevote: Duration,
+    /// How long to wait for more precommits once 2/3 of them disagree.
+    pub precommit: Duration,
+    /// Extra time given to each step per round, until the validators
+    /// catch up with each other.
+    pub delta: Duration,
+}
+
+impl Default for BftConfig {
+    fn default() -> Self {
+        Self {
+            validators: vec![],
+            propose: Duration::from_secs(3),
+            prevote: Duration::from_secs(1),
+            precommit: Duration::from_secs(1),
+            delta: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Step of a round.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Step {
+    Propose,
+    Prevote,
+    Precommit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Encode, Decode)]
+pub enum VoteType {
+    Prevote,
+    Precommit,
+}
+
+/// Vote of a validator for a block of a round, or for none if
+/// `vote.proposal` is empty.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignedVote {
+    pub height: u64,
+    pub round: u64,
+    pub kind: VoteType,
+    pub vote: Vote,
+}
+
+/// Message exchanged by the validators.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BftMessage {
+    Proposal(SignedProposal),
+    Vote(SignedVote),
+    /// Block committed with the precommits of the validators, for those that
+    /// missed them.
+    Commit(BlockWithProof),
+}
+
+/// Timeout of a step of a round, by height, round and step.
+pub type BftTimer = (u64, u64, Step);
+
+type Action = EngineAction<BftMessage, BftTimer>;
+
+/// Content of a `Proof` of type `Bft`: the precommits for the block of more
+/// than 2/3 of the validators.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct BftProof {
+    pub height: u64,
+    pub round: u64,
+    pub block: H256,
+    /// Validators with their precommit signature.
+    pub commits: Vec<(H160, Vec<u8>)>,
+}
+
+impl BftProof {
+    /// Checks `proof` finalizes the block `hash` at `height`.
+    pub fn verify(
+        proof: &Proof,
+        height: u64,
+        hash: &H256,
+        validators: &[H160],
+    ) -> result::Result<BftProof> {
+        if proof.type_.enum_value() != Ok(ProofType::Bft) {
+            return Err(anyhow!("not a BFT proof"));
+        }
+        let decoded = BftProof::decode(&mut &proof.content[..])
+            .map_err(|e| anyhow!("invalid BFT proof: {}", e))?;
+        if decoded.height != height || decoded.block != *hash {
+            return Err(anyhow!("proof of another block"));
+        }
+
+        let message = vote_message(height, decoded.round, VoteType::Precommit, Some(*hash));
+        let mut signers = HashSet::new();
+        for (validator, signature) in &decoded.commits {
+            if !validators.contains(validator) || recover(&message, signature)? != *validator {
+                return Err(anyhow!("invalid precommit of {:?}", validator));
+            }
+            signers.insert(*validator);
+        }
+        if signers.len() < quorum(validators.len()) {
+            return Err(anyhow!(
+                "{} of {} validators committed",
+                signers.len(),
+                validators.len()
+            ));
+        }
+        Ok(decoded)
+    }
+}
+
+/// Votes of a round and type by validator, with the block voted for.
+type Votes = HashMap<H160, (Option<H256>, Vote)>;
+
+/// Rules that take effect once per round.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Rule {
+    PrevoteWait,
+    Polka,
+    PrecommitWait,
+}
+
+/// Block proposed for a round.
+struct Proposed {
+    block: Block,
+    hash: H256,
+    /// Round of the prevotes the proposer locked the block with.
+    valid_round: Option<u64>,
+}
+
+/// Tendermint consensus state machine of one validator.
+///
+/// Each height runs rounds of three steps until a block is committed: the
+/// proposer of the round proposes a block, validators prevote for it if
+/// valid and compatible with their lock, and precommit it once more than
+/// 2/3 prevoted for it, locking on it. More than 2/3 precommits commit the
+/// block. A validator locked on a block only prevotes for another one if
+/// more than 2/3 prevoted for it in a later round, which keeps two blocks
+/// from being committed at one height while less than 1/3 are faulty.
+pub struct Bft {
+    config: BftConfig,
+    signer: Signer,
+    height: u64,
+    round: u64,
+    step: Step,
+    locked: Option<(u64, Block)>,
+    valid: Option<(u64, Block)>,
+    /// Proposals of the height by round.
+    proposals: HashMap<u64, Proposed>,
+    /// Votes of the height by round and type.
+    votes: HashMap<(u64, VoteType), Votes>,
+    /// Validity of the proposed blocks, once verified.
+    verified: HashMap<H256, bool>,
+    verifying: HashSet<H256>,
+    fired: HashSet<(u64, Rule)>,
+    /// Messages of the next height received early, with their sender.
+    future: Vec<(H160, BftMessage)>,
+    actions: Vec<Action>,
+}
+
+impl Bft {
+    pub fn new(config: BftConfig, signer: Signer) -> result::Result<Self> {
+        if config.validators.is_empty() {
+            return Err(anyhow!("no validators"));
+        }
+        Ok(Self {
+            config,
+            signer,
+            height: 0,
+            round: 0,
+            step: Step::Propose,
+            locked: None,
+            valid: None,
+            proposals: HashMap::new(),
+            votes: HashMap::new(),
+            verified: HashMap::new(),
+            verifying: HashSet::new(),
+            fired: HashSet::new(),
+            future: vec![],
+            actions: vec![],
+        })
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    pub fn round(&self) -> u64 {
+        self.round
+    }
+
+    pub fn step(&self) -> Step {
+        self.step
+    }
+
+    fn quorum(&self) -> usize {
+        quorum(self.config.validators.len())
+    }
+
+    fn proposer(&self, height: u64, round: u64) -> H160 {
+        let validators = &self.config.validators;
+        validators[((height + round) % validators.len() as u64) as usize]
+    }
+
+    fn new_height(&mut self, height: u64) {
+        self.height = height;
+        self.locked = None;
+        self.valid = None;
+        self.proposals.clear();
+        self.votes.clear();
+        self.verified.clear();
+        self.verifying.clear();
+        self.fired.clear();
+        self.start_round(0);
+
+        for (_, msg) in mem::take(&mut self.future) {
+            if let Err(e) = self.receive(msg) {
+                debug!("ignored consensus message: {}", e);
+            }
+        }
+    }
+
+    fn start_round(&mut self, round: u64) {
+        self.round = round;
+        self.step = Step::Propose;
+        if self.proposer(self.height, round) == self.signer.address() {
+            match self.valid.clone() {
+                Some((valid_round, block)) => self.broadcast_proposal(block, Some(valid_round)),
+                None => self.actions.push(Action::Propose),
+            }
+        }
+        self.schedule(Step::Propose);
+    }
+
+    fn schedule(&mut self, step: Step) {
+        let base = match step {
+            Step::Propose => self.config.propose,
+            Step::Prevote => self.config.prevote,
+            Step::Precommit => self.config.precommit,
+        };
+        self.actions.push(Action::Timeout {
+            timer: (self.height, self.round, step),
+            after: base + self.config.delta * self.round as u32,
+        });
+    }
+
+    fn broadcast_proposal(&mut self, block: Block, valid_round: Option<u64>) {
+        let hash = block_hash(block.header.get_or_default());
+        let mut proposal = Proposal::new();
+        proposal.block = Some(block.clone()).into();
+        proposal.height = self.height;
+        proposal.round = self.round;
+        if let Some(valid_round) = valid_round {
+            proposal.islock = true;
+            proposal.lock_round = valid_round;
+            proposal.lock_votes = self
+                .votes
+                .get(&(valid_round, VoteType::Prevote))
+                .into_iter()
+                .flat_map(|votes| votes.values())
+                .filter(|(voted, _)| *voted == Some(hash))
+                .map(|(_, vote)| vote.clone())
+                .collect();
+        }
+        let bytes = proposal
+            .write_to_bytes()
+            .expect("encoding a proposal never fails");
+        let mut signed = SignedProposal::new();
+        signed.signature = self.signer.sign(&bytes);
+        signed.proposal = Some(proposal).into();
+
+        self.actions.push(Action::Send {
+            to: None,
+            msg: BftMessage::Proposal(signed),
+        });
+        self.verified.insert(hash, true);
+        self.proposals.insert(
+            self.round,
+            Proposed {
+                block,
+                hash,
+                valid_round,
+            },
+        );
+    }
+
+    fn vote(&mut self, kind: VoteType, block: Option<H256>) {
+        let message = vote_message(self.height, self.round, kind, block);
+        let mut vote = Vote::new();
+        vote.sender = self.signer.address().as_bytes().to_vec();
+        vote.proposal = block
+            .map(|hash| hash.as_bytes().to_vec())
+            .unwrap_or_default();
+        vote.signature = self.signer.sign(&message);
+
+        self.votes
+            .entry((self.round, kind))
+            .or_default()
+            .insert(self.signer.address(), (block, vote.clone()));
+        self.actions.push(Action::Send {
+            to: None,
+            msg: BftMessage::Vote(SignedVote {
+                height: self.height,
+                round: self.round,
+                kind,
+                vote,
+            }),
+        });
+    }
+
+    /// Records a message of another validator, without acting on it yet.
+    fn receive(&mut self, msg: BftMessage) -> result::Result<()> {
+        let height = match &msg {
+            BftMessage::Proposal(signed) => signed.proposal.height,
+            BftMessage::Vote(vote) => vote.height,
+            BftMessage::Commit(sealed) => sealed.blk.header.height,
+        };
+        if height == self.height + 1 {
+            let sender = self.check_sender(height, &msg)?;
+            if self
+                .future
+                .iter()
+                .filter(|(from, _)| *from == sender)
+                .count()
+                >= MAX_FUTURE
+            {
+                return Err(anyhow!("too many early messages of {:?}", sender));
+            }
+            self.future.push((sender, msg));
+            return Ok(());
+        }
+        if height != self.height {
+            return Err(anyhow!("message of height {}", height));
+        }
+
+        match msg {
+            BftMessage::Proposal(signed) => self.receive_proposal(signed),
+            BftMessage::Vote(vote) => {
+                let (sender, block) =
+                    self.check_vote(self.height, vote.round, vote.kind, &vote.vote)?;
+                self.votes
+                    .entry((vote.round, vote.kind))
+                    .or_default()
+                    .entry(sender)
+                    .or_insert((block, vote.vote));
+                Ok(())
+            }
+            BftMessage::Commit(sealed) => {
+                let hash = block_hash(sealed.blk.header.get_or_default());
+                BftProof::verify(
+                    sealed.proof.get_or_default(),
+                    self.height,
+                    &hash,
+                    &self.config.validators,
+                )?;
+                self.actions.push(Action::Commit(sealed));
+                self.new_height(self.height + 1);
+                Ok(())
+            }
+        }
+    }
+
+    fn receive_proposal(&mut self, signed: SignedProposal) -> result::Result<()> {
+        let proposal = signed.proposal.get_or_default();
+        let round = proposal.round;
+        let bytes = proposal.write_to_bytes()?;
+        let sender = recover(&bytes, &signed.signature)?;
+        if sender != self.proposer(self.height, round) {
+            return Err(anyhow!(
+                "{:?} is not the proposer of round {}",
+                sender,
+                round
+            ));
+        }
+        if self.proposals.contains_key(&round) {
+            return Ok(());
+        }
+        let block = proposal.block.get_or_default();
+        if block.header.height != self.height {
+            return Err(anyhow!("proposed block of height {}", block.header.height));
+        }
+        let hash = block_hash(block.header.get_or_default());
+
+        let valid_round = if proposal.islock {
+            let valid_round = proposal.lock_round;
+            if valid_round >= round {
+                return Err(anyhow!("locked in round {} of {}", valid_round, round));
+            }
+            let mut prevotes = HashMap::new();
+            for vote in &proposal.lock_votes {
+                let (sender, voted) =
+                    self.check_vote(self.height, valid_round, VoteType::Prevote, vote)?;
+                if voted == Some(hash) {
+                    prevotes.insert(sender, vote.clone());
+                }
+            }
+            if prevotes.len() < self.quorum() {
+                return Err(anyhow!("lock of round {} without a quorum", valid_round));
+            }
+            let votes = self
+                .votes
+                .entry((valid_round, VoteType::Prevote))
+                .or_default();
+            for (sender, vote) in prevotes {
+                votes.entry(sender).or_insert((Some(hash), vote));
+            }
+            Some(valid_round)
+        } else {
+            None
+        };
+
+        if !self.verified.contains_key(&hash) && self.verifying.insert(hash) {
+            self.actions.push(Action::Verify(block.clone()));
+        }
+        self.proposals.insert(
+            round,
+            Proposed {
+                block: block.clone(),
+                hash,
+                valid_round,
+            },
+        );
+        Ok(())
+    }
+
+    /// Checks a message of `height` is signed by a validator, returning the
+    /// validator. A commit is checked against its proof and attributed to
+    /// the proposer of the block.
+    fn check_sender(&self, height: u64, msg: &BftMessage) -> result::Result<H160> {
+        match msg {
+            BftMessage::Proposal(signed) => {
+                let proposal = signed.proposal.get_or_default();
+                let sender = recover(&proposal.write_to_bytes()?, &signed.signature)?;
+                if sender != self.proposer(height, proposal.round) {
+                    return Err(anyhow!(
+                        "{:?} is not the proposer of round {}",
+                        sender,
+                        proposal.round
+                    ));
+                }
+                Ok(sender)
+            }
+            BftMessage::Vote(vote) => {
+                let (sender, _) = self.check_vote(height, vote.round, vote.kind, &vote.vote)?;
+                Ok(sender)
+            }
+            BftMessage::Commit(sealed) => {
+                let header = sealed.blk.header.get_or_default();
+                BftProof::verify(
+                    sealed.proof.get_or_default(),
+                    height,
+                    &block_hash(header),
+                    &self.config.validators,
+                )?;
+                if header.proposer.len() != 20 {
+                    return Err(anyhow!("invalid proposer length {}", header.proposer.len()));
+                }
+                Ok(H160::from_slice(&header.proposer))
+            }
+        }
+    }
+
+    /// Checks a vote of `height` is signed by a validator, returning the
+    /// validator and the block voted for.
+    fn check_vote(
+        &self,
+        height: u64,
+        round: u64,
+        kind: VoteType,
+        vote: &Vote,
+    ) -> result::Result<(H160, Option<H256>)> {
+        if vote.sender.len() != 20 {
+            return Err(anyhow!("invalid vote sender"));
+        }
+        let sender = H160::from_slice(&vote.sender);
+        if !self.config.validators.contains(&sender) {
+            return Err(anyhow!("{:?} is not a validator", sender));
+        }
+        let block = match vote.proposal.len() {
+            0 => None,
+            32 => Some(H256::from_slice(&vote.proposal)),
+            len => return Err(anyhow!("invalid voted block length {}", len)),
+        };
+        let message = vote_message(height, round, kind, block);
+        if recover(&message, &vote.signature)? != sender {
+            return Err(anyhow!("invalid vote signature of {:?}", sender));
+        }
+        Ok((sender, block))
+    }
+
+    /// Number of `kind` votes of `round` for `block`, or for anything if
+    /// `block` is `None`.
+    fn count(&self, round: u64, kind: VoteType, block: Option<Option<H256>>) -> usize {
+        self.votes.get(&(round, kind)).map_or(0, |votes| {
+            votes
+                .values()
+                .filter(|(voted, _)| block.is_none_or(|block| *voted == block))
+                .count()
+        })
+    }
+
+    fn fire(&mut self, rule: Rule) -> bool {
+        self.fired.insert((self.round, rule))
+    }
+
+    /// Applies the rules enabled by the messages received so far, and
+    /// returns the resulting actions.
+    fn progress(&mut self) -> Vec<Action> {
+        loop {
+            let state = (self.height, self.round, self.step);
+            self.apply_rules();
+            if state == (self.height, self.round, self.step) {
+                break;
+            }
+        }
+        mem::take(&mut self.actions)
+    }
+
+    fn apply_rules(&mut self) {
+        let quorum = self.quorum();
+
+        // A block with a quorum of precommits is final whatever the round.
+        let decided = self.proposals.iter().find(|(round, proposed)| {
+            self.verified.get(&proposed.hash) == Some(&true)
+                && self.count(**round, VoteType::Precommit, Some(Some(proposed.hash))) >= quorum
+        });
+        if let Some((&round, proposed)) = decided {
+            let sealed = self.seal(round, proposed);
+            self.actions.push(Action::Commit(sealed.clone()));
+            self.actions.push(Action::Send {
+                to: None,
+                msg: BftMessage::Commit(sealed),
+            });
+            self.new_height(self.height + 1);
+            return;
+        }
+
+        // Skip to a later round more than 1/3 of the validators are in.
+        let skip = self.config.validators.len() - quorum + 1;
+        let later = self
+            .votes
+            .keys()
+            .map(|(round, _)| *round)
+            .filter(|round| *round > self.round)
+            .filter(|round| {
+                let mut senders = HashSet::<H160>::new();
+                for kind in [VoteType::Prevote, VoteType::Precommit] {
+                    if let Some(votes) = self.votes.get(&(*round, kind)) {
+                        senders.extend(votes.keys());
+                    }
+                }
+                senders.len() >= skip
+            })
+            .max();
+        if let Some(round) = later {
+            self.start_round(round);
+            return;
+        }
+
+        let round = self.round;
+        let proposal = self.proposals.get(&round).and_then(|proposed| {
+            let valid = *self.verified.get(&proposed.hash)?;
+            Some((proposed.hash, proposed.valid_round, valid))
+        });
+
+        if self.step == Step::Propose {
+            if let Some((hash, valid_round, valid)) = proposal {
+                let unlocked = match &self.locked {
+                    None => true,
+                    Some((locked_round, block)) => {
+                        block_hash(block.header.get_or_default()) == hash
+                            || valid_round.is_some_and(|valid_round| *locked_round <= valid_round)
+                    }
+                };
+                self.vote(VoteType::Prevote, (valid && unlocked).then_some(hash));
+                self.step = Step::Prevote;
+            }
+        }
+
+        if self.step == Step::Prevote
+            && self.count(round, VoteType::Prevote, None) >= quorum
+            && self.fire(Rule::PrevoteWait)
+        {
+            self.schedule(Step::Prevote);
+        }
+
+        if let Some((hash, _, true)) = proposal {
+            if self.step >= Step::Prevote
+                && self.count(round, VoteType::Prevote, Some(Some(hash))) >= quorum
+                && self.fire(Rule::Polka)
+            {
+                let block = self.proposals[&round].block.clone();
+                if self.step == Step::Prevote {
+                    self.locked = Some((round, block.clone()));
+                    self.vote(VoteType::Precommit, Some(hash));
+                    self.step = Step::Precommit;
+                }
+                self.valid = Some((round, block));
+            }
+        }
+
+        if self.step == Step::Prevote && self.count(round, VoteType::Prevote, Some(None)) >= quorum
+        {
+            self.vote(VoteType::Precommit, None);
+            self.step = Step::Precommit;
+        }
+
+        if self.count(round, VoteType::Precommit, None) >= quorum && self.fire(Rule::PrecommitWait)
+        {
+            self.schedule(Step::Precommit);
+        }
+    }
+
+    /// Attaches the precommits of `round` to the proposed block.
+    fn seal(&self, round: u64, proposed: &Proposed) -> BlockWithProof {
+        let mut commits = self.votes[&(round, VoteType::Precommit)]
+            .iter()
+            .filter(|(_, (voted, _))| *voted == Some(proposed.hash))
+            .map(|(sender, (_, vote))| (*sender, vote.signature.clone()))
+            .collect::<Vec<_>>();
+        commits.sort();
+        let content = BftProof {
+            height: self.height,
+            round,
+            block: proposed.hash,
+            commits,
+        };
+
+        let mut proof = Proof::new();
+        proof.content = content.encode();
+        proof.type_ = ProofType::Bft.into();
+        let mut sealed = BlockWithProof::new();
+        sealed.blk = Some(proposed.block.clone()).into();
+        sealed.proof = Some(proof).into();
+        sealed
+    }
+}
+
+impl ConsensusEngine for Bft {
+    type Message = BftMessage;
+    type Timer = BftTimer;
+
+    fn proof_type(&self) -> Option<ProofType> {
+        Some(ProofType::Bft)
+    }
+
+    /// Starts agreeing on the block on top of `status`.
+    fn start(&mut self, status: &RichStatus) -> Actions<Self> {
+        self.new_height(status.height + 1);
+        self.progress()
+    }
+
+    /// Proposes `block` in the current round, if it is still the one the
+    /// block was built for.
+    fn propose(&mut self, block: Block) -> Actions<Self> {
+        let round = self.round;
+        if (block.header.height, self.step) == (self.height, Step::Propose)
+            && self.proposer(self.height, round) == self.signer.address()
+            && !self.proposals.contains_key(&round)
+        {
+            self.broadcast_proposal(block, None);
+        }
+        self.progress()
+    }
+
+    fn verify(&self, header: &BlockHeader, status: &RichStatus) -> result::Result<()> {
+        BftProof::verify(
+            header.proof.get_or_default(),
+            header.height,
+            &block_hash(header),
+            &validators(status)?,
+        )?;
+        Ok(())
+    }
+
+    fn verified(&mut self, hash: H256, valid: bool) -> Actions<Self> {
+        if self.verifying.remove(&hash) {
+            self.verified.insert(hash, valid);
+        }
+        self.progress()
+    }
+
+    fn on_message(&mut self, msg: BftMessage) -> Actions<Self> {
+        if let Err(e) = self.receive(msg) {
+            debug!("ignored consensus message: {}", e);
+        }
+        self.progress()
+    }
+
+    fn on_timeout(&mut self, (height, round, step): BftTimer) -> Actions<Self> {
+        if (height, round) == (self.height, self.round) {
+            match step {
+                Step::Propose if self.step == Step::Propose => {
+                    self.vote(VoteType::Prevote, None);
+                    self.step = Step::Prevote;
+                }
+                Step::Prevote if self.step == Step::Prevote => {
+                    self.vote(VoteType::Precommit, None);
+                    self.step = Step::Precommit;
+                }
+                Step::Precommit => self.start_round(round + 1),
+                _ => {}
+            }
+        }
+        self.progress()
+    }
+}
+
+/// Number of validators that make more than 2/3 of `validators`.
+fn quorum(validators: usize) -> usize {
+    validators * 2 / 3 + 1
+}
+
+/// Bytes a validator signs to vote.
+fn vote_message(height: u64, round: u64, kind: VoteType, block: Option<H256>) -> Vec<u8> {
+    (height, round, kind, block).encode()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    fn signers(count: u8) -> Vec<Signer> {
+        (1..=count)
+            .map(|i| Signer::new(libsecp256k1::SecretKey::parse(&[i; 32]).unwrap()))
+            .collect()
+    }
+
+    fn status(height: u64) -> RichStatus {
+        let mut status = RichStatus::new();
+        status.height = height;
+        status
+    }
+
+    fn status_of(validators: &[H160]) -> RichStatus {
+        let mut status = status(0);
+        status.validators = validators.iter().map(|v| v.as_bytes().to_vec()).collect();
+        status
+    }
+
+    fn block(height: u64, round: u64, proposer: H160) -> Block {
+        let mut header = BlockHeader::new();
+        header.height = height;
+        header.timestamp = round;
+        header.proposer = proposer.as_bytes().to_vec();
+        let mut block = Block::new();
+        block.header = Some(header).into();
+        block
+    }
+
+    /// Validators exchanging messages instantly, except for the offline
+    /// ones. Pending timeouts fire in order whenever no message is left.
+    struct Network {
+        nodes: Vec<Bft>,
+        offline: HashSet<usize>,
+        queue: VecDeque<(usize, Action)>,
+        timeouts: VecDeque<(usize, BftTimer)>,
+        commits: Vec<(usize, BlockWithProof)>,
+    }
+
+    impl Network {
+        fn new(count: u8) -> Self {
+            let signers = signers(count);
+            let config = BftConfig {
+                validators: signers.iter().map(Signer::address).collect(),
+                ..Default::default()
+            };
+            let nodes = signers
+                .into_iter()
+                .map(|signer| Bft::new(config.clone(), signer).unwrap())
+                .collect();
+            Self {
+                nodes,
+                offline: HashSet::new(),
+                queue: VecDeque::new(),
+                timeouts: VecDeque::new(),
+                commits: vec![],
+            }
+        }
+
+        fn push(&mut self, node: usize, actions: Vec<Action>) {
+            if !self.offline.contains(&node) {
+                self.queue
+                    .extend(actions.into_iter().map(|action| (node, action)));
+            }
+        }
+
+        /// Runs height 1 until every online node committed it.
+        fn run(&mut self) {
+            for node in 0..self.nodes.len() {
+                let actions = self.nodes[node].start(&status(0));
+                self.push(node, actions);
+            }
+            let online = self.nodes.len() - self.offline.len();
+            for _ in 0..10_000 {
+                if self.commits.len() == online {
+                    return;
+                }
+                let (node, action) = match self.queue.pop_front() {
+                    Some(next) => next,
+                    None => {
+                        let (node, timer) = self.timeouts.pop_front().unwrap();
+                        let actions = self.nodes[node].on_timeout(timer);
+                        self.push(node, actions);
+                        continue;
+                    }
+                };
+                match action {
+                    Action::Send { msg, .. } => {
+                        for other in 0..self.nodes.len() {
+                            if other != node && !self.offline.contains(&other) {
+                                let actions = self.nodes[other].on_message(msg.clone());
+                                self.push(other, actions);
+                            }
+                        }
+                    }
+                    Action::Propose => {
+                        let bft = &self.nodes[node];
+                        let block = block(bft.height(), bft.round(), bft.signer.address());
+                        let actions = self.nodes[node].propose(block);
+                        self.push(node, actions);
+                    }
+                    Action::Verify(block) => {
+                        let hash = block_hash(block.header.get_or_default());
+                        let actions = self.nodes[node].verified(hash, true);
+                        self.push(node, actions);
+                    }
+                    Action::Commit(sealed) => self.commits.push((node, sealed)),
+                    Action::Timeout { timer, .. } => self.timeouts.push_back((node, timer)),
+                    Action::Import(_) => unreachable!("BFT blocks are final"),
+                    Action::Persist(_) => unreachable!("BFT keeps its state in memory"),
+                }
+            }
+            panic!("height 1 was not committed");
+        }
+
+        fn committed(&self) -> H256 {
+            let hashes = self
+                .commits
+                .iter()
+                .map(|(_, sealed)| block_hash(sealed.blk.header.get_or_default()))
+                .collect::<HashSet<_>>();
+            assert_eq!(hashes.len(), 1);
+            hashes.into_iter().next().unwrap()
+        }
+    }
+
+    #[test]
+    fn single_validator_commits_its_proposals() {
+        let signer = signers(1).remove(0);
+        let address = signer.address();
+        let config = BftConfig {
+            validators: vec![address],
+            ..Default::default()
+        };
+        let mut bft = Bft::new(config.clone(), signer).unwrap();
+        assert!(bft.start(&status(0)).contains(&Action::Propose));
+
+        let actions = bft.propose(block(1, 0, address));
+        let sealed = actions
+            .iter()
+            .find_map(|action| match action {
+                Action::Commit(sealed) => Some(sealed),
+                _ => None,
+            })
+            .unwrap();
+        let hash = block_hash(sealed.blk.header.get_or_default());
+        let proof = BftProof::verify(sealed.proof.get_or_default(), 1, &hash, &config.validators);
+        assert_eq!(proof.unwrap().commits.len(), 1);
+        assert_eq!((bft.height(), bft.round()), (2, 0));
+        assert!(actions.contains(&Action::Propose));
+
+        let mut header = sealed.blk.header.get_or_default().clone();
+        header.proof = sealed.proof.clone();
+        assert!(bft.verify(&header, &status_of(&config.validators)).is_ok());
+        header.height = 2;
+        assert!(bft.verify(&header, &status_of(&config.validators)).is_err());
+    }
+
+    #[test]
+    fn validators_agree_on_a_block() {
+        let mut network = Network::new(4);
+        network.run();
+        let hash = network.committed();
+
+        let validators = network.nodes[0].config.validators.clone();
+        for (_, sealed) in &network.commits {
+            let proof =
+                BftProof::verify(sealed.proof.get_or_default(), 1, &hash, &validators).unwrap();
+            assert_eq!(proof.round, 0);
+            assert!(proof.commits.len() >= 3);
+        }
+    }
+
+    #[test]
+    fn next_round_replaces_an_offline_proposer() {
+        let mut network = Network::new(4);
+        // Validator 1 proposes round 0 of height 1.
+        network.offline.insert(1);
+        network.run();
+        let hash = network.committed();
+
+        let (_, sealed) = &network.commits[0];
+        let validators = network.nodes[0].config.validators.clone();
+        let proof = BftProof::verify(sealed.proof.get_or_default(), 1, &hash, &validators).unwrap();
+        assert_eq!(proof.round, 1);
+        assert_eq!(sealed.blk.header.proposer, validators[2].as_bytes());
+    }
+
+    #[test]
+    fn rejects_proofs_without_quorum() {
+        let mut network = Network::new(4);
+        network.run();
+        let hash = network.committed();
+        let validators = network.nodes[0].config.validators.clone();
+        let (_, sealed) = &network.commits[0];
+
+        let mut proof = sealed.proof.get_or_default().clone();
+        let mut content = BftProof::decode(&mut &proof.content[..]).unwrap();
+        content.commits.truncate(2);
+        proof.content = content.encode();
+        assert!(BftProof::verify(&proof, 1, &hash, &validators).is_err());
+        assert!(BftProof::verify(sealed.proof.get_or_default(), 2, &hash, &validators).is_err());
+        assert!(BftProof::verify(
+            sealed.proof.get_or_default(),
+            1,
+            &H256::repeat_byte(1),
+            &validators
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn locked_validators_only_prevote_their_block() {
+        let signers = signers(4);
+        let validators = signers.iter().map(Signer::address).collect::<Vec<_>>();
+        let config = BftConfig {
+            validators: validators.clone(),
+            ..Default::default()
+        };
+        let mut others = signers;
+        let mut bft = Bft::new(config, others.remove(0)).unwrap();
+        bft.start(&status(0));
+
+        let prevote = |signer: &Signer, round: u64, block: Option<H256>| {
+            let mut vote = Vote::new();
+            vote.sender = signer.address().as_bytes().to_vec();
+            vote.proposal = block.map(|h| h.as_bytes().to_vec()).unwrap_or_default();
+            vote.signature = signer.sign(&vote_message(1, round, VoteType::Prevote, block));
+            BftMessage::Vote(SignedVote {
+                height: 1,
+                round,
+                kind: VoteType::Prevote,
+                vote,
+            })
+        };
+        let proposal = |signer: &Signer, round: u64, block: &Block| {
+            let mut proposal = Proposal::new();
+            proposal.block = Some(block.clone()).into();
+            proposal.height = 1;
+            proposal.round = round;
+            let mut signed = SignedProposal::new();
+            signed.signature = signer.sign(&proposal.write_to_bytes().unwrap());
+            signed.proposal = Some(proposal).into();
+            BftMessage::Proposal(signed)
+        };
+
+        // Round 0: the proposer and another validator prevote block a,
+        // which locks the engine on it.
+        let a = block(1, 0, validators[1]);
+        let a_hash = block_hash(a.header.get_or_default());
+        bft.on_message(proposal(&others[0], 0, &a));
+        bft.verified(a_hash, true);
+        bft.on_message(prevote(&others[0], 0, Some(a_hash)));
+        bft.on_message(prevote(&others[1], 0, Some(a_hash)));
+        assert_eq!(bft.step(), Step::Precommit);
+        bft.on_timeout((1, 0, Step::Precommit));
+        assert_eq!(bft.round(), 1);
+
+        // Round 1: another block is proposed without a newer lock.
+        let b = block(1, 1, validators[2]);
+        let b_hash = block_hash(b.header.get_or_default());
+        let actions = bft.on_message(proposal(&others[1], 1, &b));
+        bft.verified(b_hash, true);
+        let prevote_round_1 = bft.votes[&(1, VoteType::Prevote)][&validators[0]].0;
+        assert!(actions.contains(&Action::Verify(b)));
+        assert_eq!(prevote_round_1, None);
+    }
+
+    #[test]
+    fn checks_early_messages_before_keeping_them() {
+        let signers = signers(5);
+        let validators = signers[..4].iter().map(Signer::address).collect();
+        let config = BftConfig {
+            validators,
+            ..Default::default()
+        };
+        let mut bft = Bft::new(config, signers[0].clone()).unwrap();
+        bft.start(&status(0));
+
+        let prevote = |signer: &Signer, round: u64, signed_round: u64| {
+            let mut vote = Vote::new();
+            vote.sender = signer.address().as_bytes().to_vec();
+            vote.signature = signer.sign(&vote_message(2, signed_round, VoteType::Prevote, None));
+            BftMessage::Vote(SignedVote {
+                height: 2,
+                round,
+                kind: VoteType::Prevote,
+                vote,
+            })
+        };
+
+        bft.on_message(prevote(&signers[4], 0, 0));
+        bft.on_message(prevote(&signers[1], 0, 1));
+        assert!(bft.future.is_empty());
+
+        for round in 0..2 * MAX_FUTURE as u64 {
+            bft.on_message(prevote(&signers[1], round, round));
+        }
+        assert_eq!(bft.future.len(), MAX_FUTURE);
+    }
+}
@@ -0,0 +1,387 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ethereum_types::{H160, H256};
+use proto::communication::inner_message::Content;
+use proto::{
+    block_hash, Block, BlockHeader, BlockWithProof, ChainRequest, MessageBus, ProofType, RichStatus,
+};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// Effect of a consensus engine on the outside world.
+#[derive(Debug, PartialEq)]
+pub enum EngineAction<M, T> {
+    /// Send a message to a node, or to every other node if `to` is `None`.
+    Send { to: Option<H160>, msg: M },
+    /// Build a block on top of the chain and pass it to
+    /// `ConsensusEngine::propose`.
+    Propose,
+    /// Verify a block proposed by another node and pass the outcome to
+    /// `ConsensusEngine::verified`.
+    Verify(Block),
+    /// Add a sealed block whose proof doesn't make it final.
+    Import(BlockWithProof),
+    /// Add a block finalized by consensus.
+    Commit(BlockWithProof),
+    /// Call `ConsensusEngine::on_timeout` with `timer` after `after`.
+    Timeout { timer: T, after: Duration },
+    /// Store the state of the engine, passed to `ConsensusEngine::restore`
+    /// on restart, before carrying out the next actions.
+    Persist(Vec<u8>),
+}
+
+/// Actions of the engine `E`.
+pub type Actions<E> =
+    Vec<EngineAction<<E as ConsensusEngine>::Message, <E as ConsensusEngine>::Timer>>;
+
+/// Algorithm agreeing on the blocks of the chain.
+///
+/// Engines perform no I/O: each call returns the `EngineAction`s to carry
+/// out, which `EngineService` does against the chain.
+pub trait ConsensusEngine: Send {
+    /// Message exchanged with the engines of the other nodes.
+    type Message: Send + 'static;
+    /// Identifies an elapsed timeout.
+    type Timer: Ord + Send + 'static;
+
+    /// Type of the proofs the engine seals blocks with, `None` for blocks
+    /// without proof.
+    fn proof_type(&self) -> Option<ProofType>;
+
+    /// Restores the state last persisted by an `EngineAction::Persist`,
+    /// before the engine starts.
+    fn restore(&mut self, _state: &[u8]) -> result::Result<()> {
+        Ok(())
+    }
+
+    /// Starts with the chain at `status`.
+    fn start(&mut self, status: &RichStatus) -> Actions<Self>;
+
+    /// Proposes the block built for an `EngineAction::Propose`.
+    fn propose(&mut self, block: Block) -> Actions<Self>;
+
+    /// Checks the proof of `header`, sealed on top of the chain at `status`.
+    fn verify(&self, header: &BlockHeader, status: &RichStatus) -> result::Result<()>;
+
+    /// Records the outcome of an `EngineAction::Verify`.
+    fn verified(&mut self, _hash: H256, _valid: bool) -> Actions<Self> {
+        vec![]
+    }
+
+    fn on_message(&mut self, msg: Self::Message) -> Actions<Self>;
+
+    fn on_timeout(&mut self, timer: Self::Timer) -> Actions<Self>;
+
+    /// Notifies that a block the engine committed or imported was added,
+    /// moving the chain to `status`.
+    fn on_finalized(&mut self, _status: &RichStatus) -> Actions<Self> {
+        vec![]
+    }
+}
+
+/// Runs a `ConsensusEngine` against the chain, exchanging messages with the
+/// other nodes through `inbound` and `outbound`.
+///
+/// Every block is added once `ConsensusEngine::verify` accepts its seal
+/// against the status of its parent, be it sealed by the engine or relayed
+/// by the chain from another node through `blocks`.
+pub struct EngineService<E: ConsensusEngine> {
+    engine: E,
+    bus: Arc<MessageBus>,
+    inbound: Receiver<E::Message>,
+    /// Messages along with the node they are for, `None` for all of them.
+    outbound: Sender<(Option<H160>, E::Message)>,
+    /// Blocks received by the chain from other nodes.
+    blocks: Receiver<Content>,
+    timeouts: BinaryHeap<Reverse<(Instant, E::Timer)>>,
+}
+
+impl<E: ConsensusEngine> EngineService<E> {
+    pub fn new(
+        engine: E,
+        bus: Arc<MessageBus>,
+        inbound: Receiver<E::Message>,
+        outbound: Sender<(Option<H160>, E::Message)>,
+        blocks: Receiver<Content>,
+    ) -> Self {
+        Self {
+            engine,
+            bus,
+            inbound,
+            outbound,
+            blocks,
+            timeouts: BinaryHeap::new(),
+        }
+    }
+
+    pub async fn run(&mut self) -> result::Result<()> {
+        if let Some(state) = self.bus.chain(ChainRequest::ConsensusState).await? {
+            self.engine.restore(&state)?;
+        }
+        let status = self.bus.chain(ChainRequest::Status).await?;
+        let actions = self.engine.start(&status);
+        self.execute(actions).await?;
+
+        loop {
+            let deadline = self.timeouts.peek().map(|Reverse((at, _))| *at);
+            let actions = tokio::select! {
+                msg = self.inbound.recv() => match msg {
+                    Some(msg) => self.engine.on_message(msg),
+                    None => break,
+                },
+                Some(content) = self.blocks.recv() => peer_block(content),
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    let Reverse((_, timer)) = self.timeouts.pop().expect("a timeout is due");
+                    self.engine.on_timeout(timer)
+                }
+            };
+            self.execute(actions).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn execute(&mut self, actions: Actions<E>) -> result::Result<()> {
+        let mut queue = VecDeque::from(actions);
+        while let Some(action) = queue.pop_front() {
+            match action {
+                EngineAction::Send { to, msg } => {
+                    if self.outbound.send((to, msg)).await.is_err() {
+                        warn!("consensus messages are no longer sent");
+                    }
+                }
+                EngineAction::Propose => {
+                    let block = self.bus.chain(ChainRequest::ProposeBlock).await?;
+                    queue.extend(self.engine.propose(block));
+                }
+                EngineAction::Verify(block) => {
+                    let hash = block_hash(block.header.get_or_default());
+                    let verified = self
+                        .bus
+                        .chain(|reply| ChainRequest::VerifyBlock(block, reply))
+                        .await?;
+                    queue.extend(self.engine.verified(hash, verified.is_ok()));
+                }
+                EngineAction::Import(sealed) => {
+                    let mut block = sealed.blk.get_or_default().clone();
+                    block.header.mut_or_insert_default().proof = sealed.proof.clone();
+                    let height = block.header.height;
+                    let parent = parent_status(&self.bus, block.header.get_or_default()).await?;
+                    if let Err(e) = self.check(block.header.get_or_default(), parent) {
+                        warn!("rejected block {}: {}", height, e);
+                        continue;
+                    }
+                    // The chain logs why blocks are rejected.
+                    if let Ok(hash) = self
+                        .bus
+                        .chain(|reply| ChainRequest::SubmitBlock(block, reply))
+                        .await?
+                    {
+                        info!("imported block {} {:?}", height, hash);
+                        let status = self.bus.chain(ChainRequest::Status).await?;
+                        queue.extend(self.engine.on_finalized(&status));
+                    }
+                }
+                EngineAction::Commit(sealed) => {
+                    let height = sealed.blk.header.height;
+                    let mut header = sealed.blk.header.get_or_default().clone();
+                    header.proof = sealed.proof.clone();
+                    let parent = parent_status(&self.bus, &header).await?;
+                    if let Err(e) = self.check(&header, parent) {
+                        warn!("rejected block {}: {}", height, e);
+                        continue;
+                    }
+                    match self
+                        .bus
+                        .chain(|reply| ChainRequest::CommitBlock(sealed, reply))
+                        .await?
+                    {
+                        Ok(hash) => {
+                            info!("committed block {} {:?}", height, hash);
+                            let status = self.bus.chain(ChainRequest::Status).await?;
+                            queue.extend(self.engine.on_finalized(&status));
+                        }
+                        Err(e) => warn!("failed to commit block {}: {}", height, e),
+                    }
+                }
+                EngineAction::Timeout { timer, after } => {
+                    self.timeouts.push(Reverse((Instant::now() + after, timer)))
+                }
+                EngineAction::Persist(state) => {
+                    self.bus
+                        .chain(|reply| ChainRequest::SetConsensusState(state, reply))
+                        .await?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the seal of `header` against the status of the chain as of
+    /// its parent.
+    ///
+    /// Returns why the block is rejected.
+    fn check(
+        &self,
+        header: &BlockHeader,
+        parent: Result<RichStatus, String>,
+    ) -> Result<(), String> {
+        self.engine
+            .verify(header, &parent?)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Status of the chain as of the parent of `header`, or why it is unknown.
+async fn parent_status(
+    bus: &MessageBus,
+    header: &BlockHeader,
+) -> result::Result<Result<RichStatus, String>> {
+    if header.prevhash.len() != 32 {
+        return Ok(Err("invalid parent hash".to_string()));
+    }
+    let parent = H256::from_slice(&header.prevhash);
+    let status = bus
+        .chain(|reply| ChainRequest::BlockStatus(parent, reply))
+        .await?;
+    Ok(status.ok_or_else(|| format!("unknown parent {:?}", parent)))
+}
+
+/// Adds a block of another node: final if it comes with a proof, as any
+/// block otherwise.
+fn peer_block<M, T>(content: Content) -> Vec<EngineAction<M, T>> {
+    match content {
+        Content::Block(block) => {
+            let mut sealed = BlockWithProof::new();
+            sealed.blk = Some(block).into();
+            vec![EngineAction::Import(sealed)]
+        }
+        Content::BlockWithProof(sealed) => vec![EngineAction::Commit(sealed)],
+        _ => vec![],
+    }
+}
+
+/// Validators listed in `status`.
+pub(crate) fn validators(status: &RichStatus) -> result::Result<Vec<H160>> {
+    status
+        .validators
+        .iter()
+        .map(|validator| match validator.len() {
+            20 => Ok(H160::from_slice(validator)),
+            len => Err(anyhow::anyhow!("invalid validator length {}", len)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InstantSeal;
+    use proto::Message;
+    use tokio::sync::mpsc::channel;
+
+    #[tokio::test]
+    async fn commits_the_blocks_of_the_engine() {
+        let (chain_sender, mut chain) = channel(16);
+        let bus = Arc::new(MessageBus {
+            jsonrpc_sender: channel(1).0,
+            auth_sender: channel(1).0,
+            chain_sender,
+        });
+        let engine = InstantSeal::new(Duration::from_millis(10));
+        let (_requests, receiver) = channel(1);
+        let (_blocks, blocks) = channel(1);
+        let mut service = EngineService::new(engine, bus, receiver, channel(1).0, blocks);
+        tokio::spawn(async move { service.run().await });
+
+        let mut height = 0;
+        while height < 2 {
+            match chain.recv().await.unwrap() {
+                Message::Request(ChainRequest::ConsensusState(reply)) => {
+                    let _ = reply.send(None);
+                }
+                Message::Request(ChainRequest::Status(reply)) => {
+                    let mut status = RichStatus::new();
+                    status.height = height;
+                    let _ = reply.send(status);
+                }
+                Message::Request(ChainRequest::BlockStatus(_, reply)) => {
+                    let mut status = RichStatus::new();
+                    status.height = height;
+                    let _ = reply.send(Some(status));
+                }
+                Message::Request(ChainRequest::ProposeBlock(reply)) => {
+                    let mut block = Block::new();
+                    let header = block.header.mut_or_insert_default();
+                    header.height = height + 1;
+                    header.prevhash = H256::repeat_byte(height as u8).as_bytes().to_vec();
+                    let _ = reply.send(block);
+                }
+                Message::Request(ChainRequest::CommitBlock(sealed, reply)) => {
+                    assert_eq!(sealed.blk.header.height, height + 1);
+                    assert!(sealed.proof.is_none());
+                    height += 1;
+                    let _ = reply.send(Ok(H256::repeat_byte(height as u8)));
+                }
+                msg => panic!("unexpected {:?}", msg),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_the_seal_of_peer_blocks() {
+        let (chain_sender, mut chain) = channel(16);
+        let bus = Arc::new(MessageBus {
+            jsonrpc_sender: channel(1).0,
+            auth_sender: channel(1).0,
+            chain_sender,
+        });
+        let (_requests, receiver) = channel(1);
+        let (peers, blocks) = channel(4);
+        let mut service =
+            EngineService::new(InstantSeal::manual(), bus, receiver, channel(1).0, blocks);
+        tokio::spawn(async move { service.run().await });
+
+        let head = H256::repeat_byte(1);
+        let block = |height: u64, parent: H256| {
+            let mut block = Block::new();
+            let header = block.header.mut_or_insert_default();
+            header.height = height;
+            header.prevhash = parent.as_bytes().to_vec();
+            block
+        };
+        // Instant seal blocks come without proof.
+        let mut sealed = BlockWithProof::new();
+        sealed.blk = Some(block(1, head)).into();
+        sealed.proof = Some(proto::Proof::new()).into();
+        peers.send(Content::BlockWithProof(sealed)).await.unwrap();
+        peers
+            .send(Content::Block(block(2, H256::repeat_byte(2))))
+            .await
+            .unwrap();
+        peers.send(Content::Block(block(3, head))).await.unwrap();
+
+        loop {
+            match chain.recv().await.unwrap() {
+                Message::Request(ChainRequest::ConsensusState(reply)) => {
+                    let _ = reply.send(None);
+                }
+                Message::Request(ChainRequest::Status(reply)) => {
+                    let _ = reply.send(RichStatus::new());
+                }
+                Message::Request(ChainRequest::BlockStatus(hash, reply)) => {
+                    let _ = reply.send(Some(RichStatus::new()).filter(|_| hash == head));
+                }
+                Message::Request(ChainRequest::SubmitBlock(block, _)) => {
+                    assert_eq!(block.header.height, 3);
+                    break;
+                }
+                msg => panic!("unexpected {:?}", msg),
+            }
+        }
+    }
+}
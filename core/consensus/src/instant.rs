@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use ethereum_types::H256;
+use proto::{block_hash, Block, BlockHeader, BlockWithProof, ProofType, Reply, RichStatus};
+
+use crate::engine::{Actions, ConsensusEngine, EngineAction};
+
+/// Request to an `InstantSeal` engine to seal a block of the ready
+/// transactions.
+#[derive(Debug)]
+pub struct SealRequest {
+    /// Seal a block even if no transaction is ready.
+    pub create_empty: bool,
+    /// Receives the hash of the sealed block, or why none was sealed.
+    pub reply: Option<Reply<Result<H256, String>>>,
+}
+
+/// Engine of a single node sealing its own blocks, final right away and
+/// without proof, every `interval` and whenever requested.
+pub struct InstantSeal {
+    interval: Option<Duration>,
+    /// Request the block being proposed is sealed for.
+    request: Option<SealRequest>,
+}
+
+impl InstantSeal {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval: Some(interval),
+            request: None,
+        }
+    }
+
+    /// Engine sealing blocks only when requested.
+    pub fn manual() -> Self {
+        Self {
+            interval: None,
+            request: None,
+        }
+    }
+
+    fn schedule(&self) -> Actions<Self> {
+        self.interval
+            .map(|after| EngineAction::Timeout { timer: (), after })
+            .into_iter()
+            .collect()
+    }
+}
+
+impl ConsensusEngine for InstantSeal {
+    type Message = SealRequest;
+    type Timer = ();
+
+    fn proof_type(&self) -> Option<ProofType> {
+        None
+    }
+
+    fn start(&mut self, _status: &RichStatus) -> Actions<Self> {
+        self.schedule()
+    }
+
+    fn propose(&mut self, block: Block) -> Actions<Self> {
+        let (create_empty, reply) = match self.request.take() {
+            Some(request) => (request.create_empty, request.reply),
+            None => (true, None),
+        };
+        let result = if create_empty || !block.body.transactions.is_empty() {
+            Ok(block_hash(block.header.get_or_default()))
+        } else {
+            Err("no transactions to seal".to_string())
+        };
+        let sealed = result.is_ok();
+        if let Some(reply) = reply {
+            let _ = reply.send(result);
+        }
+        if !sealed {
+            return vec![];
+        }
+
+        let mut sealed = BlockWithProof::new();
+        sealed.blk = Some(block).into();
+        vec![EngineAction::Commit(sealed)]
+    }
+
+    fn verify(&self, header: &BlockHeader, _status: &RichStatus) -> result::Result<()> {
+        match header.proof.as_ref() {
+            Some(_) => Err(anyhow!("unexpected proof")),
+            None => Ok(()),
+        }
+    }
+
+    /// Actions are carried out before the next message is handled, so the
+    /// block proposed is the one of `request`.
+    fn on_message(&mut self, request: SealRequest) -> Actions<Self> {
+        self.request = Some(request);
+        vec![EngineAction::Propose]
+    }
+
+    fn on_timeout(&mut self, _timer: ()) -> Actions<Self> {
+        let mut actions = vec![EngineAction::Propose];
+        actions.extend(self.schedule());
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::oneshot;
+
+    fn request(create_empty: bool) -> (SealRequest, oneshot::Receiver<Result<H256, String>>) {
+        let (reply, response) = oneshot::channel();
+        let request = SealRequest {
+            create_empty,
+            reply: Some(reply),
+        };
+        (request, response)
+    }
+
+    #[test]
+    fn seals_requested_blocks() {
+        let mut engine = InstantSeal::manual();
+        assert!(engine.start(&RichStatus::new()).is_empty());
+
+        let (seal, mut response) = request(false);
+        assert!(matches!(
+            engine.on_message(seal).as_slice(),
+            [EngineAction::Propose]
+        ));
+        assert!(engine.propose(Block::new()).is_empty());
+        assert!(response.try_recv().unwrap().is_err());
+
+        let (seal, mut response) = request(true);
+        engine.on_message(seal);
+        let mut block = Block::new();
+        block.header.mut_or_insert_default().height = 1;
+        let hash = block_hash(block.header.get_or_default());
+        match engine.propose(block).as_slice() {
+            [EngineAction::Commit(sealed)] => assert_eq!(sealed.blk.header.height, 1),
+            actions => panic!("unexpected {:?}", actions),
+        }
+        assert_eq!(response.try_recv().unwrap(), Ok(hash));
+    }
+}
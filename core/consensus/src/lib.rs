@@ -0,0 +1,13 @@
+mod aura;
+mod bft;
+mod engine;
+mod instant;
+mod raft;
+mod signer;
+
+pub use aura::{slot, slot_owner, Aura, AuraProof};
+pub use bft::{Bft, BftConfig, BftMessage, BftProof, BftTimer, SignedVote, Step, VoteType};
+pub use engine::{Actions, ConsensusEngine, EngineAction, EngineService};
+pub use instant::{InstantSeal, SealRequest};
+pub use raft::{Entry, Raft, RaftConfig, RaftMessage, RaftProof, RaftTimer, Role};
+pub use signer::{recover, Signer};
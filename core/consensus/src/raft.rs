@@ -0,0 +1,1033 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use codec::{Decode, Encode};
+use ethereum_types::H160;
+use hashing::keccak_256;
+use proto::{Block, BlockHeader, BlockWithProof, Proof, ProofType, RichStatus};
+use tracing::{debug, info};
+
+use crate::engine::{Actions, ConsensusEngine, EngineAction};
+
+/// Most entries sent in one `AppendEntries`.
+const MAX_ENTRIES: usize = 64;
+/// Committed entries kept to catch up lagging followers.
+const RETAINED_ENTRIES: u64 = 1024;
+
+/// Cluster and timing of the Raft engine.
+#[derive(Clone, Debug)]
+pub struct RaftConfig {
+    /// Nodes of the cluster, including this one.
+    pub peers: Vec<H160>,
+    /// Shortest time without hearing from a leader before starting an
+    /// election. Each node waits up to twice as long, so that one usually
+    /// starts before the others.
+    pub election: Duration,
+    /// Time between two messages of the leader to each follower.
+    pub heartbeat: Duration,
+    /// Time between two blocks produced by the leader.
+    pub interval: Duration,
+}
+
+impl Default for RaftConfig {
+    fn default() -> Self {
+        Self {
+            peers: vec![],
+            election: Duration::from_secs(1),
+            heartbeat: Duration::from_millis(200),
+            interval: Duration::from_secs(3),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+/// Entry of the replicated log: a block, whose height is its index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub term: u64,
+    /// Term of the leader that proposed the block, earlier than `term` for
+    /// a block taken over by a later leader.
+    pub proposed: u64,
+    pub block: Block,
+}
+
+impl Entry {
+    fn index(&self) -> u64 {
+        self.block.header.height
+    }
+}
+
+/// Message exchanged by the nodes of the cluster.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RaftMessage {
+    RequestVote {
+        term: u64,
+        candidate: H160,
+        last_index: u64,
+        last_term: u64,
+    },
+    Vote {
+        term: u64,
+        voter: H160,
+        granted: bool,
+    },
+    AppendEntries {
+        term: u64,
+        leader: H160,
+        prev_index: u64,
+        prev_term: u64,
+        entries: Vec<Entry>,
+        commit: u64,
+    },
+    AppendResponse {
+        term: u64,
+        follower: H160,
+        success: bool,
+        /// Last index of the follower's log.
+        last_index: u64,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RaftTimer {
+    Election,
+    Heartbeat,
+    Produce,
+}
+
+/// Timer along with the id telling whether it was reset since it was set.
+type Timeout = (RaftTimer, u64);
+type Action = EngineAction<RaftMessage, Timeout>;
+
+/// Content of a `Proof` of type `Raft`: the log index a block was committed
+/// at, and the term of the leader that proposed it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct RaftProof {
+    pub term: u64,
+    pub index: u64,
+}
+
+/// Term and vote of a node, persisted before it answers another node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode)]
+struct HardState {
+    term: u64,
+    voted_for: Option<H160>,
+}
+
+impl RaftProof {
+    /// Decodes the proof of `header`, checking it is at the block's height
+    /// and that the block was proposed by the leader of its term among
+    /// `leaders`.
+    pub fn verify(header: &BlockHeader, leaders: &HashMap<u64, H160>) -> result::Result<RaftProof> {
+        let proof = header.proof.get_or_default();
+        if proof.type_.enum_value() != Ok(ProofType::Raft) {
+            return Err(anyhow!("not a Raft proof"));
+        }
+        let decoded = RaftProof::decode(&mut &proof.content[..])
+            .map_err(|e| anyhow!("invalid Raft proof: {}", e))?;
+        if decoded.index != header.height {
+            return Err(anyhow!("proof of index {}", decoded.index));
+        }
+        match leaders.get(&decoded.term) {
+            Some(leader) if header.proposer == leader.as_bytes() => Ok(decoded),
+            Some(leader) => Err(anyhow!("term {} is led by {:?}", decoded.term, leader)),
+            None => Err(anyhow!("unknown leader of term {}", decoded.term)),
+        }
+    }
+}
+
+/// Raft state machine of one node.
+///
+/// The leader elected for a term produces blocks and replicates them to
+/// the followers, and a block is committed once a majority of the nodes
+/// stored it. The log index of a block is its height, so the leader only
+/// proposes a block once the previous one is committed. A leader elected
+/// with an uncommitted block from an earlier term replicates it again under
+/// its own term, since only entries of the current term may be committed by
+/// counting replicas.
+///
+/// Blocks below the head of the chain at start are committed already and
+/// match on every node. The term and vote are persisted before the node
+/// answers another one, so that a node restarting never votes twice in a
+/// term; it joins as a follower and learns the leader from the others.
+pub struct Raft {
+    config: RaftConfig,
+    id: H160,
+    role: Role,
+    term: u64,
+    voted_for: Option<H160>,
+    leader: Option<H160>,
+    /// Leader of the terms of the blocks in the log, as the leaders told.
+    leaders: HashMap<u64, H160>,
+    /// Index of the last entry before `log`, committed.
+    base: u64,
+    base_term: u64,
+    log: Vec<Entry>,
+    commit: u64,
+    /// Index of the last block handed to the chain.
+    applied: u64,
+    votes: HashSet<H160>,
+    /// Next index to send to each follower.
+    next: HashMap<H160, u64>,
+    /// Highest index known to be replicated on each follower.
+    matched: HashMap<H160, u64>,
+    proposing: bool,
+    /// Id of the armed timers.
+    timers: HashMap<RaftTimer, u64>,
+    timer_id: u64,
+    actions: Vec<Action>,
+}
+
+impl Raft {
+    pub fn new(config: RaftConfig, id: H160) -> result::Result<Self> {
+        if !config.peers.contains(&id) {
+            return Err(anyhow!("{:?} is not a peer", id));
+        }
+        Ok(Self {
+            config,
+            id,
+            role: Role::Follower,
+            term: 0,
+            voted_for: None,
+            leader: None,
+            leaders: HashMap::new(),
+            base: 0,
+            base_term: 0,
+            log: vec![],
+            commit: 0,
+            applied: 0,
+            votes: HashSet::new(),
+            next: HashMap::new(),
+            matched: HashMap::new(),
+            proposing: false,
+            timers: HashMap::new(),
+            timer_id: 0,
+            actions: vec![],
+        })
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn term(&self) -> u64 {
+        self.term
+    }
+
+    pub fn leader(&self) -> Option<H160> {
+        self.leader
+    }
+
+    /// Index of the last committed block.
+    pub fn commit_index(&self) -> u64 {
+        self.commit
+    }
+
+    fn quorum(&self) -> usize {
+        self.config.peers.len() / 2 + 1
+    }
+
+    fn others(&self) -> Vec<H160> {
+        let id = self.id;
+        self.config
+            .peers
+            .iter()
+            .copied()
+            .filter(|peer| *peer != id)
+            .collect()
+    }
+
+    fn last_index(&self) -> u64 {
+        self.base + self.log.len() as u64
+    }
+
+    fn last_term(&self) -> u64 {
+        self.log.last().map_or(self.base_term, |entry| entry.term)
+    }
+
+    /// Term of the entry at `index`, `None` if it is committed and no longer
+    /// kept or beyond the log.
+    fn term_at(&self, index: u64) -> Option<u64> {
+        if index == self.base {
+            return Some(self.base_term);
+        }
+        let offset = index.checked_sub(self.base + 1)?;
+        self.log.get(offset as usize).map(|entry| entry.term)
+    }
+
+    /// Arms `timer`, replacing its previous deadline.
+    fn arm(&mut self, timer: RaftTimer) {
+        let after = match timer {
+            RaftTimer::Election => {
+                // Spread the elections of the nodes pseudo-randomly.
+                let seed = keccak_256(&(self.id, self.term).encode());
+                let spread = u64::from_be_bytes(seed[..8].try_into().unwrap());
+                let election = self.config.election.as_millis() as u64;
+                self.config.election + Duration::from_millis(spread % election.max(1))
+            }
+            RaftTimer::Heartbeat => self.config.heartbeat,
+            RaftTimer::Produce => self.config.interval,
+        };
+        self.timer_id += 1;
+        self.timers.insert(timer, self.timer_id);
+        self.actions.push(Action::Timeout {
+            timer: (timer, self.timer_id),
+            after,
+        });
+    }
+
+    /// Persists the term and vote, ahead of the messages sent after.
+    fn persist(&mut self) {
+        let state = HardState {
+            term: self.term,
+            voted_for: self.voted_for,
+        };
+        self.actions.push(Action::Persist(state.encode()));
+    }
+
+    fn send(&mut self, to: H160, msg: RaftMessage) {
+        self.actions.push(Action::Send { to: Some(to), msg });
+    }
+
+    fn campaign(&mut self) {
+        self.term += 1;
+        self.role = Role::Candidate;
+        self.voted_for = Some(self.id);
+        self.leader = None;
+        self.votes = HashSet::from([self.id]);
+        self.persist();
+        debug!("campaigning for term {}", self.term);
+        if self.votes.len() >= self.quorum() {
+            self.become_leader();
+            return;
+        }
+
+        for peer in self.others() {
+            self.send(
+                peer,
+                RaftMessage::RequestVote {
+                    term: self.term,
+                    candidate: self.id,
+                    last_index: self.last_index(),
+                    last_term: self.last_term(),
+                },
+            );
+        }
+        self.arm(RaftTimer::Election);
+    }
+
+    fn become_follower(&mut self, term: u64, leader: Option<H160>) {
+        if term > self.term {
+            self.term = term;
+            self.voted_for = None;
+            self.persist();
+        }
+        if self.role == Role::Leader {
+            info!("stepped down in term {}", self.term);
+        }
+        self.role = Role::Follower;
+        self.leader = leader;
+        self.proposing = false;
+        self.timers.remove(&RaftTimer::Heartbeat);
+        self.timers.remove(&RaftTimer::Produce);
+        self.arm(RaftTimer::Election);
+    }
+
+    fn become_leader(&mut self) {
+        info!("elected leader of term {}", self.term);
+        self.role = Role::Leader;
+        self.leader = Some(self.id);
+        self.leaders.insert(self.term, self.id);
+        self.timers.remove(&RaftTimer::Election);
+        let next = self.last_index() + 1;
+        for peer in self.others() {
+            self.next.insert(peer, next);
+            self.matched.insert(peer, 0);
+        }
+        if self.last_index() > self.commit {
+            // Take over the uncommitted block so that it can be committed.
+            let term = self.term;
+            if let Some(entry) = self.log.last_mut() {
+                entry.term = term;
+            }
+        }
+
+        self.replicate();
+        self.advance_commit();
+        self.arm(RaftTimer::Heartbeat);
+        self.arm(RaftTimer::Produce);
+    }
+
+    fn on_request_vote(&mut self, term: u64, candidate: H160, last_index: u64, last_term: u64) {
+        let up_to_date = (last_term, last_index) >= (self.last_term(), self.last_index());
+        let granted = term == self.term
+            && self.voted_for.is_none_or(|voted| voted == candidate)
+            && up_to_date;
+        if granted {
+            self.voted_for = Some(candidate);
+            self.persist();
+            self.arm(RaftTimer::Election);
+        }
+        self.send(
+            candidate,
+            RaftMessage::Vote {
+                term: self.term,
+                voter: self.id,
+                granted,
+            },
+        );
+    }
+
+    fn on_append(
+        &mut self,
+        term: u64,
+        leader: H160,
+        prev_index: u64,
+        prev_term: u64,
+        entries: Vec<Entry>,
+        commit: u64,
+    ) {
+        if term < self.term {
+            self.respond(leader, false);
+            return;
+        }
+        if self.role != Role::Follower || self.leader != Some(leader) {
+            self.become_follower(term, Some(leader));
+        } else {
+            self.arm(RaftTimer::Election);
+        }
+        self.leaders.insert(term, leader);
+
+        // Committed entries match on every node, whatever their term.
+        let matches = prev_index <= self.base || self.term_at(prev_index) == Some(prev_term);
+        if !matches {
+            self.respond(leader, false);
+            return;
+        }
+
+        let last_new = prev_index + entries.len() as u64;
+        for entry in entries {
+            let index = entry.index();
+            if index <= self.base {
+                continue;
+            }
+            match self.term_at(index) {
+                Some(term) if term == entry.term => continue,
+                Some(_) => self.log.truncate((index - self.base - 1) as usize),
+                None => {}
+            }
+            if index == self.last_index() + 1 {
+                // The leader vouches for the proposers of earlier terms.
+                if entry.block.header.proposer.len() == 20 {
+                    self.leaders
+                        .entry(entry.proposed)
+                        .or_insert_with(|| H160::from_slice(&entry.block.header.proposer));
+                }
+                self.log.push(entry);
+            }
+        }
+
+        if commit > self.commit {
+            self.commit = commit.min(last_new).min(self.last_index());
+            self.apply();
+        }
+        self.respond(leader, true);
+    }
+
+    fn respond(&mut self, leader: H160, success: bool) {
+        self.send(
+            leader,
+            RaftMessage::AppendResponse {
+                term: self.term,
+                follower: self.id,
+                success,
+                last_index: self.last_index(),
+            },
+        );
+    }
+
+    fn on_append_response(&mut self, term: u64, follower: H160, success: bool, last_index: u64) {
+        if self.role != Role::Leader || term != self.term {
+            return;
+        }
+        let next = self.next.get(&follower).copied().unwrap_or(1);
+        if success {
+            let matched = self.matched.entry(follower).or_default();
+            *matched = (*matched).max(last_index);
+            self.next.insert(follower, *matched + 1);
+            self.advance_commit();
+        } else {
+            let retry = (next - 1).min(last_index + 1).max(self.base + 1);
+            self.next.insert(follower, retry);
+            // Lagging further behind than the log retained is left to the
+            // block synchronization of the chain.
+            if retry < next {
+                self.append_to(follower);
+            }
+        }
+    }
+
+    fn replicate(&mut self) {
+        for peer in self.others() {
+            self.append_to(peer);
+        }
+    }
+
+    fn append_to(&mut self, peer: H160) {
+        let next = self
+            .next
+            .get(&peer)
+            .copied()
+            .unwrap_or(self.base + 1)
+            .max(self.base + 1);
+        let prev_index = next - 1;
+        let prev_term = self.term_at(prev_index).unwrap_or_default();
+        let start = (next - self.base - 1) as usize;
+        let entries = self
+            .log
+            .iter()
+            .skip(start)
+            .take(MAX_ENTRIES)
+            .cloned()
+            .collect();
+        self.send(
+            peer,
+            RaftMessage::AppendEntries {
+                term: self.term,
+                leader: self.id,
+                prev_index,
+                prev_term,
+                entries,
+                commit: self.commit,
+            },
+        );
+    }
+
+    /// Commits the entries of the current term stored on a majority.
+    fn advance_commit(&mut self) {
+        let quorum = self.quorum();
+        let committed = (self.commit + 1..=self.last_index()).rev().find(|index| {
+            let replicas = 1 + self.matched.values().filter(|m| **m >= *index).count();
+            self.term_at(*index) == Some(self.term) && replicas >= quorum
+        });
+        if let Some(index) = committed {
+            self.commit = index;
+            self.apply();
+        }
+    }
+
+    /// Hands the newly committed blocks to the chain and drops the entries
+    /// no longer needed.
+    fn apply(&mut self) {
+        for index in self.applied + 1..=self.commit {
+            let entry = &self.log[(index - self.base - 1) as usize];
+            let content = RaftProof {
+                term: entry.proposed,
+                index,
+            };
+            let mut proof = Proof::new();
+            proof.content = content.encode();
+            proof.type_ = ProofType::Raft.into();
+            let mut sealed = BlockWithProof::new();
+            sealed.blk = Some(entry.block.clone()).into();
+            sealed.proof = Some(proof).into();
+            self.actions.push(Action::Commit(sealed));
+        }
+        self.applied = self.commit;
+
+        let keep = self.commit.saturating_sub(RETAINED_ENTRIES);
+        if keep > self.base {
+            let dropped = self.log.drain(..(keep - self.base) as usize);
+            self.base_term = dropped.last().map_or(self.base_term, |entry| entry.term);
+            self.base = keep;
+            let oldest = self.log.iter().map(|entry| entry.proposed).min();
+            let oldest = oldest.unwrap_or(self.term).min(self.term);
+            self.leaders.retain(|term, _| *term >= oldest);
+        }
+    }
+}
+
+impl ConsensusEngine for Raft {
+    type Message = RaftMessage;
+    /// Timer with the id it was armed with.
+    type Timer = Timeout;
+
+    fn proof_type(&self) -> Option<ProofType> {
+        Some(ProofType::Raft)
+    }
+
+    fn restore(&mut self, state: &[u8]) -> result::Result<()> {
+        let state =
+            HardState::decode(&mut &state[..]).map_err(|e| anyhow!("invalid Raft state: {}", e))?;
+        self.term = state.term;
+        self.voted_for = state.voted_for;
+        Ok(())
+    }
+
+    /// Joins the cluster with the chain at `status`.
+    fn start(&mut self, status: &RichStatus) -> Actions<Self> {
+        self.base = status.height;
+        self.commit = status.height;
+        self.applied = status.height;
+        if self.quorum() == 1 {
+            self.campaign();
+        } else {
+            self.arm(RaftTimer::Election);
+        }
+        mem::take(&mut self.actions)
+    }
+
+    /// Appends the block and replicates it, if still leading.
+    fn propose(&mut self, block: Block) -> Actions<Self> {
+        if self.role == Role::Leader
+            && self.proposing
+            && block.header.height == self.last_index() + 1
+        {
+            let term = self.term;
+            self.log.push(Entry {
+                term,
+                proposed: term,
+                block,
+            });
+            self.replicate();
+            self.advance_commit();
+        }
+        self.proposing = false;
+        mem::take(&mut self.actions)
+    }
+
+    fn verify(&self, header: &BlockHeader, _status: &RichStatus) -> result::Result<()> {
+        // The proposer is checked against the leaders learned since the node
+        // started. They are not persisted, and `status` records no term to
+        // recover them from, so after a restart blocks of earlier terms are
+        // rejected; the node gets them from the log of the current leader.
+        RaftProof::verify(header, &self.leaders)?;
+        Ok(())
+    }
+
+    fn on_message(&mut self, msg: RaftMessage) -> Actions<Self> {
+        let (term, sender) = match &msg {
+            RaftMessage::RequestVote {
+                term, candidate, ..
+            } => (*term, candidate),
+            RaftMessage::Vote { term, voter, .. } => (*term, voter),
+            RaftMessage::AppendEntries { term, leader, .. } => (*term, leader),
+            RaftMessage::AppendResponse { term, follower, .. } => (*term, follower),
+        };
+        if !self.config.peers.contains(sender) {
+            debug!("dropped message of non-peer {:?}", sender);
+            return vec![];
+        }
+        if term > self.term {
+            self.become_follower(term, None);
+        }
+
+        match msg {
+            RaftMessage::RequestVote {
+                term,
+                candidate,
+                last_index,
+                last_term,
+            } => self.on_request_vote(term, candidate, last_index, last_term),
+            RaftMessage::Vote {
+                term,
+                voter,
+                granted,
+            } => {
+                if self.role == Role::Candidate && term == self.term && granted {
+                    self.votes.insert(voter);
+                    if self.votes.len() >= self.quorum() {
+                        self.become_leader();
+                    }
+                }
+            }
+            RaftMessage::AppendEntries {
+                term,
+                leader,
+                prev_index,
+                prev_term,
+                entries,
+                commit,
+            } => self.on_append(term, leader, prev_index, prev_term, entries, commit),
+            RaftMessage::AppendResponse {
+                term,
+                follower,
+                success,
+                last_index,
+            } => self.on_append_response(term, follower, success, last_index),
+        }
+        mem::take(&mut self.actions)
+    }
+
+    fn on_timeout(&mut self, (timer, id): Timeout) -> Actions<Self> {
+        if self.timers.get(&timer) == Some(&id) {
+            self.timers.remove(&timer);
+            match timer {
+                RaftTimer::Election => self.campaign(),
+                RaftTimer::Heartbeat => {
+                    self.replicate();
+                    self.arm(RaftTimer::Heartbeat);
+                }
+                RaftTimer::Produce => {
+                    if self.last_index() == self.commit && !self.proposing {
+                        self.proposing = true;
+                        self.actions.push(Action::Propose);
+                    }
+                    self.arm(RaftTimer::Produce);
+                }
+            }
+        }
+        mem::take(&mut self.actions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Reverse;
+    use std::collections::{BinaryHeap, VecDeque};
+
+    /// Nodes exchanging messages instantly on a simulated clock, except for
+    /// the offline ones.
+    struct Cluster {
+        nodes: Vec<Raft>,
+        offline: HashSet<usize>,
+        now: Duration,
+        queue: VecDeque<(usize, Action)>,
+        timers: BinaryHeap<Reverse<(Duration, usize, Timeout)>>,
+        /// Blocks committed by each node, in order.
+        chains: Vec<Vec<BlockWithProof>>,
+        /// State each node persisted last.
+        states: Vec<Option<Vec<u8>>>,
+    }
+
+    impl Cluster {
+        fn new(count: u8) -> Self {
+            let peers = (1..=count).map(H160::repeat_byte).collect::<Vec<_>>();
+            let config = RaftConfig {
+                peers: peers.clone(),
+                ..Default::default()
+            };
+            let mut cluster = Self {
+                nodes: peers
+                    .iter()
+                    .map(|id| Raft::new(config.clone(), *id).unwrap())
+                    .collect(),
+                offline: HashSet::new(),
+                now: Duration::ZERO,
+                queue: VecDeque::new(),
+                timers: BinaryHeap::new(),
+                chains: vec![vec![]; count as usize],
+                states: vec![None; count as usize],
+            };
+            for node in 0..count as usize {
+                let actions = cluster.nodes[node].start(&RichStatus::new());
+                cluster.push(node, actions);
+            }
+            cluster
+        }
+
+        fn push(&mut self, node: usize, actions: Vec<Action>) {
+            self.queue
+                .extend(actions.into_iter().map(|action| (node, action)));
+        }
+
+        fn leader(&self) -> Option<usize> {
+            (0..self.nodes.len()).find(|node| {
+                !self.offline.contains(node) && self.nodes[*node].role() == Role::Leader
+            })
+        }
+
+        /// Runs the cluster for `duration` of simulated time.
+        fn run(&mut self, duration: Duration) {
+            let until = self.now + duration;
+            loop {
+                if let Some((node, action)) = self.queue.pop_front() {
+                    if !self.offline.contains(&node) {
+                        self.execute(node, action);
+                    }
+                    continue;
+                }
+                match self.timers.peek() {
+                    Some(Reverse((at, ..))) if *at <= until => {
+                        let Reverse((at, node, timer)) = self.timers.pop().unwrap();
+                        self.now = at;
+                        if !self.offline.contains(&node) {
+                            let actions = self.nodes[node].on_timeout(timer);
+                            self.push(node, actions);
+                        }
+                    }
+                    _ => break,
+                }
+            }
+            self.now = until;
+        }
+
+        fn execute(&mut self, node: usize, action: Action) {
+            match action {
+                Action::Send { to, msg } => {
+                    let to = to.unwrap().as_bytes()[0] as usize - 1;
+                    if !self.offline.contains(&to) {
+                        let actions = self.nodes[to].on_message(msg);
+                        self.push(to, actions);
+                    }
+                }
+                Action::Propose => {
+                    let mut header = BlockHeader::new();
+                    header.height = self.nodes[node].commit_index() + 1;
+                    header.timestamp = self.nodes[node].term();
+                    header.proposer = vec![node as u8 + 1; 20];
+                    let mut block = Block::new();
+                    block.header = Some(header).into();
+                    let actions = self.nodes[node].propose(block);
+                    self.push(node, actions);
+                }
+                Action::Commit(sealed) => self.chains[node].push(sealed),
+                Action::Timeout { timer, after } => {
+                    self.timers.push(Reverse((self.now + after, node, timer)));
+                }
+                Action::Persist(state) => self.states[node] = Some(state),
+                Action::Verify(_) | Action::Import(_) => unreachable!("Raft trusts the leader"),
+            }
+        }
+
+        /// Blocks committed by `node`, with the term they were proposed in,
+        /// checked by the node.
+        fn chain(&self, node: usize) -> Vec<(u64, Vec<u8>)> {
+            self.chains[node]
+                .iter()
+                .map(|sealed| {
+                    let mut header = sealed.blk.header.get_or_default().clone();
+                    header.proof = sealed.proof.clone();
+                    self.nodes[node]
+                        .verify(&header, &RichStatus::new())
+                        .unwrap();
+                    let proof = RaftProof::verify(&header, &self.nodes[node].leaders).unwrap();
+                    (proof.term, header.proposer)
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn single_node_leads_and_commits() {
+        let mut cluster = Cluster::new(1);
+        cluster.run(Duration::from_secs(10));
+        assert_eq!(cluster.leader(), Some(0));
+        assert_eq!(cluster.nodes[0].commit_index(), 3);
+        assert_eq!(cluster.chain(0).len(), 3);
+    }
+
+    #[test]
+    fn followers_commit_the_blocks_of_the_leader() {
+        let mut cluster = Cluster::new(3);
+        cluster.run(Duration::from_secs(10));
+        let leader = cluster.leader().unwrap();
+        let term = cluster.nodes[leader].term();
+        for node in &cluster.nodes {
+            assert_eq!(node.term(), term);
+            assert_eq!(node.leader(), Some(H160::repeat_byte(leader as u8 + 1)));
+        }
+
+        let chain = cluster.chain(leader);
+        assert!(chain.len() >= 2);
+        for node in 0..3 {
+            // Followers learn of the last commit with the next heartbeat.
+            assert!(cluster.chain(node).len() >= chain.len() - 1);
+            assert!(chain.starts_with(&cluster.chain(node)));
+        }
+    }
+
+    #[test]
+    fn new_leader_takes_over() {
+        let mut cluster = Cluster::new(3);
+        cluster.run(Duration::from_secs(10));
+        let old = cluster.leader().unwrap();
+        let term = cluster.nodes[old].term();
+        let committed = cluster.chain(old).len();
+        cluster.offline.insert(old);
+
+        cluster.run(Duration::from_secs(10));
+        let leader = cluster.leader().unwrap();
+        assert_ne!(leader, old);
+        assert!(cluster.nodes[leader].term() > term);
+
+        let chain = cluster.chain(leader);
+        assert!(chain.len() > committed + 1);
+        assert_eq!(chain.last().unwrap().1, vec![leader as u8 + 1; 20]);
+        for node in (0..3).filter(|node| *node != old) {
+            assert!(chain.starts_with(&cluster.chain(node)));
+        }
+    }
+
+    #[test]
+    fn replaces_conflicting_entries() {
+        let config = RaftConfig {
+            peers: vec![H160::repeat_byte(1), H160::repeat_byte(2)],
+            ..Default::default()
+        };
+        let mut follower = Raft::new(config, H160::repeat_byte(2)).unwrap();
+        follower.start(&RichStatus::new());
+        let entry = |term: u64, proposer: u8| {
+            let mut block = Block::new();
+            block.header.mut_or_insert_default().height = 1;
+            block.header.mut_or_insert_default().proposer = vec![proposer; 20];
+            Entry {
+                term,
+                proposed: term,
+                block,
+            }
+        };
+        let append = |term: u64, entries: Vec<Entry>, commit: u64| RaftMessage::AppendEntries {
+            term,
+            leader: H160::repeat_byte(1),
+            prev_index: 0,
+            prev_term: 0,
+            entries,
+            commit,
+        };
+
+        follower.on_message(append(1, vec![entry(1, 1)], 0));
+        let actions = follower.on_message(append(2, vec![entry(2, 3)], 1));
+        let committed = actions
+            .iter()
+            .find_map(|action| match action {
+                Action::Commit(sealed) => Some(sealed),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(committed.blk.header.proposer, vec![3; 20]);
+        assert_eq!(follower.term(), 2);
+        assert!(actions.contains(&Action::Send {
+            to: Some(H160::repeat_byte(1)),
+            msg: RaftMessage::AppendResponse {
+                term: 2,
+                follower: H160::repeat_byte(2),
+                success: true,
+                last_index: 1,
+            },
+        }));
+    }
+
+    #[test]
+    fn checks_the_leader_of_the_term() {
+        let mut cluster = Cluster::new(3);
+        cluster.run(Duration::from_secs(10));
+        let leader = cluster.leader().unwrap();
+        let follower = (leader + 1) % 3;
+        let sealed = cluster.chains[follower].last().unwrap();
+        let mut header = sealed.blk.header.get_or_default().clone();
+        header.proof = sealed.proof.clone();
+        let node = &cluster.nodes[follower];
+        assert!(node.verify(&header, &RichStatus::new()).is_ok());
+
+        let mut forged = header.clone();
+        forged.proposer = vec![follower as u8 + 1; 20];
+        assert!(node.verify(&forged, &RichStatus::new()).is_err());
+
+        let mut unknown = header;
+        let proof = RaftProof {
+            term: node.term() + 1,
+            index: unknown.height,
+        };
+        unknown.proof.mut_or_insert_default().content = proof.encode();
+        assert!(node.verify(&unknown, &RichStatus::new()).is_err());
+    }
+
+    #[test]
+    fn persists_the_vote_before_answering() {
+        let config = RaftConfig {
+            peers: (1..=3).map(H160::repeat_byte).collect(),
+            ..Default::default()
+        };
+        let request = |candidate: u8| RaftMessage::RequestVote {
+            term: 1,
+            candidate: H160::repeat_byte(candidate),
+            last_index: 0,
+            last_term: 0,
+        };
+        let granted = |actions: &[Action]| {
+            actions.iter().any(|action| {
+                matches!(
+                    action,
+                    Action::Send {
+                        msg: RaftMessage::Vote { granted: true, .. },
+                        ..
+                    }
+                )
+            })
+        };
+
+        let mut node = Raft::new(config.clone(), H160::repeat_byte(3)).unwrap();
+        node.start(&RichStatus::new());
+        let actions = node.on_message(request(1));
+        assert!(granted(&actions));
+        let persisted = actions
+            .iter()
+            .rposition(|action| matches!(action, Action::Persist(_)))
+            .unwrap();
+        let vote = actions
+            .iter()
+            .position(|action| matches!(action, Action::Send { .. }))
+            .unwrap();
+        assert!(persisted < vote);
+        let state = match &actions[persisted] {
+            Action::Persist(state) => state.clone(),
+            _ => unreachable!(),
+        };
+
+        // Restarted, the node doesn't vote for another candidate of the term.
+        let mut node = Raft::new(config, H160::repeat_byte(3)).unwrap();
+        node.restore(&state).unwrap();
+        node.start(&RichStatus::new());
+        assert_eq!(node.term(), 1);
+        assert!(!granted(&node.on_message(request(2))));
+        assert!(granted(&node.on_message(request(1))));
+        assert!(node.restore(&[1]).is_err());
+    }
+
+    #[test]
+    fn drops_messages_of_non_peers() {
+        let config = RaftConfig {
+            peers: (1..=3).map(H160::repeat_byte).collect(),
+            ..Default::default()
+        };
+        let mut node = Raft::new(config, H160::repeat_byte(1)).unwrap();
+        node.start(&RichStatus::new());
+
+        let mut block = Block::new();
+        block.header.mut_or_insert_default().height = 1;
+        let actions = node.on_message(RaftMessage::AppendEntries {
+            term: 5,
+            leader: H160::repeat_byte(4),
+            prev_index: 0,
+            prev_term: 0,
+            entries: vec![Entry {
+                term: 5,
+                proposed: 5,
+                block,
+            }],
+            commit: 1,
+        });
+        assert!(actions.is_empty());
+        assert_eq!(node.term(), 0);
+        assert!(node.leaders.is_empty());
+
+        let actions = node.on_message(RaftMessage::RequestVote {
+            term: 5,
+            candidate: H160::repeat_byte(4),
+            last_index: 0,
+            last_term: 0,
+        });
+        assert!(actions.is_empty());
+        assert_eq!(node.term(), 0);
+    }
+}
@@ -0,0 +1,56 @@
+use anyhow::anyhow;
+use ethereum_types::{H160, H256};
+use hashing::keccak_256;
+
+/// Key a validator signs consensus messages with.
+#[derive(Clone)]
+pub struct Signer {
+    secret: libsecp256k1::SecretKey,
+    address: H160,
+}
+
+impl Signer {
+    pub fn new(secret: libsecp256k1::SecretKey) -> Self {
+        let public = libsecp256k1::PublicKey::from_secret_key(&secret);
+        Self {
+            secret,
+            address: public_key_address(&public),
+        }
+    }
+
+    /// Address of the validator, derived from the key as for Ethereum
+    /// accounts.
+    pub fn address(&self) -> H160 {
+        self.address
+    }
+
+    /// Signs the keccak of `message`, returning `r || s || v`.
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        let message = libsecp256k1::Message::parse(&keccak_256(message));
+        let (signature, recid) = libsecp256k1::sign(&message, &self.secret);
+        let mut bytes = signature.serialize().to_vec();
+        bytes.push(recid.serialize());
+        bytes
+    }
+}
+
+/// Recovers the address that signed the keccak of `message`.
+pub fn recover(message: &[u8], signature: &[u8]) -> result::Result<H160> {
+    if signature.len() != 65 {
+        return Err(anyhow!("invalid signature length {}", signature.len()));
+    }
+    let message = libsecp256k1::Message::parse(&keccak_256(message));
+    let recid = libsecp256k1::RecoveryId::parse(signature[64])
+        .map_err(|_| anyhow!("invalid recovery id"))?;
+    let signature = libsecp256k1::Signature::parse_standard_slice(&signature[..64])
+        .map_err(|_| anyhow!("invalid signature"))?;
+    let public = libsecp256k1::recover(&message, &signature, &recid)
+        .map_err(|_| anyhow!("fail to recover signer"))?;
+    Ok(public_key_address(&public))
+}
+
+fn public_key_address(public: &libsecp256k1::PublicKey) -> H160 {
+    let mut res = [0u8; 64];
+    res.copy_from_slice(&public.serialize()[1..65]);
+    H160::from(H256::from(keccak_256(&res)))
+}
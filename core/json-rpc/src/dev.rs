@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use consensus::SealRequest;
+use ethereum_types::{H256, U64};
+use jsonrpsee::core::async_trait;
+use jsonrpsee::core::RpcResult as Result;
+use proto::{ChainRequest, MessageBus};
+use rpc_core::DevApiServer;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::internal_err;
+
+/// Serves the `DevApi` of a node whose `InstantSeal` engine receives
+/// `seal` requests.
+pub struct DevServer {
+    seal: mpsc::Sender<SealRequest>,
+    bus: Arc<MessageBus>,
+}
+
+impl DevServer {
+    pub fn new(seal: mpsc::Sender<SealRequest>, bus: Arc<MessageBus>) -> Self {
+        DevServer { seal, bus }
+    }
+
+    async fn seal(&self, create_empty: bool) -> Result<H256> {
+        let (reply, response) = oneshot::channel();
+        let request = SealRequest {
+            create_empty,
+            reply: Some(reply),
+        };
+        self.seal
+            .send(request)
+            .await
+            .map_err(|_| internal_err("consensus service closed"))?;
+        response
+            .await
+            .map_err(|_| internal_err("consensus service closed"))?
+            .map_err(internal_err)
+    }
+}
+
+#[async_trait]
+impl DevApiServer for DevServer {
+    async fn create_block(&self, create_empty: bool) -> Result<H256> {
+        self.seal(create_empty).await
+    }
+
+    async fn increase_time(&self, seconds: u64) -> Result<u64> {
+        self.bus
+            .chain(|reply| ChainRequest::IncreaseTime(seconds, reply))
+            .await
+            .map_err(internal_err)
+    }
+
+    async fn mine(&self, timestamp: Option<u64>) -> Result<U64> {
+        if let Some(timestamp) = timestamp {
+            self.bus
+                .chain(|reply| ChainRequest::SetTime(timestamp, reply))
+                .await
+                .map_err(internal_err)?;
+        }
+        self.seal(true).await?;
+        Ok(U64::zero())
+    }
+}
@@ -1,7 +1,9 @@
+mod dev;
 mod server;
 mod signer;
 
-pub use rpc_core::EthApiServer;
+pub use dev::DevServer;
+pub use rpc_core::{DevApiServer, EthApiServer};
 pub use server::Server;
 pub use signer::*;
 
@@ -1,77 +1,155 @@
 use std::sync::Arc;
+use chain::StateProvider;
 use ethereum_types::*;
+use evm::StateView;
 use jsonrpsee::core::async_trait;
 use jsonrpsee::core::RpcResult as Result;
+use kvdb::KeyValueDB;
+use proto::{AuthRequest, ChainRequest, MessageBus};
 use rpc_core::{types::*, EthApiServer};
 use txpool::TransactionPool;
 use crate::internal_err;
 use crate::signer::EthSigner;
 
-pub struct Server<P> {
+pub struct Server<P, T> {
+    chain_id: u64,
     signers: Vec<Box<dyn EthSigner>>,
     pool: Arc<P>,
+    state: Arc<StateProvider<T>>,
+    bus: Arc<MessageBus>,
 }
 
-impl <P>Server<P> {
-    pub fn new(signers: Vec<Box<dyn EthSigner>>, pool: Arc<P>) -> Self {
+impl <P, T: KeyValueDB>Server<P, T> {
+    pub fn new(
+        chain_id: u64,
+        signers: Vec<Box<dyn EthSigner>>,
+        pool: Arc<P>,
+        state: Arc<StateProvider<T>>,
+        bus: Arc<MessageBus>,
+    ) -> Self {
         Server {
+            chain_id,
             signers,
             pool,
+            state,
+            bus,
         }
     }
 
+    /// Resolves the state queried by a read method, the latest one by default.
+    fn state_at(&self, number: Option<BlockNumber>) -> Result<StateView<T>> {
+        let store = self.state.store();
+        let state = match number.unwrap_or_default() {
+            BlockNumber::Latest | BlockNumber::Pending => Ok(self.state.latest()),
+            BlockNumber::Earliest => self.state.at_height(0),
+            BlockNumber::Num(height) => self.state.at_height(height),
+            BlockNumber::Hash { hash, require_canonical } => {
+                if require_canonical {
+                    let header = store.header(&hash).map_err(internal_err)?;
+                    let canonical = match header {
+                        Some(header) => store.block_hash(header.height).map_err(internal_err)?,
+                        None => None,
+                    };
+                    if canonical != Some(hash) {
+                        return Err(internal_err(format!("block {:?} is not canonical", hash)));
+                    }
+                }
+                self.state.at_hash(&hash)
+            }
+            BlockNumber::Safe | BlockNumber::Finalized => {
+                match store.finalized_hash().map_err(internal_err)? {
+                    Some(hash) => self.state.at_hash(&hash),
+                    None => self.state.at_height(0),
+                }
+            }
+        };
+        state.map_err(internal_err)
+    }
+
     pub fn accounts(&self) -> Result<Vec<H160>> {
-        let accounts = Vec::new();
-        Ok(accounts)
+        Ok(self.signers.iter().flat_map(|signer| signer.accounts()).collect())
     }
 
     pub fn block_number(&self) -> Result<U256> {
-        Ok(U256::zero())
+        let best = self.state.store().best_header().map_err(internal_err)?;
+        Ok(U256::from(best.map(|header| header.height).unwrap_or_default()))
     }
 
     pub fn chain_id(&self) -> Result<Option<U64>> {
-        Ok(Some(U64::from(1)))
+        Ok(Some(U64::from(self.chain_id)))
     }
 
-    async fn block_by_hash(&self, _hash: H256, _full: bool) -> Result<Option<RichBlock>> {
-        Ok(None)
+    async fn block_by_hash(&self, hash: H256, full: bool) -> Result<Option<RichBlock>> {
+        self.bus
+            .chain(|reply| ChainRequest::BlockByHash(hash, full, reply))
+            .await
+            .map_err(internal_err)
     }
 
     async fn block_by_number(
         &self,
-        _number: BlockNumber,
-        _full: bool,
+        number: BlockNumber,
+        full: bool,
     ) -> Result<Option<RichBlock>> {
-        Ok(None)
+        let height = match number {
+            BlockNumber::Latest | BlockNumber::Pending => self
+                .bus
+                .chain(ChainRequest::BlockNumber)
+                .await
+                .map_err(internal_err)?,
+            BlockNumber::Earliest => 0,
+            BlockNumber::Num(height) => height,
+            BlockNumber::Hash { hash, .. } => return self.block_by_hash(hash, full).await,
+            BlockNumber::Safe | BlockNumber::Finalized => {
+                match self.state.store().finalized_hash().map_err(internal_err)? {
+                    Some(hash) => return self.block_by_hash(hash, full).await,
+                    None => 0,
+                }
+            }
+        };
+        self.bus
+            .chain(|reply| ChainRequest::BlockByNumber(height, full, reply))
+            .await
+            .map_err(internal_err)
     }
 
     async fn transaction_by_hash(&self, _hash: H256) -> Result<Option<Transaction>> {
         Ok(None)
     }
 
-    async fn transaction_receipt(&self, _hash: H256) -> Result<Option<Receipt>> {
-        Ok(None)
+    async fn transaction_receipt(&self, hash: H256) -> Result<Option<Receipt>> {
+        self.bus
+            .chain(|reply| ChainRequest::Receipt(hash, reply))
+            .await
+            .map_err(internal_err)
     }
 
-    fn balance(&self, _address: H160, _number: Option<BlockNumber>) -> Result<U256> {
-        Ok(U256::zero())
+    fn balance(&self, address: H160, number: Option<BlockNumber>) -> Result<U256> {
+        let account = self.state_at(number)?.account(address).map_err(internal_err)?;
+        Ok(account.map(|account| account.balance).unwrap_or_default())
     }
 
     fn storage_at(
         &self,
-        _address: H160,
-        _index: U256,
-        _number: Option<BlockNumber>,
+        address: H160,
+        index: U256,
+        number: Option<BlockNumber>,
     ) -> Result<H256> {
-        Ok(H256::default())
+        let mut key = [0u8; 32];
+        index.to_big_endian(&mut key);
+        self.state_at(number)?
+            .storage(address, H256(key))
+            .map_err(internal_err)
     }
 
-    fn transaction_count(&self, _address: H160, _number: Option<BlockNumber>) -> Result<U256> {
-        Ok(U256::zero())
+    fn transaction_count(&self, address: H160, number: Option<BlockNumber>) -> Result<U256> {
+        let account = self.state_at(number)?.account(address).map_err(internal_err)?;
+        Ok(account.map(|account| account.nonce).unwrap_or_default())
     }
 
-    fn code_at(&self, _address: H160, _number: Option<BlockNumber>) -> Result<Bytes> {
-        Ok(Bytes::default())
+    fn code_at(&self, address: H160, number: Option<BlockNumber>) -> Result<Bytes> {
+        let code = self.state_at(number)?.code(address).map_err(internal_err)?;
+        Ok(Bytes::new(code))
     }
 
     fn call(&self, _request: CallRequest, _number: Option<BlockNumber>) -> Result<Bytes> {
@@ -83,9 +161,10 @@ impl <P>Server<P> {
     }
 }
 
-impl<P> Server<P>
+impl<P, T> Server<P, T>
     where
         P: TransactionPool+ Send + Sync + 'static,
+        T: KeyValueDB,
 {
     async fn send_transaction(&self, request: TransactionRequest) -> Result<H256> {
         let from = match request.from {
@@ -97,7 +176,7 @@ impl<P> Server<P>
 
         let nonce = match request.nonce {
             Some(nonce) => nonce,
-            None => U256::zero(),
+            None => self.pool.pending_nonce(&from),
         };
 
         let chain_id = match self.chain_id() {
@@ -164,19 +243,30 @@ impl<P> Server<P>
         };
         let transaction_hash = transaction.hash();
 
-        self.pool.submit_one(transaction)?;
+        self.pool.submit_one(transaction).map_err(internal_err)?;
 
         Ok(transaction_hash)
     }
 
-    async fn send_raw_transaction(&self, _bytes: Bytes) -> Result<H256> {
-        Ok(H256::default())
+    async fn send_raw_transaction(&self, bytes: Bytes) -> Result<H256> {
+        let transaction = self
+            .bus
+            .auth(|reply| AuthRequest::VerifyTransaction(bytes.into_vec(), reply))
+            .await
+            .map_err(internal_err)?
+            .map_err(internal_err)?;
+        let transaction_hash = transaction.hash();
+
+        self.pool.submit_one(transaction).map_err(internal_err)?;
+
+        Ok(transaction_hash)
     }
 }
 
 #[async_trait]
-impl <P>EthApiServer for Server<P>
-where P: TransactionPool + Send + Sync + 'static
+impl <P, T>EthApiServer for Server<P, T>
+where P: TransactionPool + Send + Sync + 'static,
+      T: KeyValueDB + 'static
 {
     fn accounts(&self) -> Result<Vec<H160>> {
         self.accounts()
@@ -0,0 +1,125 @@
+use std::path::{Path, PathBuf};
+
+use chain::Pruning;
+use ethereum_types::H256;
+use proto::ProofType;
+use serde::{Deserialize, Deserializer};
+
+/// Node settings, loaded from JSON.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct NodeConfig {
+    /// Proofs sealing the blocks, `"Bft"`, `"AuthorityRound"` or `"Raft"`,
+    /// which select the consensus engine. Without one the node seals its own
+    /// blocks alone.
+    #[serde(deserialize_with = "deserialize_proof_type")]
+    pub proof_type: Option<ProofType>,
+    /// Secret key the node signs as a validator with.
+    pub validator_key: Option<H256>,
+    /// Time between two blocks in seconds.
+    pub block_interval: u64,
+    /// Seal a block as soon as a transaction is ready, or when requested
+    /// through the dev RPCs, instead of every `block_interval`.
+    pub dev: bool,
+    /// State history served to `eth_*` queries by block number, as a number
+    /// of blocks below the best one, or `"archive"` to keep all of it.
+    #[serde(deserialize_with = "deserialize_pruning")]
+    pub pruning: Pruning,
+    /// File pooled transactions are journaled to, `null` to not journal
+    /// them.
+    pub txpool_journal: Option<PathBuf>,
+    /// Time between two rewrites of the transaction journal in seconds.
+    pub txpool_rejournal: u64,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            proof_type: None,
+            validator_key: None,
+            block_interval: 3,
+            dev: false,
+            pruning: Pruning::default(),
+            txpool_journal: Some(Path::new(crate::DATA_DIR).join("transactions.rlp")),
+            txpool_rejournal: 3600,
+        }
+    }
+}
+
+impl NodeConfig {
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path.as_ref())?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+}
+
+fn deserialize_proof_type<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<ProofType>, D::Error> {
+    let name = Option::<String>::deserialize(deserializer)?;
+    name.map(|name| match name.as_str() {
+        "AuthorityRound" => Ok(ProofType::AuthorityRound),
+        "Raft" => Ok(ProofType::Raft),
+        "Bft" => Ok(ProofType::Bft),
+        _ => Err(serde::de::Error::custom(format!(
+            "unknown proof type {}",
+            name
+        ))),
+    })
+    .transpose()
+}
+
+fn deserialize_pruning<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pruning, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Depth {
+        Blocks(u64),
+        Name(String),
+    }
+
+    match Depth::deserialize(deserializer)? {
+        Depth::Blocks(depth) => Ok(Pruning::KeepRecent(depth)),
+        Depth::Name(name) if name == "archive" => Ok(Pruning::Archive),
+        Depth::Name(name) => Err(serde::de::Error::custom(format!(
+            "unknown pruning {}",
+            name
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_config() {
+        let config: NodeConfig = serde_json::from_str(
+            r#"{
+                "proofType": "Bft",
+                "validatorKey": "0x0000000000000000000000000000000000000000000000000000000000000001"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.proof_type, Some(ProofType::Bft));
+        assert_eq!(config.validator_key, Some(H256::from_low_u64_be(1)));
+        assert_eq!(config.block_interval, 3);
+        assert!(!config.dev);
+        assert_eq!(config.pruning, Pruning::KeepRecent(128));
+        assert!(config.txpool_journal.is_some());
+
+        assert_eq!(
+            serde_json::from_str::<NodeConfig>("{}").unwrap(),
+            NodeConfig::default()
+        );
+        assert!(serde_json::from_str::<NodeConfig>(r#"{"proofType": "Pow"}"#).is_err());
+
+        let config: NodeConfig = serde_json::from_str(r#"{"pruning": "archive"}"#).unwrap();
+        assert_eq!(config.pruning, Pruning::Archive);
+        let config: NodeConfig = serde_json::from_str(r#"{"pruning": 16}"#).unwrap();
+        assert_eq!(config.pruning, Pruning::KeepRecent(16));
+        assert!(serde_json::from_str::<NodeConfig>(r#"{"pruning": "none"}"#).is_err());
+
+        let config: NodeConfig = serde_json::from_str(r#"{"txpoolJournal": null}"#).unwrap();
+        assert_eq!(config.txpool_journal, None);
+    }
+}
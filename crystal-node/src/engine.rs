@@ -0,0 +1,109 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use consensus::{
+    Aura, Bft, BftConfig, ConsensusEngine, EngineService, InstantSeal, Raft, RaftConfig,
+    SealRequest, Signer,
+};
+use ethereum_types::H160;
+use proto::communication::inner_message::Content;
+use proto::{MessageBus, ProofType};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tracing::info;
+use txpool::{PoolEvent, TransactionPool};
+
+use crate::config::NodeConfig;
+
+/// Capacity of the channels of consensus messages.
+pub const MESSAGE_CAPACITY: usize = 1024;
+
+/// Spawns the consensus engine for the proof type of `config`, agreeing on
+/// blocks with `validators` and checking the `blocks` of other nodes.
+///
+/// Returns the sender of seal requests of a node sealing its own blocks.
+pub fn spawn(
+    config: &NodeConfig,
+    validators: Vec<H160>,
+    bus: Arc<MessageBus>,
+    blocks: Receiver<Content>,
+) -> anyhow::Result<Option<Sender<SealRequest>>> {
+    if config.dev && config.proof_type.is_some() {
+        return Err(anyhow!("dev nodes seal their blocks without proof"));
+    }
+    let interval = Duration::from_secs(config.block_interval);
+    let signer = || -> anyhow::Result<Signer> {
+        let key = config
+            .validator_key
+            .ok_or_else(|| anyhow!("a validator key is required by {:?}", config.proof_type))?;
+        Ok(Signer::new(libsecp256k1::SecretKey::parse(&key.0)?))
+    };
+    match config.proof_type {
+        None if config.dev => return Ok(Some(run(InstantSeal::manual(), bus, blocks))),
+        None => return Ok(Some(run(InstantSeal::new(interval), bus, blocks))),
+        Some(ProofType::Bft) => {
+            let config = BftConfig {
+                validators,
+                ..Default::default()
+            };
+            run(Bft::new(config, signer()?)?, bus, blocks);
+        }
+        Some(ProofType::AuthorityRound) => {
+            run(Aura::new(signer()?), bus, blocks);
+        }
+        Some(ProofType::Raft) => {
+            let config = RaftConfig {
+                peers: validators,
+                interval,
+                ..Default::default()
+            };
+            run(Raft::new(config, signer()?.address())?, bus, blocks);
+        }
+    }
+    Ok(None)
+}
+
+/// Requests a block whenever a transaction of `pool` becomes ready.
+pub fn seal_ready<P: TransactionPool>(pool: &P, seal: Sender<SealRequest>) {
+    let mut events = pool.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                // Missed events may have been about ready transactions.
+                Ok(PoolEvent::Ready(_)) | Err(RecvError::Lagged(_)) => {}
+                Ok(_) => continue,
+                Err(RecvError::Closed) => break,
+            }
+            let request = SealRequest {
+                create_empty: false,
+                reply: None,
+            };
+            if seal.send(request).await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Runs `engine`, returning the sender of its inbound messages.
+fn run<E: ConsensusEngine + 'static>(
+    engine: E,
+    bus: Arc<MessageBus>,
+    blocks: Receiver<Content>,
+) -> Sender<E::Message> {
+    info!("starting consensus with {:?} proofs", engine.proof_type());
+    let (inbound, receiver) = tokio::sync::mpsc::channel(MESSAGE_CAPACITY);
+    let (sender, mut outbound) = tokio::sync::mpsc::channel(MESSAGE_CAPACITY);
+    // The node has no network to exchange the messages with other nodes yet.
+    let network = inbound.clone();
+    tokio::spawn(async move {
+        let _inbound = network;
+        while outbound.recv().await.is_some() {}
+    });
+    tokio::spawn(async move {
+        let mut service = EngineService::new(engine, bus, receiver, sender, blocks);
+        service.run().await.unwrap();
+    });
+    inbound
+}
@@ -1,15 +1,28 @@
+mod config;
+mod engine;
 mod rpc;
 mod service;
 
-use proto::{Message, MessageBus};
+use json_rpc::EthSigner;
+use proto::MessageBus;
 use signal_hook::{
     consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM},
     iterator::Signals,
 };
+use std::path::Path;
 use std::sync::Arc;
 use tracing::log::info;
 use tracing_subscriber::FmtSubscriber;
 
+/// Directory the node keeps its databases in.
+const DATA_DIR: &str = "data";
+/// Genesis specification read on first start.
+const GENESIS_FILE: &str = "genesis.json";
+/// Node settings, the defaults are used if missing.
+const CONFIG_FILE: &str = "node.json";
+/// How often the state released by old blocks is deleted.
+const STATE_PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
 fn init_log() {
     let subscriber = FmtSubscriber::builder()
         .with_max_level(tracing::Level::INFO)
@@ -22,6 +35,13 @@ fn init_log() {
 }
 
 async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let node_config = if Path::new(CONFIG_FILE).exists() {
+        config::NodeConfig::from_file(CONFIG_FILE)?
+    } else {
+        info!("{} not found, using the default config", CONFIG_FILE);
+        config::NodeConfig::default()
+    };
+
     let (jsonrpc_sender, _jsonrpc_reciver) = tokio::sync::mpsc::channel(1024);
     let (auth_sender, auth_reciver) = tokio::sync::mpsc::channel(1024);
     let (chain_sender, chain_reciver) = tokio::sync::mpsc::channel(1024);
@@ -32,17 +52,147 @@ async fn run() -> Result<(), Box<dyn std::error::Error>> {
         chain_sender,
     });
 
-    service::run()?;
+    let db = Arc::new(kvdb_rocksdb::Database::open(
+        &kvdb_rocksdb::DatabaseConfig::with_columns(chain::NUM_COLUMNS),
+        Path::new(DATA_DIR).join("db"),
+    )?);
+    let state = Arc::new(evm::Executive::new(db.clone()));
+    let store = chain::ChainStore::new(db);
+
+    let genesis = if Path::new(GENESIS_FILE).exists() {
+        chain::Genesis::from_file(GENESIS_FILE)?
+    } else {
+        info!("{} not found, using the default genesis", GENESIS_FILE);
+        let mut genesis = chain::Genesis::default();
+        if node_config.dev {
+            // Funds the accounts of the dev signer with a million ether.
+            for account in json_rpc::EthDevSigner::new().accounts() {
+                let funded = chain::GenesisAccount {
+                    balance: ethereum_types::U256::exp10(24),
+                    ..Default::default()
+                };
+                genesis.alloc.insert(account, funded);
+            }
+        }
+        genesis
+    };
+    let genesis_hash = genesis.init(&store, &state)?;
+    info!("genesis block {:?}", genesis_hash);
+
+    let config = txpool::PoolConfig {
+        chain_id: genesis.chain_id,
+        journal: node_config.txpool_journal.clone(),
+        // Intervals may not be empty.
+        rejournal: std::time::Duration::from_secs(node_config.txpool_rejournal.max(1)),
+        ..Default::default()
+    };
+    let rejournal = config.rejournal;
+    let pool = Arc::new(txpool::BasicPool::new(config, state.clone()));
+
+    let provider = Arc::new(chain::StateProvider::new(
+        store.clone(),
+        state.clone(),
+        node_config.pruning,
+    ));
+    let (peer_blocks, blocks) = tokio::sync::mpsc::channel(engine::MESSAGE_CAPACITY);
+    let seal = engine::spawn(
+        &node_config,
+        genesis.validators.clone(),
+        bus.clone(),
+        blocks,
+    )?;
+    let dev_seal = match seal {
+        Some(seal) if node_config.dev => {
+            engine::seal_ready(pool.as_ref(), seal.clone());
+            Some(seal)
+        }
+        _ => None,
+    };
+    service::run(
+        genesis.chain_id,
+        pool.clone(),
+        provider.clone(),
+        bus.clone(),
+        dev_seal,
+    )?;
+
+    let pruner = provider.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(STATE_PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match pruner.prune() {
+                Ok(stats) if stats.nodes > 0 => info!(
+                    "pruned state of {} blocks, deleted {} nodes, reclaimed {} bytes ({} bytes in total)",
+                    stats.blocks,
+                    stats.nodes,
+                    stats.bytes,
+                    pruner.pruned().bytes
+                ),
+                Ok(_) => {}
+                Err(err) => tracing::error!("prune state error: {}", err),
+            }
+        }
+    });
+
+    if node_config.txpool_journal.is_some() {
+        let pool_clone = pool.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(rejournal);
+            // The first tick completes immediately and the pool has just
+            // written its journal.
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                pool_clone.rejournal();
+            }
+        });
+    }
 
     let bus_clone = bus.clone();
+    let auth_config = auth::AuthConfig {
+        chain_id: genesis.chain_id,
+        ..Default::default()
+    };
     tokio::spawn(async move {
-        let mut ath = auth::Server::new(auth_reciver, bus_clone);
+        let mut ath = auth::Server::new(auth_reciver, bus_clone, auth_config);
         ath.run().await.unwrap();
     });
 
+    let proposer = match node_config.validator_key {
+        Some(key) => {
+            let secret = libsecp256k1::SecretKey::parse(&key.0)?;
+            consensus::Signer::new(secret).address()
+        }
+        None => Default::default(),
+    };
     let bus_clone = bus.clone();
+    let interval = std::time::Duration::from_secs(node_config.block_interval);
     tokio::spawn(async move {
-        let mut chain = chain::Server::new(chain_reciver, bus_clone);
+        let config = chain::ProducerConfig {
+            interval,
+            quota_limit: genesis.quota_limit,
+            proposer,
+            chain_id: genesis.chain_id,
+        };
+        let producer = chain::BlockProducer::new(config, pool.clone(), state.clone());
+        let import = chain::ImportConfig {
+            chain_id: genesis.chain_id,
+            quota_limit: genesis.quota_limit,
+            validators: genesis.validators.clone(),
+        };
+        let importer = chain::BlockImporter::new(import, store.clone(), state);
+        let mut chain = chain::Server::new(
+            chain_reciver,
+            bus_clone,
+            pool,
+            producer,
+            importer,
+            store,
+            provider,
+        )
+        .unwrap();
+        chain.forward_blocks(peer_blocks);
         chain.run().await.unwrap();
     });
 
@@ -52,9 +202,7 @@ async fn run() -> Result<(), Box<dyn std::error::Error>> {
         info!("Received signal {:?}", signal);
         match signal {
             SIGTERM | SIGQUIT | SIGINT => {
-                bus.auth_sender.send(Message::Close).await?;
-                bus.jsonrpc_sender.send(Message::Close).await?;
-                bus.chain_sender.send(Message::Close).await?;
+                bus.close().await?;
                 break;
             }
             SIGHUP => {
@@ -1,12 +1,25 @@
+use chain::StateProvider;
+use consensus::SealRequest;
 use jsonrpsee::RpcModule;
+use kvdb::KeyValueDB;
+use proto::MessageBus;
 use std::sync::Arc;
+use tokio::sync::mpsc::Sender;
 use txpool::TransactionPool;
 
-pub fn new<P>(pool: Arc<P>, enable_dev_signer: bool) -> anyhow::Result<RpcModule<()>>
+pub fn new<P, T>(
+    chain_id: u64,
+    pool: Arc<P>,
+    state: Arc<StateProvider<T>>,
+    bus: Arc<MessageBus>,
+    enable_dev_signer: bool,
+    seal: Option<Sender<SealRequest>>,
+) -> anyhow::Result<RpcModule<()>>
 where
     P: TransactionPool + 'static,
+    T: KeyValueDB + 'static,
 {
-    use json_rpc::{EthApiServer, EthDevSigner, EthSigner};
+    use json_rpc::{DevApiServer, EthApiServer, EthDevSigner, EthSigner};
 
     let mut io = RpcModule::new(());
 
@@ -17,7 +30,10 @@ where
 
     // let pool = Arc::new(txpool::BasicPool::new());
 
-    io.merge(json_rpc::Server::new(signers, pool).into_rpc())?;
+    if let Some(seal) = seal {
+        io.merge(json_rpc::DevServer::new(seal, bus.clone()).into_rpc())?;
+    }
+    io.merge(json_rpc::Server::new(chain_id, signers, pool, state, bus).into_rpc())?;
 
     Ok(io)
 }
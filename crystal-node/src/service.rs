@@ -1,12 +1,26 @@
 use std::sync::Arc;
 use crate::rpc;
 
+use chain::StateProvider;
+use consensus::SealRequest;
 use jsonrpsee::RpcModule;
-
-pub fn run() -> anyhow::Result<()> {
-    let pool = Arc::new(txpool::BasicPool::new());
-
-    let module = rpc::new(pool, true)?;
+use kvdb::KeyValueDB;
+use proto::MessageBus;
+use tokio::sync::mpsc::Sender;
+use txpool::TransactionPool;
+
+pub fn run<P, T>(
+    chain_id: u64,
+    pool: Arc<P>,
+    state: Arc<StateProvider<T>>,
+    bus: Arc<MessageBus>,
+    seal: Option<Sender<SealRequest>>,
+) -> anyhow::Result<()>
+where
+    P: TransactionPool + 'static,
+    T: KeyValueDB + 'static,
+{
+    let module = rpc::new(chain_id, pool, state, bus, true, seal)?;
 
     tokio::task::spawn(async {
         run_json_rpc_server(module).await.unwrap();
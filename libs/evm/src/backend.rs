@@ -1,80 +1,110 @@
-use crate::key_mapping::StorageDoubleMap;
 use crate::Vicinity;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use ethereum::Account;
 use ethereum_types::{H160, H256, U256};
 use evm::backend::{Apply, ApplyBackend, Backend, Basic, Log};
+use keccak_hash::{keccak, KECCAK_EMPTY};
 use kvdb::KeyValueDB;
-use std::sync::Arc;
 use tracing::error;
-
-const STORAGE_CODE_KEY: u8 = 1;
-const STORAGE_SLOT_KEY: u8 = 2;
-
-#[derive(Clone, Debug)]
-pub struct CrystalBackend<'vicinity, T> {
+use trie::{
+    HashDB, HashDBRef, Layout, OverlayDB, Trie, TrieDBBuilder, TrieDBMutBuilder, TrieMut,
+    EMPTY_PREFIX, EMPTY_ROOT,
+};
+
+/// Column of the state trie nodes and contract code, both keyed by hash.
+pub const COL_STATE: u32 = 0;
+/// Key of the latest state root in `COL_STATE`.
+pub const STATE_ROOT_KEY: &[u8] = b"state_root";
+
+/// Backend over the Ethereum state trie of a given root.
+///
+/// Accounts are stored in a secure trie keyed by the keccak of their address,
+/// and the slots of each account in its own secure trie of root
+/// `Account.storage_root`. Applying changes moves the backend to the
+/// resulting root; the new trie nodes stay in the overlay until its owner
+/// commits it.
+///
+/// Trie nodes and contract code are reference counted, so the changes also
+/// dereference the values the previous root used.
+pub struct CrystalBackend<'vicinity, 'db, T: ?Sized> {
     vicinity: &'vicinity Vicinity,
-    state: Arc<T>,
+    db: OverlayDB<'db, T>,
+    root: H256,
 }
 
-impl<'vicinity, T: KeyValueDB> CrystalBackend<'vicinity, T> {
-    /// Create a new memory backend.
-    pub fn new(vicinity: &'vicinity Vicinity, state: Arc<T>) -> Self {
-        Self { vicinity, state }
+impl<'vicinity, 'db, T: KeyValueDB + ?Sized> CrystalBackend<'vicinity, 'db, T> {
+    pub fn new(vicinity: &'vicinity Vicinity, db: OverlayDB<'db, T>, root: H256) -> Self {
+        Self { vicinity, db, root }
     }
 
-    fn address_key(address: H160) -> StorageDoubleMap {
-        StorageDoubleMap {
-            module: "evm".as_bytes().to_vec(),
-            storage: address.as_bytes().to_vec(),
-        }
+    /// Root of the state trie.
+    pub fn root(&self) -> H256 {
+        self.root
     }
 
-    fn gen_code_key(address: H160, hash: H256) -> Vec<u8> {
-        Self::address_key(address).storage_double_map_final_key(STORAGE_CODE_KEY, hash)
+    /// Returns the overlay holding the changes applied so far.
+    pub fn into_db(self) -> OverlayDB<'db, T> {
+        self.db
     }
 
-    fn gen_code_key_perfix(address: H160) -> Vec<u8> {
-        Self::address_key(address).storage_double_map_key_prefix(STORAGE_CODE_KEY)
+    pub fn account(&self, address: H160) -> Result<Option<Account>> {
+        read_account(&self.db, &self.root, address)
     }
 
-    fn gen_slot_key(address: H160, index: H256) -> Vec<u8> {
-        Self::address_key(address).storage_double_map_final_key(STORAGE_SLOT_KEY, index)
+    fn storage_root(&self, address: H160) -> Result<H256> {
+        Ok(self
+            .account(address)?
+            .map(|account| account.storage_root)
+            .unwrap_or(EMPTY_ROOT))
     }
 
-    // evm:address+slot+index ->  value
-    fn gen_slot_key_perfix(address: H160) -> Vec<u8> {
-        Self::address_key(address).storage_double_map_key_prefix(STORAGE_SLOT_KEY)
+    pub fn read_storage(&self, address: H160, index: H256) -> Result<H256> {
+        let root = self.storage_root(address)?;
+        let trie = TrieDBBuilder::<Layout>::new(&self.db, &root).build();
+        match trie.get(keccak(index).as_bytes())? {
+            Some(value) => Ok(u256_to_h256(rlp::decode(&value)?)),
+            None => Ok(H256::zero()),
+        }
     }
 
-    // evm:address -> account
-    fn gen_accout_key(address: H160) -> Vec<u8> {
-        Self::address_key(address).storage_double_map_prefix()
-    }
-    /// Get the underlying `BTreeMap` storing the state.
-    pub fn state(&self) -> &T {
-        &self.state
+    pub fn read_code(&self, address: H160) -> Result<Vec<u8>> {
+        let code_hash = match self.account(address)? {
+            Some(account) if account.code_hash != KECCAK_EMPTY => account.code_hash,
+            _ => return Ok(vec![]),
+        };
+        HashDB::get(&self.db, &code_hash, EMPTY_PREFIX)
+            .ok_or_else(|| anyhow!("missing code {:?} of {:?}", code_hash, address))
     }
 
-    fn contains(&self, key: &[u8]) -> bool {
-        self.state.has_key(0, key).unwrap_or(false)
+    /// Trie nodes proving the account of `address`, in the format of
+    /// `eth_getProof`.
+    pub fn account_proof(&self, address: H160) -> Result<Vec<Vec<u8>>> {
+        Ok(trie::generate_proof(
+            &self.db,
+            &self.root,
+            keccak(address).as_bytes(),
+        )?)
     }
 
-    fn get_account(&self, address: H160) -> Result<Option<Account>> {
-        let v = self.state.get(0, &Self::gen_accout_key(address))?;
-        match v {
-            Some(data) => Ok(rlp::decode(&data)?),
-            None => Ok(None),
-        }
+    /// Trie nodes proving the slot `index` of `address` against the account
+    /// storage root.
+    pub fn storage_proof(&self, address: H160, index: H256) -> Result<Vec<Vec<u8>>> {
+        let root = self.storage_root(address)?;
+        Ok(trie::generate_proof(
+            &self.db,
+            &root,
+            keccak(index).as_bytes(),
+        )?)
     }
 
-    fn apply<A, I, L>(&mut self, values: A, _logs: L, delete_empty: bool) -> Result<()>
+    pub(crate) fn apply<A, I, L>(&mut self, values: A, _logs: L, delete_empty: bool) -> Result<()>
     where
         A: IntoIterator<Item = Apply<I>>,
         I: IntoIterator<Item = (H256, H256)>,
         L: IntoIterator<Item = Log>,
     {
-        let mut tx = self.state.transaction();
+        let db = &mut self.db;
+        let root = &mut self.root;
         for apply in values {
             match apply {
                 Apply::Modify {
@@ -84,82 +114,119 @@ impl<'vicinity, T: KeyValueDB> CrystalBackend<'vicinity, T> {
                     storage,
                     reset_storage,
                 } => {
-                    let mut find = false;
-                    let mut tx_sub = self.state.transaction();
-
-                    let mut account = match self.get_account(address)? {
-                        Some(account) => {
-                            find = true;
-                            account
-                        }
-                        None => Account {
-                            nonce: U256::zero(),
-                            balance: U256::zero(),
-                            storage_root: H256::zero(),
-                            code_hash: H256::zero(),
-                        },
-                    };
+                    let mut account = read_account(db, root, address)?.unwrap_or_else(|| Account {
+                        nonce: U256::zero(),
+                        balance: U256::zero(),
+                        storage_root: EMPTY_ROOT,
+                        code_hash: KECCAK_EMPTY,
+                    });
 
                     account.balance = basic.balance;
                     account.nonce = basic.nonce;
-                    let has_code = code.is_some();
 
                     if let Some(code) = code {
-                        let hash = keccak_hash::keccak(&code);
-                        account.code_hash = hash;
-
-                        tx_sub.put(0, Self::gen_code_key(address, hash).as_ref(), &code)
+                        release_code(db, &account.code_hash);
+                        account.code_hash = match code.is_empty() {
+                            true => KECCAK_EMPTY,
+                            false => db.insert(EMPTY_PREFIX, &code),
+                        };
                     }
 
                     if reset_storage {
-                        tx_sub.delete_prefix(0, &Self::gen_slot_key_perfix(address))
+                        clear_storage(db, &mut account.storage_root)?;
                     }
-
-                    let storage_prefix = Self::gen_slot_key_perfix(address);
-                    let iter = self.state.iter_with_prefix(0, &storage_prefix);
-                    for item in iter {
-                        let (key, value) = item?;
-                        if H256::from_slice(&value) == H256::default() {
-                            tx_sub.delete(0, &key)
-                        }
-                    }
-
-                    for (index, value) in storage {
-                        if value == H256::default() {
-                            // account.storage.remove(&index);
-                            tx_sub.delete(0, &Self::gen_slot_key(address, index));
-                        } else {
-                            // account.storage.insert(index, value);
-                            tx_sub.put(0, &Self::gen_slot_key(address, index), value.as_bytes())
+                    {
+                        let mut trie = TrieDBMutBuilder::<Layout>::from_existing(
+                            db,
+                            &mut account.storage_root,
+                        )
+                        .build();
+                        for (index, value) in storage {
+                            let key = keccak(index);
+                            if value.is_zero() {
+                                trie.remove(key.as_bytes())?;
+                            } else {
+                                let value = U256::from_big_endian(value.as_bytes());
+                                trie.insert(key.as_bytes(), &rlp::encode(&value))?;
+                            }
                         }
                     }
 
-                    if account.balance == U256::zero()
-                        && account.nonce == U256::zero()
-                        && delete_empty
-                        && (has_code
-                            || self.state.has_key(0, &Self::gen_code_key_perfix(address))?)
+                    if delete_empty
+                        && account.nonce.is_zero()
+                        && account.balance.is_zero()
+                        && account.code_hash == KECCAK_EMPTY
                     {
-                        tx_sub.ops.clear();
-
-                        tx.delete_prefix(0, &Self::gen_accout_key(address))
+                        delete_account(db, root, address, account)?;
                     } else {
-                        tx_sub.put(0, &Self::gen_accout_key(address), &rlp::encode(&account));
-
-                        tx.ops.append(&mut tx_sub.ops);
+                        let mut trie = TrieDBMutBuilder::<Layout>::from_existing(db, root).build();
+                        trie.insert(keccak(address).as_bytes(), &rlp::encode(&account))?;
+                    }
+                }
+                Apply::Delete { address } => {
+                    if let Some(account) = read_account(db, root, address)? {
+                        delete_account(db, root, address, account)?;
                     }
                 }
-                Apply::Delete { address } => tx.delete_prefix(0, &Self::gen_accout_key(address)),
             }
         }
 
-        self.state.write(tx)?;
-
         Ok(())
     }
 }
 
-impl<'vicinity, T: KeyValueDB> Backend for CrystalBackend<'vicinity, T> {
+fn read_account<D: HashDBRef<trie::KeccakHasher, Vec<u8>>>(
+    db: &D,
+    root: &H256,
+    address: H160,
+) -> Result<Option<Account>> {
+    let trie = TrieDBBuilder::<Layout>::new(db, root).build();
+    match trie.get(keccak(address).as_bytes())? {
+        Some(account) => Ok(Some(rlp::decode(&account)?)),
+        None => Ok(None),
+    }
+}
+
+/// Removes `address` from the account trie along with its storage and code.
+fn delete_account<T: KeyValueDB + ?Sized>(
+    db: &mut OverlayDB<T>,
+    root: &mut H256,
+    address: H160,
+    mut account: Account,
+) -> Result<()> {
+    clear_storage(db, &mut account.storage_root)?;
+    release_code(db, &account.code_hash);
+    let mut trie = TrieDBMutBuilder::<Layout>::from_existing(db, root).build();
+    trie.remove(keccak(address).as_bytes())?;
+    Ok(())
+}
+
+/// Empties a storage trie, dereferencing all of its nodes.
+fn clear_storage<T: KeyValueDB + ?Sized>(db: &mut OverlayDB<T>, root: &mut H256) -> Result<()> {
+    let keys = TrieDBBuilder::<Layout>::new(db, root)
+        .build()
+        .key_iter()?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut trie = TrieDBMutBuilder::<Layout>::from_existing(db, root).build();
+    for key in keys {
+        trie.remove(&key)?;
+    }
+    Ok(())
+}
+
+fn release_code<T: KeyValueDB + ?Sized>(db: &mut OverlayDB<T>, code_hash: &H256) {
+    if *code_hash != KECCAK_EMPTY {
+        db.remove(code_hash, EMPTY_PREFIX);
+    }
+}
+
+fn u256_to_h256(value: U256) -> H256 {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    H256(bytes)
+}
+
+impl<'vicinity, 'db, T: KeyValueDB + ?Sized> Backend for CrystalBackend<'vicinity, 'db, T> {
     fn gas_price(&self) -> U256 {
         self.vicinity.gas_price
     }
@@ -201,11 +268,11 @@ impl<'vicinity, T: KeyValueDB> Backend for CrystalBackend<'vicinity, T> {
     }
 
     fn exists(&self, address: H160) -> bool {
-        self.contains(&address.as_bytes())
+        matches!(self.account(address), Ok(Some(_)))
     }
 
     fn basic(&self, address: H160) -> Basic {
-        self.get_account(address)
+        self.account(address)
             .map(|v| match v {
                 Some(acc) => Basic {
                     balance: acc.balance,
@@ -217,33 +284,17 @@ impl<'vicinity, T: KeyValueDB> Backend for CrystalBackend<'vicinity, T> {
     }
 
     fn code(&self, address: H160) -> Vec<u8> {
-        let code_hash = match self.get_account(address) {
-            Ok(acc) => match acc {
-                Some(acc) => acc.code_hash,
-                None => return vec![],
-            },
-            Err(_) => return vec![],
-        };
-
-        match self.state.get(0, code_hash.as_bytes()) {
-            Ok(v) => match v {
-                Some(code) => code,
-                None => vec![],
-            },
-            Err(_) => vec![],
-        }
+        self.read_code(address).unwrap_or_else(|err| {
+            error!("read code of {:?} error: {}", address, err);
+            vec![]
+        })
     }
 
     fn storage(&self, address: H160, index: H256) -> H256 {
-        let key = Self::gen_slot_key(address, index);
-
-        match self.state.get(0, key.as_ref()) {
-            Ok(v) => match v {
-                Some(data) => H256::from_slice(data.as_ref()),
-                None => H256::zero(),
-            },
-            Err(_) => H256::zero(),
-        }
+        self.read_storage(address, index).unwrap_or_else(|err| {
+            error!("read storage of {:?} error: {}", address, err);
+            H256::zero()
+        })
     }
 
     fn original_storage(&self, address: H160, index: H256) -> Option<H256> {
@@ -251,7 +302,7 @@ impl<'vicinity, T: KeyValueDB> Backend for CrystalBackend<'vicinity, T> {
     }
 }
 
-impl<'vicinity, T: KeyValueDB> ApplyBackend for CrystalBackend<'vicinity, T> {
+impl<'vicinity, 'db, T: KeyValueDB + ?Sized> ApplyBackend for CrystalBackend<'vicinity, 'db, T> {
     fn apply<A, I, L>(&mut self, values: A, logs: L, delete_empty: bool)
     where
         A: IntoIterator<Item = Apply<I>>,
@@ -259,7 +310,7 @@ impl<'vicinity, T: KeyValueDB> ApplyBackend for CrystalBackend<'vicinity, T> {
         L: IntoIterator<Item = Log>,
     {
         if let Err(err) = self.apply(values, logs, delete_empty) {
-            error!("commit status into db error: {}", err);
+            error!("apply state changes error: {}", err);
         }
     }
 }
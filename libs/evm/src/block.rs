@@ -0,0 +1,282 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use ethereum_types::{H160, H256, U256};
+use evm::backend::{Apply, Backend};
+use evm::executor::stack::{StackExecutor, StackState, StackSubstateMetadata};
+use kvdb::{DBTransaction, KeyValueDB};
+use trie::OverlayDB;
+
+use crate::backend::{CrystalBackend, COL_STATE, STATE_ROOT_KEY};
+use crate::stack::CrystalStackState;
+use crate::{pruning, Account, ExecutionInfo, Executive, MemoryAccount, Vicinity};
+
+/// Fields of a transaction executed by a `BlockExecution`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransactionArgs {
+    /// Sender of the transaction.
+    pub caller: H160,
+    pub value: U256,
+    /// Input of a call or init code of a contract creation.
+    pub data: Vec<u8>,
+    pub gas_limit: u64,
+    pub max_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub nonce: Option<U256>,
+    pub access_list: Vec<(H160, Vec<H256>)>,
+}
+
+/// State changes of the transactions of one block.
+///
+/// Transactions execute on top of the state the execution started from, each
+/// one seeing the changes of the previous ones. The changes are kept in
+/// memory until `commit` writes them in a single database transaction;
+/// dropping the execution discards them, as when the block is rejected.
+pub struct BlockExecution<'a, T> {
+    executive: &'a Executive<T>,
+    /// Root the execution started from.
+    parent: H256,
+    db: OverlayDB<'a, T>,
+    root: H256,
+    /// Height and hash of the block the changes are journaled for.
+    block: Option<(u64, H256)>,
+}
+
+impl<'a, T: KeyValueDB> BlockExecution<'a, T> {
+    pub(crate) fn new(executive: &'a Executive<T>, root: H256) -> Self {
+        Self {
+            executive,
+            parent: root,
+            db: OverlayDB::new(&*executive.db, COL_STATE),
+            root,
+            block: None,
+        }
+    }
+
+    /// Journals the changes as those of the block `hash` at `height`, for
+    /// `Executive::prune` to release them once the block is pruned. The
+    /// changes of executions never journaled are kept for good.
+    pub fn journal(&mut self, height: u64, hash: H256) {
+        self.block = Some((height, hash));
+    }
+
+    /// Root of the state trie after the transactions executed so far.
+    pub fn state_root(&self) -> H256 {
+        self.root
+    }
+
+    /// Executes a call of `target` on top of the previous transactions.
+    pub fn call(
+        &mut self,
+        target: H160,
+        args: TransactionArgs,
+        _is_transactional: bool,
+        validate: bool,
+        vicinity: Vicinity,
+    ) -> Result<ExecutionInfo<Vec<u8>>> {
+        let backend = self.backend(&vicinity);
+        if validate {
+            if let Err(e) = check_nonce(&backend, &args) {
+                self.db = backend.into_db();
+                return Err(e);
+            }
+        }
+        let metadata = StackSubstateMetadata::new(args.gas_limit, &self.executive.config);
+        let state = CrystalStackState::new(metadata, &backend);
+        let mut executor = StackExecutor::new_with_precompiles(
+            state,
+            &self.executive.config,
+            &self.executive.precompile,
+        );
+        let (reason, retv) = executor.transact_call(
+            args.caller,
+            target,
+            args.value,
+            args.data,
+            args.gas_limit,
+            args.access_list,
+        );
+        // let gas = executor.gas();
+        let used_gas = U256::from(executor.used_gas());
+        // let actual_fee = executor.fee(tot)
+
+        let mut state = executor.into_state();
+        if validate {
+            bump_nonce(&mut state, &args.caller, args.nonce);
+        }
+        let (value, logs) = state.deconstruct();
+        self.apply(backend, value)?;
+
+        Ok(ExecutionInfo {
+            exit_reason: reason,
+            value: retv,
+            used_gas,
+            logs: Vec::from_iter(logs), //logs, //state.substate.logs(),
+        })
+    }
+
+    /// Executes a contract creation on top of the previous transactions.
+    pub fn create(
+        &mut self,
+        args: TransactionArgs,
+        _is_transactional: bool,
+        validate: bool,
+        vicinity: Vicinity,
+    ) -> Result<ExecutionInfo<H160>> {
+        let backend = self.backend(&vicinity);
+        if validate {
+            if let Err(e) = check_nonce(&backend, &args) {
+                self.db = backend.into_db();
+                return Err(e);
+            }
+        }
+        let metadata = StackSubstateMetadata::new(args.gas_limit, &self.executive.config);
+        let state = CrystalStackState::new(metadata, &backend);
+        let mut executor = StackExecutor::new_with_precompiles(
+            state,
+            &self.executive.config,
+            &self.executive.precompile,
+        );
+        let address = executor.create_address(evm::CreateScheme::Legacy {
+            caller: args.caller,
+        });
+
+        let (reason, _) = executor.transact_create(
+            args.caller,
+            args.value,
+            args.data,
+            args.gas_limit,
+            args.access_list,
+        );
+        let used_gas = U256::from(executor.used_gas());
+
+        let mut state = executor.into_state();
+        if validate {
+            bump_nonce(&mut state, &args.caller, args.nonce);
+        }
+        let (value, logs) = state.deconstruct();
+        self.apply(backend, value)?;
+
+        Ok(ExecutionInfo {
+            exit_reason: reason,
+            value: address,
+            used_gas,
+            logs: Vec::from_iter(logs), //logs, //state.substate.logs(),
+        })
+    }
+
+    /// Writes accounts into the state, replacing existing ones.
+    pub fn insert_accounts(&mut self, accounts: BTreeMap<H160, MemoryAccount>) -> Result<()> {
+        let vicinity = Vicinity::default();
+        let backend = self.backend(&vicinity);
+        let values = accounts
+            .into_iter()
+            .map(|(address, account)| Apply::Modify {
+                address,
+                basic: Account {
+                    balance: account.balance,
+                    nonce: account.nonce,
+                },
+                code: Some(account.code).filter(|code| !code.is_empty()),
+                storage: account.storage,
+                reset_storage: true,
+            })
+            .collect::<Vec<_>>();
+        self.apply(backend, values)
+    }
+
+    /// Atomically writes the changes and makes their root the latest state.
+    ///
+    /// Fails if another execution was committed since this one started.
+    pub fn commit(self) -> Result<H256> {
+        let executive = self.executive;
+        let _lock = executive.lock.lock().unwrap();
+        let latest = executive.state_root();
+        if latest != self.parent {
+            return Err(anyhow!(
+                "state moved from {:?} to {:?} during block execution",
+                self.parent,
+                latest
+            ));
+        }
+
+        let root = self.root;
+        let mut tx = self.changes()?;
+        tx.put(COL_STATE, STATE_ROOT_KEY, root.as_bytes());
+        executive.db.write(tx)?;
+        executive.set_root(root);
+        Ok(root)
+    }
+
+    /// Atomically writes the changes, leaving the latest state as it is, as
+    /// for blocks off the canonical chain. `Executive::reset` moves to them.
+    pub fn write(self) -> Result<H256> {
+        let executive = self.executive;
+        let _lock = executive.lock.lock().unwrap();
+        let root = self.root;
+        executive.db.write(self.changes()?)?;
+        Ok(root)
+    }
+
+    /// Database transaction writing the changes along with their journal.
+    /// The changes of a block journaled already are in the database, and
+    /// counting their references again would keep them from being pruned.
+    fn changes(self) -> Result<DBTransaction> {
+        let db = &*self.executive.db;
+        let mut tx = DBTransaction::new();
+        if let Some((_, hash)) = self.block {
+            if pruning::is_journaled(db, &hash)? {
+                return Ok(tx);
+            }
+        }
+        let journal = pruning::commit(db, self.db.into_changes(), &mut tx)?;
+        if let Some((height, hash)) = self.block {
+            pruning::journal(db, height, &hash, &journal, &mut tx)?;
+        }
+        Ok(tx)
+    }
+
+    /// Moves the overlay into a backend at the current root, `apply` gives
+    /// it back.
+    fn backend<'v>(&mut self, vicinity: &'v Vicinity) -> CrystalBackend<'v, 'a, T> {
+        let db = std::mem::replace(&mut self.db, OverlayDB::new(&*self.executive.db, COL_STATE));
+        CrystalBackend::new(vicinity, db, self.root)
+    }
+
+    /// Applies `values` to the backend and takes the overlay back from it.
+    ///
+    /// On failure the changes of the transaction are partially written, so
+    /// the execution must be dropped.
+    fn apply<A, I>(&mut self, mut backend: CrystalBackend<'_, 'a, T>, values: A) -> Result<()>
+    where
+        A: IntoIterator<Item = Apply<I>>,
+        I: IntoIterator<Item = (H256, H256)>,
+    {
+        let applied = backend.apply(values, Vec::new(), false);
+        self.root = backend.root();
+        self.db = backend.into_db();
+        applied
+    }
+}
+
+/// Fails unless the nonce of the transaction is the one of its sender.
+fn check_nonce<B: Backend>(backend: &B, args: &TransactionArgs) -> Result<()> {
+    match args.nonce {
+        Some(nonce) if nonce != backend.basic(args.caller).nonce => Err(anyhow!(
+            "invalid nonce {} of {:?}, expected {}",
+            nonce,
+            args.caller,
+            backend.basic(args.caller).nonce
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Bumps the nonce of the sender when the EVM left it as is, which it does
+/// for a transaction failing before execution, so that the transaction
+/// can't be included again.
+fn bump_nonce<'config, S: StackState<'config>>(state: &mut S, caller: &H160, nonce: Option<U256>) {
+    if Some(state.basic(*caller).nonce) == nonce {
+        state.inc_nonce(*caller);
+    }
+}
@@ -1,28 +1,35 @@
-use kvdb::KeyValueDB;
+use kvdb::{DBTransaction, KeyValueDB};
 #[cfg(feature = "std")]
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::marker::PhantomData;
 use std::rc::Rc;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
+use trie::OverlayDB;
 
 use crate::backend::CrystalBackend;
+pub use crate::backend::{COL_STATE, STATE_ROOT_KEY};
+pub use crate::block::{BlockExecution, TransactionArgs};
+pub use crate::pruning::PruneStats;
 use crate::stack::CrystalStackState;
-use anyhow::Result;
+pub use crate::state::StateView;
+use anyhow::{anyhow, Result};
 use codec::{Decode, Encode};
 use ethereum_types::{H160, H256, U256};
-use evm::backend::{ApplyBackend, MemoryAccount, MemoryBackend, MemoryVicinity};
+use evm::backend::{ApplyBackend, Backend, MemoryBackend, MemoryVicinity};
 use evm::executor::stack::{MemoryStackState, PrecompileFn, StackExecutor, StackSubstateMetadata};
 use evm::Context;
 pub use evm::{
-    backend::{Basic as Account, Log},
-    Config, ExitReason,
+    backend::{Basic as Account, Log, MemoryAccount},
+    Config, ExitReason, ExitSucceed,
 };
 
 mod backend;
-mod key_mapping;
+mod block;
+mod pruning;
 mod stack;
+mod state;
 
 #[derive(Clone, Eq, PartialEq, Default, Debug, Encode, Decode)]
 /// External input from the transaction.
@@ -59,6 +66,11 @@ pub struct ExecutionInfo<T> {
 
 pub struct Executive<T> {
     db: Arc<T>,
+    /// Root of the latest state.
+    root: RwLock<H256>,
+    /// Serializes reference count updates and moves of the latest state
+    /// with pruning.
+    lock: Mutex<()>,
     config: Config,
     precompile: BTreeMap<H160, PrecompileFn>,
     _marker: PhantomData<T>,
@@ -66,8 +78,14 @@ pub struct Executive<T> {
 
 impl<T: KeyValueDB> Executive<T> {
     pub fn new(db: Arc<T>) -> Self {
+        let root = match db.get(COL_STATE, STATE_ROOT_KEY) {
+            Ok(Some(root)) if root.len() == 32 => H256::from_slice(&root),
+            _ => trie::EMPTY_ROOT,
+        };
         Self {
             db: db,
+            root: RwLock::new(root),
+            lock: Mutex::new(()),
             config: Config::berlin(),
             precompile: Default::default(),
             _marker: PhantomData::default(),
@@ -76,80 +94,103 @@ impl<T: KeyValueDB> Executive<T> {
 }
 
 impl<T: KeyValueDB> Executive<T> {
-    pub fn call(
-        &self,
-        source: H160,
-        target: H160,
-        input: Vec<u8>,
-        value: U256,
-        gas_limit: u64,
-        _max_fee_per_gas: Option<U256>,
-        _max_priority_fee_per_gas: Option<U256>,
-        _nonce: Option<U256>,
-        _access_list: Vec<(H160, Vec<H256>)>,
-        _is_transactional: bool,
-        validate: bool,
-        vicinity: Vicinity,
-    ) -> Result<ExecutionInfo<Vec<u8>>> {
-        if validate {
-            // todo validate args
+    /// Root of the latest state trie.
+    pub fn state_root(&self) -> H256 {
+        *self.root.read().unwrap()
+    }
+
+    fn set_root(&self, root: H256) {
+        *self.root.write().unwrap() = root;
+    }
+
+    fn backend<'a>(&'a self, vicinity: &'a Vicinity) -> CrystalBackend<'a, 'a, T> {
+        CrystalBackend::new(
+            vicinity,
+            OverlayDB::new(&*self.db, COL_STATE),
+            self.state_root(),
+        )
+    }
+
+    /// Starts executing a block on top of the latest state.
+    pub fn begin_block(&self) -> BlockExecution<'_, T> {
+        BlockExecution::new(self, self.state_root())
+    }
+
+    /// Starts executing a block on top of the state at `root`, which must
+    /// be retained.
+    pub fn begin_block_at(&self, root: H256) -> Result<BlockExecution<'_, T>> {
+        self.check_retained(&root)?;
+        Ok(BlockExecution::new(self, root))
+    }
+
+    /// Makes the retained state at `root` the latest one, as when the
+    /// canonical chain is reorganized.
+    pub fn reset(&self, root: H256) -> Result<()> {
+        self.check_retained(&root)?;
+        let _lock = self.lock.lock().unwrap();
+        let mut tx = DBTransaction::new();
+        tx.put(COL_STATE, STATE_ROOT_KEY, root.as_bytes());
+        self.db.write(tx)?;
+        self.set_root(root);
+        Ok(())
+    }
+
+    fn check_retained(&self, root: &H256) -> Result<()> {
+        if *root != trie::EMPTY_ROOT && self.db.get(COL_STATE, root.as_bytes())?.is_none() {
+            return Err(anyhow!("state {:?} is not retained", root));
         }
+        Ok(())
+    }
+
+    /// Read-only view of the state at `root`, which may be the state of any
+    /// retained block.
+    pub fn state_at(&self, root: H256) -> StateView<T> {
+        StateView::new(self.db.clone(), root)
+    }
+
+    /// Read-only view of the latest state.
+    pub fn latest(&self) -> StateView<T> {
+        self.state_at(self.state_root())
+    }
+
+    /// Returns the balance and nonce of `address`.
+    pub fn basic(&self, address: H160) -> Account {
+        self.backend(&Vicinity::default()).basic(address)
+    }
+
+    pub fn code(&self, address: H160) -> Vec<u8> {
+        self.backend(&Vicinity::default()).code(address)
+    }
+
+    pub fn storage(&self, address: H160, index: H256) -> H256 {
+        self.backend(&Vicinity::default()).storage(address, index)
+    }
 
-        let mut backend = CrystalBackend::new(&vicinity, self.db.clone());
-        let metadata = StackSubstateMetadata::new(gas_limit, &self.config);
-        let state = CrystalStackState::new(metadata, &backend);
-        let mut executor =
-            StackExecutor::new_with_precompiles(state, &self.config, &self.precompile);
-        let (reason, retv) =
-            executor.transact_call(source, target, value, input, gas_limit, vec![]);
-        // let gas = executor.gas();
-        let used_gas = U256::from(executor.used_gas());
-        // let actual_fee = executor.fee(tot)
-
-        let (value, logs) = executor.into_state().deconstruct();
-        backend.apply(value, vec![], false);
-
-        Ok(ExecutionInfo {
-            exit_reason: reason,
-            value: retv,
-            used_gas,
-            logs: Vec::from_iter(logs), //logs, //state.substate.logs(),
-        })
+    /// Writes accounts into the state, replacing existing ones.
+    pub fn insert_accounts(&self, accounts: BTreeMap<H160, MemoryAccount>) -> Result<H256> {
+        let mut block = self.begin_block();
+        block.insert_accounts(accounts)?;
+        block.commit()
     }
 
-    pub fn create(
+    /// Deletes the trie nodes and code released by the journaled blocks up
+    /// to and including `height`, after which the state of the blocks below
+    /// it is no longer readable. `canonical` gives the hash of the canonical
+    /// block at a height, whose journal is applied; the state of its
+    /// siblings is released instead.
+    pub fn prune(
         &self,
-        source: H160,
-        init: Vec<u8>,
-        value: U256,
-        gas_limit: u64,
-        _max_fee_per_gas: Option<U256>,
-        _max_priority_fee_per_gas: Option<U256>,
-        _nonce: Option<U256>,
-        access_list: Vec<(H160, Vec<H256>)>,
-        _is_transactional: bool,
-        _validate: bool,
-        vicinity: Vicinity,
-    ) -> Result<ExecutionInfo<H160>> {
-        let mut backend = CrystalBackend::new(&vicinity, self.db.clone());
-        let metadata = StackSubstateMetadata::new(gas_limit, &self.config);
-        let state = CrystalStackState::new(metadata, &backend);
-        let mut executor =
-            StackExecutor::new_with_precompiles(state, &self.config, &self.precompile);
-        let address = executor.create_address(evm::CreateScheme::Legacy { caller: source });
-
-        let (reason, _) = executor.transact_create(source, value, init, gas_limit, access_list);
-        let used_gas = U256::from(executor.used_gas());
-
-        let (value, logs) = executor.into_state().deconstruct();
-        backend.apply(value, vec![], false);
-
-        Ok(ExecutionInfo {
-            exit_reason: reason,
-            value: address,
-            used_gas,
-            logs: Vec::from_iter(logs), //logs, //state.substate.logs(),
-        })
+        height: u64,
+        canonical: impl Fn(u64) -> Result<Option<H256>>,
+    ) -> Result<PruneStats> {
+        let _lock = self.lock.lock().unwrap();
+        pruning::prune(&*self.db, height + 1, canonical)
+    }
+}
+
+impl<T: KeyValueDB> txpool::StateReader for Executive<T> {
+    fn nonce(&self, address: &H160) -> U256 {
+        self.basic(*address).nonce
     }
 }
 
@@ -282,7 +323,7 @@ mod tests {
     fn it_works() {
         // let result = call(2, 2);
         // assert_eq!(result, 4);
-        let kvdb = Arc::new(kvdb_memorydb::create(0));
+        let kvdb = Arc::new(kvdb_memorydb::create(1));
         let vicinity = Vicinity {
             gas_price: Default::default(),
             origin: Default::default(),
@@ -308,21 +349,22 @@ mod tests {
         let code = hex::decode(code).unwrap();
         let source = H160::from_str("0x0000000000000000000000000000000000000001").unwrap();
 
-        let rev = exec
+        let mut block = exec.begin_block();
+        let rev = block
             .create(
-                source,
-                code,
-                U256::from(10000000),
-                10000000,
-                None,
-                None,
-                None,
-                vec![],
+                TransactionArgs {
+                    caller: source,
+                    value: U256::from(10000000),
+                    data: code,
+                    gas_limit: 10000000,
+                    ..Default::default()
+                },
                 true,
                 false,
                 vicinity.clone(),
             )
             .unwrap();
+        block.commit().unwrap();
         println!(
             "code address: {:?}, reason: {:?}",
             rev.value, rev.exit_reason
@@ -333,22 +375,410 @@ mod tests {
         let data =
             hex::decode("60fe47b1000000000000000000000000000000000000000000000000000000000000002a")
                 .unwrap();
-        let rev2 = exec2
+        let mut block = exec2.begin_block();
+        let rev2 = block
             .call(
-                source,
                 rev.value,
-                data,
-                U256::from(10000000),
-                10000000,
-                None,
-                None,
-                None,
-                vec![],
+                TransactionArgs {
+                    caller: source,
+                    value: U256::from(10000000),
+                    data,
+                    gas_limit: 10000000,
+                    ..Default::default()
+                },
                 true,
                 false,
                 vicinity,
             )
             .unwrap();
+        block.commit().unwrap();
         println!("{:?}", rev2.exit_reason);
     }
+
+    #[test]
+    fn state_reader_reads_account_nonce() {
+        use txpool::StateReader;
+
+        let exec = Executive::new(Arc::new(kvdb_memorydb::create(1)));
+        let source = H160::from_low_u64_be(1);
+        assert_eq!(exec.nonce(&source), U256::zero());
+
+        let mut block = exec.begin_block();
+        block
+            .create(
+                TransactionArgs {
+                    caller: source,
+                    data: vec![0x60, 0x00],
+                    gas_limit: 1000000,
+                    ..Default::default()
+                },
+                true,
+                false,
+                Vicinity::default(),
+            )
+            .unwrap();
+        block.commit().unwrap();
+        assert_eq!(exec.nonce(&source), U256::one());
+    }
+
+    #[test]
+    fn commits_state_to_trie_root() {
+        let db = Arc::new(kvdb_memorydb::create(1));
+        let exec = Executive::new(db.clone());
+        assert_eq!(exec.state_root(), trie::EMPTY_ROOT);
+
+        let (funded, contract) = (H160::repeat_byte(1), H160::repeat_byte(2));
+        let slot = H256::from_low_u64_be(3);
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            funded,
+            MemoryAccount {
+                nonce: U256::one(),
+                balance: U256::from(1000),
+                storage: BTreeMap::new(),
+                code: vec![],
+            },
+        );
+        accounts.insert(
+            contract,
+            MemoryAccount {
+                nonce: U256::zero(),
+                balance: U256::zero(),
+                storage: BTreeMap::from([(slot, H256::from_low_u64_be(42))]),
+                code: vec![0x60, 0x00],
+            },
+        );
+        exec.insert_accounts(accounts).unwrap();
+
+        let storage_root = ethereum::util::sec_trie_root([(slot, rlp::encode(&U256::from(42)))]);
+        let expected = ethereum::util::sec_trie_root([
+            (
+                funded,
+                rlp::encode(&ethereum::Account {
+                    nonce: U256::one(),
+                    balance: U256::from(1000),
+                    storage_root: trie::EMPTY_ROOT,
+                    code_hash: keccak_hash::KECCAK_EMPTY,
+                }),
+            ),
+            (
+                contract,
+                rlp::encode(&ethereum::Account {
+                    nonce: U256::zero(),
+                    balance: U256::zero(),
+                    storage_root,
+                    code_hash: keccak_hash::keccak([0x60, 0x00]),
+                }),
+            ),
+        ]);
+        assert_eq!(exec.state_root(), expected);
+        assert_eq!(exec.code(contract), vec![0x60, 0x00]);
+        assert_eq!(exec.storage(contract, slot), H256::from_low_u64_be(42));
+
+        let proof = exec.latest().account_proof(contract).unwrap();
+        let account =
+            trie::verify_proof(&expected, keccak_hash::keccak(contract).as_bytes(), &proof)
+                .unwrap()
+                .unwrap();
+        assert_eq!(
+            rlp::decode::<ethereum::Account>(&account)
+                .unwrap()
+                .storage_root,
+            storage_root
+        );
+        let proof = exec.latest().storage_proof(contract, slot).unwrap();
+        assert_eq!(
+            trie::verify_proof(&storage_root, keccak_hash::keccak(slot).as_bytes(), &proof)
+                .unwrap(),
+            Some(rlp::encode(&U256::from(42)).to_vec())
+        );
+
+        // The latest root is restored from the database.
+        assert_eq!(Executive::new(db).state_root(), expected);
+    }
+
+    #[test]
+    fn reads_state_at_previous_roots() {
+        let exec = Executive::new(Arc::new(kvdb_memorydb::create(1)));
+        let address = H160::repeat_byte(1);
+        let account = |balance: u64| MemoryAccount {
+            nonce: U256::zero(),
+            balance: U256::from(balance),
+            storage: BTreeMap::from([(H256::zero(), H256::from_low_u64_be(balance))]),
+            code: vec![],
+        };
+
+        exec.insert_accounts(BTreeMap::from([(address, account(1))]))
+            .unwrap();
+        let first = exec.state_root();
+        exec.insert_accounts(BTreeMap::from([(address, account(2))]))
+            .unwrap();
+        assert_ne!(exec.state_root(), first);
+
+        let old = exec.state_at(first);
+        assert_eq!(old.account(address).unwrap().unwrap().balance, U256::one());
+        assert_eq!(
+            old.storage(address, H256::zero()).unwrap(),
+            H256::from_low_u64_be(1)
+        );
+        assert_eq!(
+            exec.latest().account(address).unwrap().unwrap().balance,
+            U256::from(2)
+        );
+        assert!(exec
+            .state_at(H256::repeat_byte(9))
+            .account(address)
+            .is_err());
+    }
+
+    #[test]
+    fn prunes_released_state() {
+        let db = Arc::new(kvdb_memorydb::create(1));
+        let exec = Executive::new(db.clone());
+        let address = H160::repeat_byte(1);
+        let account = |balance: u64| MemoryAccount {
+            nonce: U256::zero(),
+            balance: U256::from(balance),
+            storage: BTreeMap::from([(H256::zero(), H256::from_low_u64_be(balance))]),
+            code: vec![balance as u8; 40],
+        };
+
+        fn canonical(height: u64) -> Result<Option<H256>> {
+            Ok(Some(H256::from_low_u64_be(height)))
+        }
+
+        let mut roots = vec![];
+        for height in 0..4u64 {
+            let mut block = exec.begin_block();
+            block
+                .insert_accounts(BTreeMap::from([(address, account(height + 1))]))
+                .unwrap();
+            block.journal(height, H256::from_low_u64_be(height));
+            roots.push(block.commit().unwrap());
+        }
+        let size = || db.iter(COL_STATE).count();
+        let before = size();
+
+        // Block 2 released the state of block 1 only.
+        let stats = exec.prune(2, canonical).unwrap();
+        assert_eq!(stats.blocks, 3);
+        assert!(stats.nodes > 0 && stats.bytes > 0);
+        assert!(size() < before);
+        assert!(exec.state_at(roots[0]).account(address).is_err());
+        assert!(exec.state_at(roots[1]).code(address).is_err());
+        let retained = exec.state_at(roots[2]);
+        assert_eq!(retained.code(address).unwrap(), vec![3; 40]);
+        assert_eq!(
+            retained.storage(address, H256::zero()).unwrap(),
+            H256::from_low_u64_be(3)
+        );
+        assert_eq!(exec.basic(address).balance, U256::from(4));
+
+        // Pruning resumes from the last pruned block.
+        assert_eq!(exec.prune(2, canonical).unwrap(), PruneStats::default());
+        assert_eq!(exec.prune(3, canonical).unwrap().blocks, 1);
+        assert_eq!(exec.code(address), vec![4; 40]);
+    }
+
+    #[test]
+    fn prunes_siblings_off_the_canonical_chain() {
+        let db = Arc::new(kvdb_memorydb::create(1));
+        let exec = Executive::new(db.clone());
+        let address = H160::repeat_byte(1);
+        let accounts = |balance: u64, slot: u64| {
+            let account = MemoryAccount {
+                nonce: U256::zero(),
+                balance: U256::from(balance),
+                storage: BTreeMap::from([
+                    (H256::zero(), H256::from_low_u64_be(slot)),
+                    (H256::repeat_byte(1), H256::from_low_u64_be(1)),
+                ]),
+                code: vec![1; 40],
+            };
+            BTreeMap::from([(address, account)])
+        };
+        let (genesis, canonical, sibling) = (
+            H256::repeat_byte(0xa0),
+            H256::repeat_byte(0xa1),
+            H256::repeat_byte(0xb1),
+        );
+
+        let mut block = exec.begin_block();
+        block.insert_accounts(accounts(1, 1)).unwrap();
+        block.journal(0, genesis);
+        let parent = block.commit().unwrap();
+
+        // Two blocks at height 1 change different parts of the parent state.
+        let mut block = exec.begin_block_at(parent).unwrap();
+        block.insert_accounts(accounts(2, 1)).unwrap();
+        block.journal(1, canonical);
+        let kept = block.commit().unwrap();
+        let mut block = exec.begin_block_at(parent).unwrap();
+        block.insert_accounts(accounts(1, 2)).unwrap();
+        block.journal(1, sibling);
+        let dropped = block.write().unwrap();
+
+        // Writing a journaled block again changes nothing.
+        let state = || {
+            db.iter(COL_STATE)
+                .map(|item| item.unwrap())
+                .collect::<Vec<_>>()
+        };
+        let before = state();
+        let mut block = exec.begin_block_at(parent).unwrap();
+        block.insert_accounts(accounts(1, 2)).unwrap();
+        block.journal(1, sibling);
+        assert_eq!(block.write().unwrap(), dropped);
+        assert_eq!(state(), before);
+
+        let stats = exec
+            .prune(1, |height| Ok(Some([genesis, canonical][height as usize])))
+            .unwrap();
+        assert_eq!(stats.blocks, 3);
+        assert!(exec.state_at(parent).account(address).is_err());
+        assert!(exec.state_at(dropped).account(address).is_err());
+
+        // The canonical state is whole, including what the sibling released.
+        let retained = exec.state_at(kept);
+        assert_eq!(
+            retained.account(address).unwrap().unwrap().balance,
+            U256::from(2)
+        );
+        assert_eq!(retained.code(address).unwrap(), vec![1; 40]);
+        for (slot, value) in [(H256::zero(), 1), (H256::repeat_byte(1), 1)] {
+            assert_eq!(
+                retained.storage(address, slot).unwrap(),
+                H256::from_low_u64_be(value)
+            );
+        }
+    }
+
+    #[test]
+    fn commits_block_state_atomically() {
+        use txpool::StateReader;
+
+        let exec = Executive::new(Arc::new(kvdb_memorydb::create(1)));
+        let source = H160::repeat_byte(1);
+        let create = |block: &mut BlockExecution<_>| {
+            block
+                .create(
+                    TransactionArgs {
+                        caller: source,
+                        data: vec![0x60, 0x00],
+                        gas_limit: 1000000,
+                        ..Default::default()
+                    },
+                    true,
+                    false,
+                    Vicinity::default(),
+                )
+                .unwrap()
+        };
+
+        // Each transaction sees the changes of the previous ones, while the
+        // latest state is left untouched until the block is committed.
+        let mut block = exec.begin_block();
+        let first = create(&mut block);
+        let second = create(&mut block);
+        assert_ne!(first.value, second.value);
+        assert_ne!(block.state_root(), exec.state_root());
+        assert_eq!(exec.nonce(&source), U256::zero());
+
+        // Dropping the block discards its changes.
+        drop(block);
+        assert_eq!(exec.state_root(), trie::EMPTY_ROOT);
+        assert_eq!(exec.nonce(&source), U256::zero());
+
+        let mut block = exec.begin_block();
+        create(&mut block);
+        create(&mut block);
+        let root = block.state_root();
+        assert_eq!(block.commit().unwrap(), root);
+        assert_eq!(exec.state_root(), root);
+        assert_eq!(exec.nonce(&source), U256::from(2));
+
+        // A block executed on top of a state that moved on is refused.
+        let mut stale = exec.begin_block();
+        create(&mut stale);
+        let mut block = exec.begin_block();
+        create(&mut block);
+        block.commit().unwrap();
+        assert!(stale.commit().is_err());
+        assert_eq!(exec.nonce(&source), U256::from(3));
+    }
+
+    #[test]
+    fn fails_to_apply_changes_to_missing_state() {
+        let db = Arc::new(kvdb_memorydb::create(1));
+        let exec = Executive::new(db.clone());
+        let source = H160::repeat_byte(1);
+        let account = MemoryAccount {
+            nonce: U256::zero(),
+            balance: U256::from(1000),
+            storage: BTreeMap::new(),
+            code: vec![],
+        };
+        exec.insert_accounts(BTreeMap::from([(source, account)]))
+            .unwrap();
+
+        // Drops the trie nodes under the state root.
+        let mut tx = DBTransaction::new();
+        for item in db.iter(COL_STATE) {
+            let (key, _) = item.unwrap();
+            if key.as_ref() != STATE_ROOT_KEY {
+                tx.delete(COL_STATE, &key);
+            }
+        }
+        db.write(tx).unwrap();
+
+        let mut block = exec.begin_block();
+        let args = TransactionArgs {
+            caller: source,
+            gas_limit: 1000000,
+            ..Default::default()
+        };
+        assert!(block
+            .create(args, true, false, Vicinity::default())
+            .is_err());
+    }
+
+    #[test]
+    fn executes_blocks_on_retained_states() {
+        let exec = Executive::new(Arc::new(kvdb_memorydb::create(1)));
+        let address = H160::repeat_byte(1);
+        let accounts = |balance: u64| {
+            let account = MemoryAccount {
+                nonce: U256::zero(),
+                balance: U256::from(balance),
+                storage: BTreeMap::new(),
+                code: vec![],
+            };
+            BTreeMap::from([(address, account)])
+        };
+        let parent = exec.insert_accounts(accounts(1)).unwrap();
+
+        let mut block = exec.begin_block_at(parent).unwrap();
+        block.insert_accounts(accounts(2)).unwrap();
+        let canonical = block.commit().unwrap();
+
+        // A sibling is written without moving the latest state.
+        let mut sibling = exec.begin_block_at(parent).unwrap();
+        sibling.insert_accounts(accounts(3)).unwrap();
+        let side = sibling.write().unwrap();
+        assert_eq!(exec.state_root(), canonical);
+        assert_eq!(
+            exec.state_at(side)
+                .account(address)
+                .unwrap()
+                .unwrap()
+                .balance,
+            U256::from(3)
+        );
+
+        exec.reset(side).unwrap();
+        assert_eq!(exec.state_root(), side);
+        assert_eq!(exec.basic(address).balance, U256::from(3));
+        assert!(exec.reset(H256::repeat_byte(9)).is_err());
+        assert!(exec.begin_block_at(H256::repeat_byte(9)).is_err());
+    }
 }
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use ethereum_types::H256;
+use kvdb::{DBTransaction, KeyValueDB};
+
+use crate::backend::COL_STATE;
+
+/// Prefix of the reference count of a trie node or contract code.
+const REFS_PREFIX: &[u8] = b"refs";
+/// Prefix of the journal of a block, by block hash.
+const JOURNAL_PREFIX: &[u8] = b"journal";
+/// Prefix of the hashes of the blocks journaled at a given height.
+const BLOCKS_PREFIX: &[u8] = b"blocks";
+/// Key of the height up to which journals have been pruned.
+const PRUNED_KEY: &[u8] = b"pruned";
+
+/// Data deleted by pruning.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PruneStats {
+    /// Number of block journals pruned, including those of blocks off the
+    /// canonical chain.
+    pub blocks: u64,
+    /// Number of trie nodes and codes deleted.
+    pub nodes: u64,
+    /// Size of the deleted values.
+    pub bytes: u64,
+}
+
+impl std::ops::AddAssign for PruneStats {
+    fn add_assign(&mut self, other: PruneStats) {
+        self.blocks += other.blocks;
+        self.nodes += other.nodes;
+        self.bytes += other.bytes;
+    }
+}
+
+fn refs_key(hash: &H256) -> Vec<u8> {
+    [REFS_PREFIX, hash.as_bytes()].concat()
+}
+
+fn journal_key(hash: &H256) -> Vec<u8> {
+    [JOURNAL_PREFIX, hash.as_bytes()].concat()
+}
+
+fn blocks_key(height: u64) -> Vec<u8> {
+    [BLOCKS_PREFIX, &height.to_be_bytes()].concat()
+}
+
+/// Values referenced and dereferenced by the state changes of a block, once
+/// per reference.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Journal {
+    pub inserted: Vec<H256>,
+    pub removed: Vec<H256>,
+}
+
+impl Journal {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = (self.inserted.len() as u32).to_be_bytes().to_vec();
+        for hash in self.inserted.iter().chain(&self.removed) {
+            bytes.extend_from_slice(hash.as_bytes());
+        }
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let corrupted = || anyhow!("corrupted state journal");
+        let (count, hashes) = bytes.split_at_checked(4).ok_or_else(corrupted)?;
+        let count = u32::from_be_bytes(count.try_into().map_err(|_| corrupted())?) as usize;
+        if hashes.len() % 32 != 0 || hashes.len() / 32 < count {
+            return Err(corrupted());
+        }
+        let mut hashes = hashes.chunks_exact(32).map(H256::from_slice);
+        Ok(Self {
+            inserted: hashes.by_ref().take(count).collect(),
+            removed: hashes.collect(),
+        })
+    }
+}
+
+/// Number of references to `hash`, `None` for values written before
+/// reference counting, which are never pruned.
+fn refs<T: KeyValueDB>(db: &T, hash: &H256) -> Result<Option<u32>> {
+    match db.get(COL_STATE, &refs_key(hash))? {
+        Some(bytes) => {
+            Ok(Some(u32::from_be_bytes(bytes[..].try_into().map_err(
+                |_| anyhow!("corrupted reference count of {:?}", hash),
+            )?)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Writes the net changes of an overlay: inserted values are stored and
+/// their references counted right away, while removed ones are returned to
+/// be journaled and only released once their block is pruned.
+pub(crate) fn commit<T: KeyValueDB>(
+    db: &T,
+    changes: Vec<(H256, Vec<u8>, i32)>,
+    tx: &mut DBTransaction,
+) -> Result<Journal> {
+    let mut journal = Journal::default();
+    for (hash, value, rc) in changes {
+        if rc > 0 {
+            let refs = refs(db, &hash)?.unwrap_or_default();
+            if refs == 0 {
+                tx.put_vec(COL_STATE, hash.as_bytes(), value);
+            }
+            tx.put(
+                COL_STATE,
+                &refs_key(&hash),
+                &(refs + rc as u32).to_be_bytes(),
+            );
+            journal
+                .inserted
+                .extend(std::iter::repeat_n(hash, rc as usize));
+        } else {
+            journal
+                .removed
+                .extend(std::iter::repeat_n(hash, rc.unsigned_abs() as usize));
+        }
+    }
+    Ok(journal)
+}
+
+/// Whether the state changes of the block `hash` were journaled, and so
+/// written already.
+pub(crate) fn is_journaled<T: KeyValueDB>(db: &T, hash: &H256) -> Result<bool> {
+    Ok(db.get(COL_STATE, &journal_key(hash))?.is_some())
+}
+
+/// Records the journal of the block `hash` at `height`, next to those of its
+/// siblings.
+pub(crate) fn journal<T: KeyValueDB>(
+    db: &T,
+    height: u64,
+    hash: &H256,
+    journal: &Journal,
+    tx: &mut DBTransaction,
+) -> Result<()> {
+    let key = blocks_key(height);
+    let mut blocks = db.get(COL_STATE, &key)?.unwrap_or_default();
+    blocks.extend_from_slice(hash.as_bytes());
+    tx.put_vec(COL_STATE, &key, blocks);
+    tx.put_vec(COL_STATE, &journal_key(hash), journal.encode());
+    Ok(())
+}
+
+/// Applies the journals of the blocks below `until`, deleting the values
+/// no longer referenced. Only the canonical block of a height, as given by
+/// `canonical`, releases the values it dereferenced; the other blocks
+/// release those they referenced instead, their state being abandoned.
+pub(crate) fn prune<T: KeyValueDB>(
+    db: &T,
+    until: u64,
+    canonical: impl Fn(u64) -> Result<Option<H256>>,
+) -> Result<PruneStats> {
+    let from = match db.get(COL_STATE, PRUNED_KEY)? {
+        Some(bytes) => u64::from_be_bytes(
+            bytes[..]
+                .try_into()
+                .map_err(|_| anyhow!("corrupted pruning height"))?,
+        ),
+        None => 0,
+    };
+
+    let mut stats = PruneStats::default();
+    for height in from..until {
+        stats += prune_block(db, height, canonical(height)?)?;
+    }
+    Ok(stats)
+}
+
+fn prune_block<T: KeyValueDB>(db: &T, height: u64, canonical: Option<H256>) -> Result<PruneStats> {
+    let mut stats = PruneStats::default();
+    let mut tx = DBTransaction::new();
+    if let Some(blocks) = db.get(COL_STATE, &blocks_key(height))? {
+        let mut released = HashMap::<H256, u32>::new();
+        for block in blocks.chunks_exact(32).map(H256::from_slice) {
+            let journal = match db.get(COL_STATE, &journal_key(&block))? {
+                Some(bytes) => Journal::decode(&bytes)?,
+                None => continue,
+            };
+            let hashes = if Some(block) == canonical {
+                journal.removed
+            } else {
+                journal.inserted
+            };
+            for hash in hashes {
+                *released.entry(hash).or_default() += 1;
+            }
+            tx.delete(COL_STATE, &journal_key(&block));
+            stats.blocks += 1;
+        }
+
+        for (hash, count) in released {
+            let refs = match refs(db, &hash)? {
+                Some(refs) => refs,
+                None => continue,
+            };
+            if refs > count {
+                tx.put(COL_STATE, &refs_key(&hash), &(refs - count).to_be_bytes());
+            } else {
+                if let Some(value) = db.get(COL_STATE, hash.as_bytes())? {
+                    stats.bytes += value.len() as u64;
+                }
+                stats.nodes += 1;
+                tx.delete(COL_STATE, hash.as_bytes());
+                tx.delete(COL_STATE, &refs_key(&hash));
+            }
+        }
+        tx.delete(COL_STATE, &blocks_key(height));
+    }
+    tx.put(COL_STATE, PRUNED_KEY, &(height + 1).to_be_bytes());
+    db.write(tx)?;
+
+    Ok(stats)
+}
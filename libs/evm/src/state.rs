@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use ethereum_types::{H160, H256};
+use kvdb::KeyValueDB;
+use trie::OverlayDB;
+
+use crate::backend::{CrystalBackend, COL_STATE};
+use crate::Vicinity;
+
+/// Read-only view of the state trie at a given root.
+///
+/// Reads fail if the nodes of the root are not in the database, which is
+/// the case for unknown and pruned states.
+pub struct StateView<T> {
+    db: Arc<T>,
+    root: H256,
+}
+
+impl<T: KeyValueDB> StateView<T> {
+    pub fn new(db: Arc<T>, root: H256) -> Self {
+        Self { db, root }
+    }
+
+    pub fn root(&self) -> H256 {
+        self.root
+    }
+
+    fn backend<'a>(&'a self, vicinity: &'a Vicinity) -> CrystalBackend<'a, 'a, T> {
+        CrystalBackend::new(vicinity, OverlayDB::new(&*self.db, COL_STATE), self.root)
+    }
+
+    /// Returns the state trie entry of `address`.
+    pub fn account(&self, address: H160) -> Result<Option<ethereum::Account>> {
+        self.backend(&Vicinity::default()).account(address)
+    }
+
+    pub fn code(&self, address: H160) -> Result<Vec<u8>> {
+        self.backend(&Vicinity::default()).read_code(address)
+    }
+
+    pub fn storage(&self, address: H160, index: H256) -> Result<H256> {
+        self.backend(&Vicinity::default())
+            .read_storage(address, index)
+    }
+
+    /// Proves the account of `address` against the state root.
+    pub fn account_proof(&self, address: H160) -> Result<Vec<Vec<u8>>> {
+        self.backend(&Vicinity::default()).account_proof(address)
+    }
+
+    /// Proves a storage slot of `address` against the account storage root.
+    pub fn storage_proof(&self, address: H160, index: H256) -> Result<Vec<Vec<u8>>> {
+        self.backend(&Vicinity::default())
+            .storage_proof(address, index)
+    }
+}
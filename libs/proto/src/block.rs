@@ -0,0 +1,15 @@
+use ethereum_types::H256;
+use protobuf::Message;
+
+use crate::BlockHeader;
+
+/// Hash identifying a block: keccak of its protobuf encoded header without
+/// the proof, which is only known once consensus sealed the block.
+pub fn block_hash(header: &BlockHeader) -> H256 {
+    let mut header = header.clone();
+    header.proof.clear();
+    let bytes = header
+        .write_to_bytes()
+        .expect("encoding a block header never fails");
+    H256::from(hashing::keccak_256(&bytes))
+}
@@ -0,0 +1,163 @@
+use std::convert::Infallible;
+use std::fmt;
+
+use ethereum::TransactionV2;
+use ethereum_types::H256;
+use rpc_core::types::{Receipt, RichBlock};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+
+use crate::{Block, BlockWithProof, InnerMessage, RichStatus};
+
+/// Channel a service answers a request on.
+pub type Reply<T> = oneshot::Sender<T>;
+
+/// Message received by a service whose requests are of type `R`.
+#[derive(Debug)]
+pub enum Message<R = Infallible> {
+    /// Message relayed from another node.
+    Inner(InnerMessage),
+    /// Request from another service of this node.
+    Request(R),
+    Close,
+}
+
+/// Requests served by the chain.
+#[derive(Debug)]
+pub enum ChainRequest {
+    /// Height of the best block.
+    BlockNumber(Reply<u64>),
+    /// Best block with the validators and the block interval in seconds.
+    Status(Reply<RichStatus>),
+    /// Status as of a block, as `Status` gives it for the best block.
+    BlockStatus(H256, Reply<Option<RichStatus>>),
+    /// Canonical block at a height, with its full transactions if the flag
+    /// is set or only their hashes otherwise.
+    BlockByNumber(u64, bool, Reply<Option<RichBlock>>),
+    BlockByHash(H256, bool, Reply<Option<RichBlock>>),
+    /// Receipt of a canonical transaction.
+    Receipt(H256, Reply<Option<Receipt>>),
+    /// Verifies and imports a block, replying with its hash or the reason it
+    /// was rejected.
+    SubmitBlock(Block, Reply<Result<H256, String>>),
+    /// Produces a block on top of the best block for consensus to propose.
+    ProposeBlock(Reply<Block>),
+    /// Verifies a block proposed by another node, replying with its hash or
+    /// the reason it is invalid.
+    VerifyBlock(Block, Reply<Result<H256, String>>),
+    /// Adds a block finalized by consensus, replying with its hash or the
+    /// reason it was rejected.
+    CommitBlock(BlockWithProof, Reply<Result<H256, String>>),
+    /// Moves the clock of produced blocks forward by a number of seconds,
+    /// replying with how far it is ahead of the system time.
+    IncreaseTime(u64, Reply<u64>),
+    /// Moves the clock of produced blocks to a timestamp in seconds.
+    SetTime(u64, Reply<()>),
+    /// State the consensus engine persisted last, if any.
+    ConsensusState(Reply<Option<Vec<u8>>>),
+    /// Stores the state of the consensus engine, replying once it is written.
+    SetConsensusState(Vec<u8>, Reply<()>),
+}
+
+/// Requests served by auth.
+#[derive(Debug)]
+pub enum AuthRequest {
+    /// Decodes an EIP-2718 encoded transaction and checks its chain id and
+    /// signature, replying with the transaction or the reason it is invalid.
+    VerifyTransaction(Vec<u8>, Reply<Result<TransactionV2, String>>),
+}
+
+/// Error of a request to a service that stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Closed(pub &'static str);
+
+impl fmt::Display for Closed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} service closed", self.0)
+    }
+}
+
+impl std::error::Error for Closed {}
+
+/// Senders to the services of the node.
+pub struct MessageBus {
+    pub jsonrpc_sender: Sender<Message>,
+    pub auth_sender: Sender<Message<AuthRequest>>,
+    pub chain_sender: Sender<Message<ChainRequest>>,
+}
+
+impl MessageBus {
+    /// Sends the request `build` makes around a reply channel to the chain
+    /// and waits for the reply, e.g. `bus.chain(ChainRequest::BlockNumber)`.
+    pub async fn chain<T>(
+        &self,
+        build: impl FnOnce(Reply<T>) -> ChainRequest,
+    ) -> Result<T, Closed> {
+        request(&self.chain_sender, "chain", build).await
+    }
+
+    /// Sends the request `build` makes around a reply channel to auth and
+    /// waits for the reply.
+    pub async fn auth<T>(&self, build: impl FnOnce(Reply<T>) -> AuthRequest) -> Result<T, Closed> {
+        request(&self.auth_sender, "auth", build).await
+    }
+
+    /// Asks every service to stop.
+    pub async fn close(&self) -> Result<(), Closed> {
+        self.auth_sender
+            .send(Message::Close)
+            .await
+            .map_err(|_| Closed("auth"))?;
+        self.jsonrpc_sender
+            .send(Message::Close)
+            .await
+            .map_err(|_| Closed("jsonrpc"))?;
+        self.chain_sender
+            .send(Message::Close)
+            .await
+            .map_err(|_| Closed("chain"))
+    }
+}
+
+async fn request<R, T>(
+    sender: &Sender<Message<R>>,
+    service: &'static str,
+    build: impl FnOnce(Reply<T>) -> R,
+) -> Result<T, Closed> {
+    let (reply, response) = oneshot::channel();
+    sender
+        .send(Message::Request(build(reply)))
+        .await
+        .map_err(|_| Closed(service))?;
+    response.await.map_err(|_| Closed(service))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc::channel;
+
+    #[tokio::test]
+    async fn replies_to_requests() {
+        let (chain_sender, mut chain) = channel(1);
+        let bus = MessageBus {
+            jsonrpc_sender: channel(1).0,
+            auth_sender: channel(1).0,
+            chain_sender,
+        };
+        tokio::spawn(async move {
+            while let Some(Message::Request(ChainRequest::BlockNumber(reply))) = chain.recv().await
+            {
+                let _ = reply.send(7);
+            }
+        });
+
+        assert_eq!(bus.chain(ChainRequest::BlockNumber).await, Ok(7));
+        assert_eq!(
+            bus.auth(|reply| AuthRequest::VerifyTransaction(vec![], reply))
+                .await
+                .unwrap_err(),
+            Closed("auth")
+        );
+    }
+}
@@ -0,0 +1,22 @@
+use ethereum_types::{H256, U64};
+use jsonrpsee::{core::RpcResult as Result, proc_macros::rpc};
+
+/// Controls of a development node sealing its own blocks.
+#[rpc(server)]
+#[async_trait]
+pub trait DevApi {
+    /// Seals a block of the ready transactions, even without any if
+    /// `create_empty` is set, returning its hash.
+    #[method(name = "engine_createBlock")]
+    async fn create_block(&self, create_empty: bool) -> Result<H256>;
+
+    /// Moves the clock of the next blocks forward by `seconds`, returning
+    /// how far in seconds it is ahead of the system time.
+    #[method(name = "evm_increaseTime")]
+    async fn increase_time(&self, seconds: u64) -> Result<u64>;
+
+    /// Seals a block, at `timestamp` if given, even without transactions,
+    /// returning `0x0`.
+    #[method(name = "evm_mine")]
+    async fn mine(&self, timestamp: Option<u64>) -> Result<U64>;
+}
@@ -1,5 +1,7 @@
 pub mod types;
 
+mod dev;
 mod eth;
 
+pub use self::dev::DevApiServer;
 pub use self::eth::EthApiServer;
@@ -0,0 +1,216 @@
+use std::borrow::Borrow;
+use std::ops::Range;
+
+use ethereum_types::H256;
+use rlp::{DecoderError, Prototype, Rlp, RlpStream};
+use trie_db::node::{NibbleSlicePlan, NodeHandlePlan, NodePlan, Value, ValuePlan};
+use trie_db::{ChildReference, NodeCodec};
+
+/// Keccak of the RLP empty string, the root of an empty trie.
+pub const EMPTY_ROOT: H256 = H256([
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+]);
+
+/// RLP encoding of the empty string, the empty node.
+const EMPTY_NODE: [u8; 1] = [0x80];
+
+/// Hex-prefix flag of leaf partial keys.
+const LEAF_FLAG: u8 = 0x20;
+/// Hex-prefix flag of partial keys with an odd number of nibbles.
+const ODD_FLAG: u8 = 0x10;
+
+/// Node codec of the Ethereum Merkle-Patricia trie as specified in the
+/// yellow paper, appendix D.
+///
+/// Nodes are RLP lists: a leaf or extension is its hex-prefix encoded partial
+/// key and its value or child, a branch its 16 children and its value. Child
+/// nodes shorter than 32 bytes are embedded in their parent instead of being
+/// referenced by hash.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RlpNodeCodec;
+
+impl NodeCodec for RlpNodeCodec {
+    type Error = DecoderError;
+    type HashOut = H256;
+
+    fn hashed_null_node() -> H256 {
+        EMPTY_ROOT
+    }
+
+    fn decode_plan(data: &[u8]) -> Result<NodePlan, Self::Error> {
+        let rlp = Rlp::new(data);
+        match rlp.prototype()? {
+            Prototype::Data(0) => Ok(NodePlan::Empty),
+            Prototype::List(2) => {
+                let mut items = Items::new(&rlp)?;
+                let (key, key_range) = items.next()?;
+                let (item, item_range) = items.next()?;
+
+                let key = key.data()?;
+                let (flags, start) = match key.first() {
+                    Some(&flags) => (flags, key_range.end - key.len()),
+                    None => return Err(DecoderError::RlpIsTooShort),
+                };
+                let partial = if flags & ODD_FLAG == 0 {
+                    NibbleSlicePlan::new(start + 1..key_range.end, 0)
+                } else {
+                    NibbleSlicePlan::new(start..key_range.end, 1)
+                };
+
+                if flags & LEAF_FLAG == 0 {
+                    Ok(NodePlan::Extension {
+                        partial,
+                        child: child_plan(&item, item_range)?,
+                    })
+                } else {
+                    Ok(NodePlan::Leaf {
+                        partial,
+                        value: ValuePlan::Inline(payload_range(&item, item_range)?),
+                    })
+                }
+            }
+            Prototype::List(17) => {
+                let mut items = Items::new(&rlp)?;
+                let mut children = [
+                    None, None, None, None, None, None, None, None, None, None, None, None, None,
+                    None, None, None,
+                ];
+                for child in children.iter_mut() {
+                    let (item, range) = items.next()?;
+                    if !item.is_empty() {
+                        *child = Some(child_plan(&item, range)?);
+                    }
+                }
+                let (item, range) = items.next()?;
+                let value = match item.is_empty() {
+                    true => None,
+                    false => Some(ValuePlan::Inline(payload_range(&item, range)?)),
+                };
+                Ok(NodePlan::Branch { value, children })
+            }
+            _ => Err(DecoderError::Custom("invalid trie node")),
+        }
+    }
+
+    fn is_empty_node(data: &[u8]) -> bool {
+        data == EMPTY_NODE
+    }
+
+    fn empty_node() -> &'static [u8] {
+        &EMPTY_NODE
+    }
+
+    fn leaf_node(partial: impl Iterator<Item = u8>, number_nibble: usize, value: Value) -> Vec<u8> {
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&hex_prefix(partial, number_nibble, true));
+        match value {
+            Value::Inline(value) => stream.append(&value),
+            Value::Node(..) => unreachable!("values are never stored apart from their node"),
+        };
+        stream.out().to_vec()
+    }
+
+    fn extension_node(
+        partial: impl Iterator<Item = u8>,
+        number_nibble: usize,
+        child_ref: ChildReference<H256>,
+    ) -> Vec<u8> {
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&hex_prefix(partial, number_nibble, false));
+        append_child(&mut stream, &child_ref);
+        stream.out().to_vec()
+    }
+
+    fn branch_node(
+        children: impl Iterator<Item = impl Borrow<Option<ChildReference<H256>>>>,
+        value: Option<Value>,
+    ) -> Vec<u8> {
+        let mut stream = RlpStream::new_list(17);
+        for child in children {
+            match child.borrow() {
+                Some(child) => append_child(&mut stream, child),
+                None => {
+                    stream.append_empty_data();
+                }
+            }
+        }
+        match value {
+            Some(Value::Inline(value)) => stream.append(&value),
+            Some(Value::Node(..)) => unreachable!("values are never stored apart from their node"),
+            None => stream.append_empty_data(),
+        };
+        stream.out().to_vec()
+    }
+
+    fn branch_node_nibbled(
+        _partial: impl Iterator<Item = u8>,
+        _number_nibble: usize,
+        _children: impl Iterator<Item = impl Borrow<Option<ChildReference<H256>>>>,
+        _value: Option<Value>,
+    ) -> Vec<u8> {
+        unreachable!("the Ethereum trie uses extension nodes")
+    }
+}
+
+/// Walks the items of an RLP list along with their byte range in the node.
+struct Items<'a> {
+    rlp: &'a Rlp<'a>,
+    index: usize,
+    offset: usize,
+}
+
+impl<'a> Items<'a> {
+    fn new(rlp: &'a Rlp<'a>) -> Result<Self, DecoderError> {
+        Ok(Self {
+            rlp,
+            index: 0,
+            offset: rlp.payload_info()?.header_len,
+        })
+    }
+
+    fn next(&mut self) -> Result<(Rlp<'a>, Range<usize>), DecoderError> {
+        let item = self.rlp.at(self.index)?;
+        let start = self.offset;
+        self.index += 1;
+        self.offset += item.as_raw().len();
+        Ok((item, start..self.offset))
+    }
+}
+
+/// Range of the payload of the string `item` spanning `range`.
+fn payload_range(item: &Rlp, range: Range<usize>) -> Result<Range<usize>, DecoderError> {
+    let info = item.payload_info()?;
+    Ok(range.start + info.header_len..range.end)
+}
+
+/// A child is referenced by its hash unless its encoding is embedded.
+fn child_plan(item: &Rlp, range: Range<usize>) -> Result<NodeHandlePlan, DecoderError> {
+    match item.is_list() {
+        true => Ok(NodeHandlePlan::Inline(range)),
+        false if item.size() == 32 => Ok(NodeHandlePlan::Hash(payload_range(item, range)?)),
+        false => Err(DecoderError::Custom("invalid child reference")),
+    }
+}
+
+fn append_child(stream: &mut RlpStream, child: &ChildReference<H256>) {
+    match child {
+        ChildReference::Hash(hash) => stream.append(hash),
+        ChildReference::Inline(data, len) => stream.append_raw(&data[..*len], 1),
+    };
+}
+
+/// Hex-prefix encoding of a partial key. `partial` holds the nibbles packed
+/// two per byte, with the first one alone in the low bits of the first byte
+/// when their number is odd.
+fn hex_prefix(mut partial: impl Iterator<Item = u8>, number_nibble: usize, leaf: bool) -> Vec<u8> {
+    let flags = if leaf { LEAF_FLAG } else { 0 };
+    let mut key = Vec::with_capacity(number_nibble / 2 + 1);
+    if number_nibble % 2 == 1 {
+        key.push(flags | ODD_FLAG | partial.next().unwrap_or_default());
+    } else {
+        key.push(flags);
+    }
+    key.extend(partial);
+    key
+}
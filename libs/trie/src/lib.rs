@@ -0,0 +1,136 @@
+mod codec;
+mod overlay;
+mod proof;
+
+use std::collections::BTreeMap;
+
+pub use codec::{RlpNodeCodec, EMPTY_ROOT};
+pub use ethereum::util::KeccakHasher;
+use ethereum_types::H256;
+pub use hash_db::{AsHashDB, HashDB, HashDBRef, Hasher, Prefix, EMPTY_PREFIX};
+use memory_db::HashKey;
+pub use overlay::OverlayDB;
+pub use proof::{generate_proof, verify_proof};
+use trie_db::{NodeCodec, TrieConfiguration, TrieLayout};
+pub use trie_db::{Trie, TrieDBBuilder, TrieDBMutBuilder, TrieMut};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Trie(#[from] Box<trie_db::TrieError<H256, rlp::DecoderError>>),
+    #[error("proof does not match the trie root")]
+    InvalidProof,
+}
+
+/// Layout of the Ethereum Merkle-Patricia trie: keccak hashed nodes in the
+/// RLP encoding of `RlpNodeCodec`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Layout;
+
+impl TrieLayout for Layout {
+    const USE_EXTENSION: bool = true;
+    const ALLOW_EMPTY: bool = false;
+    const MAX_INLINE_VALUE: Option<u32> = None;
+
+    type Hash = KeccakHasher;
+    type Codec = RlpNodeCodec;
+}
+
+impl TrieConfiguration for Layout {}
+
+pub type TrieDB<'db, 'cache> = trie_db::TrieDB<'db, 'cache, Layout>;
+pub type TrieDBMut<'db> = trie_db::TrieDBMut<'db, Layout>;
+
+/// In memory trie node store.
+pub type MemoryDB = memory_db::MemoryDB<KeccakHasher, HashKey<KeccakHasher>, Vec<u8>>;
+
+/// Creates an empty node store whose null node is the empty trie.
+pub fn memory_db() -> MemoryDB {
+    MemoryDB::new(RlpNodeCodec::empty_node())
+}
+
+/// Key of the item at `index` in the tries of a block's transactions and
+/// receipts.
+pub fn index_key(index: usize) -> Vec<u8> {
+    rlp::encode(&index).to_vec()
+}
+
+/// Root of the trie mapping the RLP encoded index of each item to the item.
+pub fn ordered_root<I, V>(items: I) -> H256
+where
+    I: IntoIterator<Item = V>,
+    V: AsRef<[u8]>,
+{
+    Layout::trie_root(
+        items
+            .into_iter()
+            .enumerate()
+            .map(|(index, item)| (index_key(index), item))
+            .collect::<BTreeMap<_, _>>(),
+    )
+}
+
+/// Builds the trie of `ordered_root`, keeping its nodes to prove items from.
+pub fn ordered_trie<I, V>(items: I) -> (H256, MemoryDB)
+where
+    I: IntoIterator<Item = V>,
+    V: AsRef<[u8]>,
+{
+    let mut db = memory_db();
+    let mut root = H256::default();
+    {
+        let mut trie = TrieDBMutBuilder::<Layout>::new(&mut db, &mut root).build();
+        for (index, item) in items.into_iter().enumerate() {
+            trie.insert(&index_key(index), item.as_ref())
+                .expect("in memory trie has all its nodes");
+        }
+    }
+    (root, db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(count: usize) -> Vec<Vec<u8>> {
+        (0..count).map(|i| vec![i as u8; i % 40 + 1]).collect()
+    }
+
+    #[test]
+    fn empty_root() {
+        assert_eq!(ordered_root(Vec::<Vec<u8>>::new()), EMPTY_ROOT);
+        assert_eq!(ordered_trie(Vec::<Vec<u8>>::new()).0, EMPTY_ROOT);
+        assert_eq!(
+            EMPTY_ROOT,
+            ethereum::util::ordered_trie_root(Vec::<Vec<u8>>::new())
+        );
+    }
+
+    #[test]
+    fn matches_ethereum_roots() {
+        for count in [1, 2, 3, 16, 17, 128, 129, 300] {
+            let items = items(count);
+            let expected = ethereum::util::ordered_trie_root(&items);
+            assert_eq!(ordered_root(&items), expected, "{} items", count);
+            assert_eq!(ordered_trie(&items).0, expected, "{} items", count);
+        }
+
+        let pairs = (0..50u8)
+            .map(|i| (vec![i, i / 3], vec![i; 33]))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(Layout::trie_root(&pairs), ethereum::util::trie_root(&pairs));
+    }
+
+    #[test]
+    fn reads_back_items() {
+        let items = items(200);
+        let (root, db) = ordered_trie(&items);
+        let trie = TrieDBBuilder::<Layout>::new(&db, &root).build();
+        for (index, item) in items.iter().enumerate() {
+            assert_eq!(trie.get(&index_key(index)).unwrap().as_ref(), Some(item));
+        }
+        assert_eq!(trie.get(&index_key(200)).unwrap(), None);
+    }
+}
@@ -0,0 +1,134 @@
+use ethereum_types::H256;
+use hash_db::{AsHashDB, HashDB, HashDBRef, Prefix};
+use kvdb::{DBTransaction, KeyValueDB};
+
+use crate::{memory_db, KeccakHasher, MemoryDB};
+
+/// Trie node store over a column of a key-value database.
+///
+/// Nodes are keyed by their hash. Inserted nodes are buffered in memory and
+/// read back from there until `commit` adds them to a database transaction.
+/// `commit` leaves removed nodes in the database, where they remain reachable
+/// from the roots they belong to; `into_changes` lets the caller reference
+/// count them instead.
+pub struct OverlayDB<'a, T: ?Sized> {
+    db: &'a T,
+    col: u32,
+    overlay: MemoryDB,
+}
+
+impl<'a, T: KeyValueDB + ?Sized> OverlayDB<'a, T> {
+    pub fn new(db: &'a T, col: u32) -> Self {
+        Self {
+            db,
+            col,
+            overlay: memory_db(),
+        }
+    }
+
+    /// Adds the nodes inserted since the overlay was created to `tx`.
+    pub fn commit(self, tx: &mut DBTransaction) {
+        let col = self.col;
+        for (key, value, rc) in self.into_changes() {
+            if rc > 0 {
+                tx.put_vec(col, key.as_bytes(), value);
+            }
+        }
+    }
+
+    /// Net changes made through the overlay: each node with the number of
+    /// times it was inserted minus the number of times it was removed.
+    pub fn into_changes(mut self) -> Vec<(H256, Vec<u8>, i32)> {
+        self.overlay
+            .drain()
+            .into_iter()
+            .filter(|(_, (_, rc))| *rc != 0)
+            .map(|(key, (value, rc))| (key, value, rc))
+            .collect()
+    }
+}
+
+impl<'a, T: KeyValueDB + ?Sized> HashDB<KeccakHasher, Vec<u8>> for OverlayDB<'a, T> {
+    fn get(&self, key: &H256, prefix: Prefix) -> Option<Vec<u8>> {
+        match HashDB::get(&self.overlay, key, prefix) {
+            Some(value) => Some(value),
+            None => self.db.get(self.col, key.as_bytes()).ok().flatten(),
+        }
+    }
+
+    fn contains(&self, key: &H256, prefix: Prefix) -> bool {
+        HashDB::get(self, key, prefix).is_some()
+    }
+
+    fn insert(&mut self, prefix: Prefix, value: &[u8]) -> H256 {
+        self.overlay.insert(prefix, value)
+    }
+
+    fn emplace(&mut self, key: H256, prefix: Prefix, value: Vec<u8>) {
+        self.overlay.emplace(key, prefix, value)
+    }
+
+    fn remove(&mut self, key: &H256, prefix: Prefix) {
+        self.overlay.remove(key, prefix)
+    }
+}
+
+impl<'a, T: KeyValueDB + ?Sized> HashDBRef<KeccakHasher, Vec<u8>> for OverlayDB<'a, T> {
+    fn get(&self, key: &H256, prefix: Prefix) -> Option<Vec<u8>> {
+        HashDB::get(self, key, prefix)
+    }
+
+    fn contains(&self, key: &H256, prefix: Prefix) -> bool {
+        HashDB::contains(self, key, prefix)
+    }
+}
+
+impl<'a, T: KeyValueDB + ?Sized> AsHashDB<KeccakHasher, Vec<u8>> for OverlayDB<'a, T> {
+    fn as_hash_db(&self) -> &dyn HashDB<KeccakHasher, Vec<u8>> {
+        self
+    }
+
+    fn as_hash_db_mut<'b>(&'b mut self) -> &'b mut (dyn HashDB<KeccakHasher, Vec<u8>> + 'b) {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Layout, Trie, TrieDBBuilder, TrieDBMutBuilder, TrieMut, EMPTY_ROOT};
+
+    #[test]
+    fn commits_nodes_to_the_database() {
+        let kvdb = kvdb_memorydb::create(1);
+        let mut root = EMPTY_ROOT;
+        let mut db = OverlayDB::new(&kvdb, 0);
+        {
+            let mut trie = TrieDBMutBuilder::<Layout>::from_existing(&mut db, &mut root).build();
+            for i in 0..50u8 {
+                trie.insert(&[i], &[i; 40]).unwrap();
+            }
+        }
+        let mut tx = DBTransaction::new();
+        db.commit(&mut tx);
+        kvdb.write(tx).unwrap();
+
+        // Updating the trie keeps the nodes of the previous root.
+        let mut updated = root;
+        let mut db = OverlayDB::new(&kvdb, 0);
+        {
+            let mut trie = TrieDBMutBuilder::<Layout>::from_existing(&mut db, &mut updated).build();
+            trie.insert(&[7], &[0; 40]).unwrap();
+        }
+        let mut tx = DBTransaction::new();
+        db.commit(&mut tx);
+        kvdb.write(tx).unwrap();
+
+        let db = OverlayDB::new(&kvdb, 0);
+        let trie = TrieDBBuilder::<Layout>::new(&db, &root).build();
+        assert_eq!(trie.get(&[7]).unwrap(), Some(vec![7; 40]));
+        let trie = TrieDBBuilder::<Layout>::new(&db, &updated).build();
+        assert_eq!(trie.get(&[7]).unwrap(), Some(vec![0; 40]));
+        assert_eq!(trie.get(&[8]).unwrap(), Some(vec![8; 40]));
+    }
+}
@@ -0,0 +1,73 @@
+use ethereum_types::H256;
+use trie_db::recorder::Recorder;
+use trie_db::{Trie, TrieDBBuilder};
+
+use crate::{memory_db, Error, HashDB, HashDBRef, Layout, Result, EMPTY_PREFIX};
+
+/// Proves the value of `key` in the trie of `root`, or its absence.
+///
+/// The proof is the list of the RLP encoded nodes on the path from the root
+/// to the key, in the format of `eth_getProof`.
+pub fn generate_proof(
+    db: &dyn HashDBRef<crate::KeccakHasher, Vec<u8>>,
+    root: &H256,
+    key: &[u8],
+) -> Result<Vec<Vec<u8>>> {
+    let mut recorder = Recorder::<Layout>::new();
+    TrieDBBuilder::<Layout>::new(db, root)
+        .with_recorder(&mut recorder)
+        .build()
+        .get(key)?;
+
+    Ok(recorder
+        .drain()
+        .into_iter()
+        .map(|record| record.data)
+        .collect())
+}
+
+/// Checks `proof` against `root` and returns the value it proves for `key`,
+/// `None` meaning the key is absent from the trie.
+pub fn verify_proof(root: &H256, key: &[u8], proof: &[Vec<u8>]) -> Result<Option<Vec<u8>>> {
+    let mut db = memory_db();
+    for node in proof {
+        db.insert(EMPTY_PREFIX, node);
+    }
+
+    TrieDBBuilder::<Layout>::new(&db, root)
+        .build()
+        .get(key)
+        .map_err(|_| Error::InvalidProof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{index_key, ordered_trie};
+
+    #[test]
+    fn proves_items_and_absence() {
+        let items = (0..100u8).map(|i| vec![i; 40]).collect::<Vec<_>>();
+        let (root, db) = ordered_trie(&items);
+
+        let proof = generate_proof(&db, &root, &index_key(42)).unwrap();
+        assert_eq!(
+            verify_proof(&root, &index_key(42), &proof).unwrap(),
+            Some(items[42].clone())
+        );
+
+        let proof = generate_proof(&db, &root, &index_key(100)).unwrap();
+        assert_eq!(verify_proof(&root, &index_key(100), &proof).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_proofs_of_other_roots() {
+        let items = (0..20u8).map(|i| vec![i; 40]).collect::<Vec<_>>();
+        let (root, db) = ordered_trie(&items);
+        let mut proof = generate_proof(&db, &root, &index_key(7)).unwrap();
+
+        assert!(verify_proof(&H256::repeat_byte(1), &index_key(7), &proof).is_err());
+        proof.pop();
+        assert!(verify_proof(&root, &index_key(7), &proof).is_err());
+    }
+}
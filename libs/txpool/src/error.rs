@@ -0,0 +1,28 @@
+use ethereum_types::{H160, H256, U256};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Reasons for the pool to reject a transaction.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("transaction {0:?} already imported")]
+    AlreadyImported(H256),
+    #[error("nonce too low: expected at least {expected}, got {got}")]
+    StaleNonce { expected: U256, got: U256 },
+    #[error("replacement transaction underpriced: fee must be bumped over {0:?}")]
+    ReplacementUnderpriced(H256),
+    #[error("invalid transaction encoding: {0}")]
+    InvalidEncoding(String),
+    #[error("transaction expired at block {valid_until_block}, the chain is at {height}")]
+    Expired { valid_until_block: u64, height: u64 },
+    #[error("invalid signature: {0}")]
+    InvalidSignature(&'static str),
+    #[error("invalid chain id: expected {expected}, got {got:?}")]
+    InvalidChainId { expected: u64, got: Option<u64> },
+    #[error("transaction pool is full")]
+    PoolFull,
+    #[error("transaction underpriced: pool is full of better paying transactions")]
+    Underpriced,
+    #[error("sender {sender:?} exceeds its quota of {limit} pooled transactions")]
+    SenderQuotaExceeded { sender: H160, limit: usize },
+}
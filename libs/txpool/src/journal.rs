@@ -0,0 +1,146 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use ethereum::{EnvelopedDecodable, EnvelopedEncodable, TransactionV2};
+
+/// On-disk journal of pooled transactions, used to restore the pool after a
+/// restart.
+///
+/// Transactions are stored as a stream of RLP byte strings, each wrapping the
+/// EIP-2718 encoding of one transaction.
+pub struct Journal {
+    path: PathBuf,
+    writer: Option<File>,
+}
+
+impl Journal {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            writer: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads all journaled transactions in the order they were written.
+    ///
+    /// A missing journal is empty. Reading stops at the first record that
+    /// can't be decoded, which is usually one cut short by a crash.
+    pub fn load(&self) -> io::Result<Vec<TransactionV2>> {
+        let mut data = Vec::new();
+        match File::open(&self.path) {
+            Ok(mut file) => file.read_to_end(&mut data)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut txs = Vec::new();
+        let mut rest = &data[..];
+        while !rest.is_empty() {
+            let len = match rlp::Rlp::new(rest).payload_info() {
+                Ok(info) if info.total() <= rest.len() => info.total(),
+                _ => break,
+            };
+            let record = rlp::Rlp::new(&rest[..len]);
+            match record.data().ok().map(TransactionV2::decode) {
+                Some(Ok(tx)) => txs.push(tx),
+                _ => break,
+            }
+            rest = &rest[len..];
+        }
+        Ok(txs)
+    }
+
+    /// Appends a transaction to the journal.
+    pub fn insert(&mut self, tx: &TransactionV2) -> io::Result<()> {
+        if self.writer.is_none() {
+            self.writer = Some(Self::open(&self.path, true)?);
+        }
+        let writer = self.writer.as_mut().expect("journal writer is open");
+        writer.write_all(&Self::record(tx))
+    }
+
+    /// Replaces the journal content with `txs`.
+    ///
+    /// The new journal is written next to the old one and renamed over it, so
+    /// a crash midway leaves the previous journal intact.
+    pub fn rotate<'a>(
+        &mut self,
+        txs: impl IntoIterator<Item = &'a TransactionV2>,
+    ) -> io::Result<()> {
+        self.writer = None;
+
+        let tmp = self.path.with_extension("new");
+        let mut writer = BufWriter::new(Self::open(&tmp, false)?);
+        for tx in txs {
+            writer.write_all(&Self::record(tx))?;
+        }
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+
+        self.writer = Some(Self::open(&self.path, true)?);
+        Ok(())
+    }
+
+    fn record(tx: &TransactionV2) -> Vec<u8> {
+        rlp::encode(&tx.encode().to_vec()).to_vec()
+    }
+
+    fn open(path: &Path, append: bool) -> io::Result<File> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethereum::{LegacyTransaction, TransactionAction, TransactionSignature};
+    use ethereum_types::{H160, H256, U256};
+
+    fn tx(nonce: u64) -> TransactionV2 {
+        TransactionV2::Legacy(LegacyTransaction {
+            nonce: U256::from(nonce),
+            gas_price: U256::one(),
+            gas_limit: U256::from(21000),
+            action: TransactionAction::Call(H160::repeat_byte(0xaa)),
+            value: U256::zero(),
+            input: vec![],
+            signature: TransactionSignature::new(27, H256::repeat_byte(1), H256::repeat_byte(1))
+                .unwrap(),
+        })
+    }
+
+    #[test]
+    fn skips_truncated_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut journal = Journal::new(dir.path().join("transactions.rlp"));
+        assert!(journal.load().unwrap().is_empty());
+
+        journal.insert(&tx(0)).unwrap();
+        journal.insert(&tx(1)).unwrap();
+        let len = fs::metadata(journal.path()).unwrap().len();
+        File::options()
+            .write(true)
+            .open(journal.path())
+            .unwrap()
+            .set_len(len - 1)
+            .unwrap();
+        assert_eq!(journal.load().unwrap(), vec![tx(0)]);
+
+        journal.rotate(&[tx(2)]).unwrap();
+        journal.insert(&tx(3)).unwrap();
+        assert_eq!(journal.load().unwrap(), vec![tx(2), tx(3)]);
+    }
+}
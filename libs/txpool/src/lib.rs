@@ -1,14 +1,51 @@
+mod error;
+mod journal;
 mod txpool;
+mod verify;
 
-use std::hash::Hash;
+use std::sync::Arc;
+
+pub use error::{Error, Result};
 use ethereum::TransactionV2;
-pub use txpool::BasicPool;
+use ethereum_types::{H160, H256, U256};
+pub use journal::Journal;
+use tokio::sync::broadcast;
+pub use txpool::{BasicPool, PoolConfig, PoolEvent, PoolStatus, PoolTransaction};
+pub use verify::{check_chain_id, recover_sender};
 
 /// Transaction pool interface.
 pub trait TransactionPool: Send + Sync {
-    /// Returns a future that imports one unverified transaction to the pool.
-    fn submit_one(&self, tx: TransactionV2) -> anyhow::Result<()>;
+    /// Verifies one transaction and imports it to the pool.
+    fn submit_one(&self, tx: TransactionV2) -> Result<()>;
+
+    /// Verifies the transaction encoded in the `data` of a proto
+    /// `Transaction` and imports it to the pool, expiring after its
+    /// `valid_until_block` if set.
+    fn submit_proto(&self, tx: &proto::Transaction) -> Result<()>;
 
     /// Get an iterator for ready transactions ordered by priority.
-    fn ready(&self) -> Vec<TransactionV2>;
-}
\ No newline at end of file
+    fn ready(&self) -> Vec<Arc<PoolTransaction>>;
+
+    /// Returns the pooled transaction with the given hash.
+    fn transaction(&self, hash: &H256) -> Option<Arc<PoolTransaction>>;
+
+    /// Returns the number of ready and future transactions.
+    fn status(&self) -> PoolStatus;
+
+    /// Returns the nonce of the next transaction of `address`: the one
+    /// following its ready transactions, or its state nonce without any.
+    fn pending_nonce(&self, address: &H160) -> U256;
+
+    /// Prunes transactions included in `block` or invalidated by it, once the
+    /// block has been committed to the chain.
+    fn maintain(&self, block: &proto::Block);
+
+    /// Subscribes to changes of the pool content.
+    fn subscribe(&self) -> broadcast::Receiver<PoolEvent>;
+}
+
+/// Account state the pool validates transaction nonces against.
+pub trait StateReader: Send + Sync {
+    /// Returns the nonce of `address` in the latest state.
+    fn nonce(&self, address: &H160) -> U256;
+}
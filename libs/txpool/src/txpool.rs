@@ -1,21 +1,1254 @@
-use ethereum::TransactionV2;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
-pub struct BasicPool {
-    
+use ethereum::{EnvelopedDecodable, EnvelopedEncodable, TransactionV2};
+use ethereum_types::{H160, H256, U256, U512};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::error::{Error, Result};
+use crate::journal::Journal;
+use crate::verify::{check_chain_id, recover_sender};
+use crate::{StateReader, TransactionPool};
+
+/// Transaction pool configuration.
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    /// Chain id transactions must be signed for.
+    pub chain_id: u64,
+    /// Accept legacy transactions signed without EIP-155 replay protection.
+    pub allow_unprotected_txs: bool,
+    /// Percentage both fee fields must be raised by to replace a pooled
+    /// transaction with the same sender and nonce.
+    pub price_bump: u64,
+    /// Maximum number of transactions held by the pool.
+    pub max_count: usize,
+    /// Maximum total size of the encoded transactions held by the pool.
+    pub max_bytes: usize,
+    /// Maximum number of transactions pooled per sender.
+    pub max_per_sender: usize,
+    /// Number of blocks after admission a transaction may be included in
+    /// before it expires. Without it, only transactions that came with a
+    /// `valid_until_block` expire.
+    pub tx_lifetime: Option<u64>,
+    /// File pooled transactions are journaled to, to survive restarts.
+    pub journal: Option<PathBuf>,
+    /// How often the journal is rewritten with the current pool content.
+    pub rejournal: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            chain_id: 1,
+            allow_unprotected_txs: false,
+            price_bump: 10,
+            max_count: 4096,
+            max_bytes: 16 * 1024 * 1024,
+            max_per_sender: 64,
+            tx_lifetime: None,
+            journal: None,
+            rejournal: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// A verified transaction held by the pool.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoolTransaction {
+    pub hash: H256,
+    /// Address recovered from the transaction signature.
+    pub sender: H160,
+    pub nonce: U256,
+    /// Length of the EIP-2718 encoded transaction.
+    pub size: usize,
+    /// Last block height the transaction may be included at, assigned by the
+    /// pool on admission unless the transaction came with one. Zero if the
+    /// transaction never expires.
+    pub valid_until_block: u64,
+    pub transaction: TransactionV2,
+}
+
+impl PoolTransaction {
+    /// Verifies the signature of `transaction` and recovers its sender.
+    pub fn new(transaction: TransactionV2, config: &PoolConfig) -> Result<Self> {
+        check_chain_id(&transaction, config.chain_id, config.allow_unprotected_txs)?;
+
+        let nonce = match &transaction {
+            TransactionV2::Legacy(t) => t.nonce,
+            TransactionV2::EIP2930(t) => t.nonce,
+            TransactionV2::EIP1559(t) => t.nonce,
+        };
+
+        Ok(Self {
+            hash: transaction.hash(),
+            sender: recover_sender(&transaction)?,
+            nonce,
+            size: transaction.encode().len(),
+            valid_until_block: 0,
+            transaction,
+        })
+    }
+
+    /// Maximum fee per gas the sender is willing to pay.
+    pub fn max_fee_per_gas(&self) -> U256 {
+        match &self.transaction {
+            TransactionV2::Legacy(t) => t.gas_price,
+            TransactionV2::EIP2930(t) => t.gas_price,
+            TransactionV2::EIP1559(t) => t.max_fee_per_gas,
+        }
+    }
+
+    /// Effective tip per gas paid to the block producer, used to order ready
+    /// transactions across senders.
+    pub fn priority(&self) -> U256 {
+        match &self.transaction {
+            TransactionV2::Legacy(t) => t.gas_price,
+            TransactionV2::EIP2930(t) => t.gas_price,
+            TransactionV2::EIP1559(t) => t.max_priority_fee_per_gas.min(t.max_fee_per_gas),
+        }
+    }
+
+    /// Whether this transaction pays enough to replace `old`.
+    fn can_replace(&self, old: &PoolTransaction, price_bump: u64) -> bool {
+        // Computed on 512 bits as a bumped fee may not fit in 256.
+        let bumped = |fee: U256| fee.full_mul(U256::from(100 + price_bump as u128)) / 100;
+
+        self.max_fee_per_gas() > old.max_fee_per_gas()
+            && U512::from(self.max_fee_per_gas()) >= bumped(old.max_fee_per_gas())
+            && self.priority() > old.priority()
+            && U512::from(self.priority()) >= bumped(old.priority())
+    }
+}
+
+/// Number of transactions in each queue of the pool.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolStatus {
+    pub ready: usize,
+    pub future: usize,
+}
+
+/// Changes to the pool content, published to subscribers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PoolEvent {
+    /// A transaction was admitted to the pool.
+    Imported(H256),
+    /// A transaction became executable and moved to the ready queue.
+    Ready(H256),
+    /// A transaction was replaced by one with the same sender and nonce.
+    Replaced { old: H256, new: H256 },
+    /// A transaction was removed from the pool without being included.
+    Dropped(H256),
+    /// A transaction was included in the block at `height`.
+    Included { hash: H256, height: u64 },
+}
+
+/// Pooled transaction hashes of one sender, ordered by nonce.
+#[derive(Default)]
+struct SenderQueue {
+    /// Transactions executable in sequence on top of the sender's state nonce.
+    ready: BTreeMap<U256, H256>,
+    /// Transactions waiting for a nonce gap to close.
+    future: BTreeMap<U256, H256>,
+}
+
+impl SenderQueue {
+    fn len(&self) -> usize {
+        self.ready.len() + self.future.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ready.is_empty() && self.future.is_empty()
+    }
+
+    fn get(&self, nonce: &U256) -> Option<&H256> {
+        self.ready.get(nonce).or_else(|| self.future.get(nonce))
+    }
+
+    fn get_mut(&mut self, nonce: &U256) -> Option<&mut H256> {
+        match self.ready.get_mut(nonce) {
+            Some(hash) => Some(hash),
+            None => self.future.get_mut(nonce),
+        }
+    }
+
+    fn remove(&mut self, nonce: &U256) -> Option<H256> {
+        self.ready
+            .remove(nonce)
+            .or_else(|| self.future.remove(nonce))
+    }
+
+    /// Hashes of all queued transactions in nonce order.
+    fn hashes(&self) -> impl DoubleEndedIterator<Item = &H256> {
+        self.ready.values().chain(self.future.values())
+    }
+
+    /// Moves future transactions that continue the ready sequence into it.
+    fn promote(&mut self, state_nonce: U256) -> Vec<H256> {
+        let mut next = match self.ready.keys().next_back() {
+            Some(last) => *last + 1,
+            None => state_nonce,
+        };
+
+        let mut promoted = Vec::new();
+        while let Some(hash) = self.future.remove(&next) {
+            self.ready.insert(next, hash);
+            promoted.push(hash);
+            next += U256::one();
+        }
+        promoted
+    }
+}
+
+#[derive(Default)]
+struct Pool {
+    /// All pooled transactions by hash.
+    by_hash: HashMap<H256, Arc<PoolTransaction>>,
+    by_sender: BTreeMap<H160, SenderQueue>,
+    /// Total size of the pooled transactions.
+    bytes: usize,
+    /// Height of the last block the pool was maintained on.
+    height: u64,
 }
 
-impl BasicPool {
-    pub fn new() -> Self {
-        Self{}
+impl Pool {
+    fn import(
+        &mut self,
+        mut tx: PoolTransaction,
+        state_nonce: U256,
+        config: &PoolConfig,
+    ) -> Result<Vec<PoolEvent>> {
+        if self.by_hash.contains_key(&tx.hash) {
+            return Err(Error::AlreadyImported(tx.hash));
+        }
+        if tx.valid_until_block != 0 && tx.valid_until_block <= self.height {
+            return Err(Error::Expired {
+                valid_until_block: tx.valid_until_block,
+                height: self.height,
+            });
+        }
+        if tx.nonce < state_nonce {
+            return Err(Error::StaleNonce {
+                expected: state_nonce,
+                got: tx.nonce,
+            });
+        }
+
+        let queue = self.by_sender.get(&tx.sender);
+        let replaced = queue
+            .and_then(|queue| queue.get(&tx.nonce))
+            .map(|hash| self.by_hash[hash].clone());
+        let (count, bytes) = match &replaced {
+            Some(old) => {
+                if !tx.can_replace(old, config.price_bump) {
+                    return Err(Error::ReplacementUnderpriced(old.hash));
+                }
+                (self.by_hash.len(), self.bytes - old.size + tx.size)
+            }
+            None => {
+                if queue.map_or(0, SenderQueue::len) >= config.max_per_sender {
+                    return Err(Error::SenderQuotaExceeded {
+                        sender: tx.sender,
+                        limit: config.max_per_sender,
+                    });
+                }
+                (self.by_hash.len() + 1, self.bytes + tx.size)
+            }
+        };
+
+        let mut events = Vec::new();
+        for hash in self.evictions(&tx, count, bytes, config)? {
+            self.remove(&hash);
+            events.push(PoolEvent::Dropped(hash));
+        }
+
+        let queue = self.by_sender.entry(tx.sender).or_default();
+        match replaced {
+            Some(old) => {
+                *queue
+                    .get_mut(&tx.nonce)
+                    .expect("replaced transaction is queued") = tx.hash;
+                self.by_hash.remove(&old.hash);
+                self.bytes -= old.size;
+                events.push(PoolEvent::Replaced {
+                    old: old.hash,
+                    new: tx.hash,
+                });
+                if queue.ready.contains_key(&tx.nonce) {
+                    events.push(PoolEvent::Ready(tx.hash));
+                }
+            }
+            None => {
+                queue.future.insert(tx.nonce, tx.hash);
+                events.push(PoolEvent::Imported(tx.hash));
+                events.extend(queue.promote(state_nonce).into_iter().map(PoolEvent::Ready));
+            }
+        }
+
+        if let (0, Some(lifetime)) = (tx.valid_until_block, config.tx_lifetime) {
+            tx.valid_until_block = self.height.saturating_add(lifetime);
+        }
+        self.bytes += tx.size;
+        self.by_hash.insert(tx.hash, Arc::new(tx));
+        Ok(events)
+    }
+
+    /// Removes the transactions included in the block at `height`, then drops
+    /// stale and expired transactions and re-splits the ready and future
+    /// queues of every sender against its post-block state nonce.
+    fn maintain(
+        &mut self,
+        height: u64,
+        included: &[H256],
+        state: &impl StateReader,
+    ) -> Vec<PoolEvent> {
+        self.height = height;
+
+        let mut events = Vec::new();
+        for hash in included {
+            if self.remove(hash).is_some() {
+                events.push(PoolEvent::Included {
+                    hash: *hash,
+                    height,
+                });
+            }
+        }
+
+        let senders = self.by_sender.keys().cloned().collect::<Vec<_>>();
+        for sender in senders {
+            let state_nonce = state.nonce(&sender);
+            let invalid = self.by_sender[&sender]
+                .hashes()
+                .filter(|hash| {
+                    let tx = &self.by_hash[*hash];
+                    tx.nonce < state_nonce
+                        || (tx.valid_until_block != 0 && tx.valid_until_block <= height)
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            for hash in invalid {
+                self.remove(&hash);
+                events.push(PoolEvent::Dropped(hash));
+            }
+
+            let queue = match self.by_sender.get_mut(&sender) {
+                Some(queue) => queue,
+                None => continue,
+            };
+            let was_ready = std::mem::take(&mut queue.ready);
+            queue.future.extend(was_ready.iter());
+            queue.promote(state_nonce);
+            events.extend(
+                queue
+                    .ready
+                    .iter()
+                    .filter(|(nonce, hash)| was_ready.get(nonce) != Some(hash))
+                    .map(|(_, hash)| PoolEvent::Ready(*hash)),
+            );
+        }
+        events
+    }
+
+    /// Picks the transactions to drop so that `tx` fits in the pool, given the
+    /// pool `count` and `bytes` totals once `tx` is added.
+    ///
+    /// Only the highest nonce transactions of other senders are evicted so no
+    /// nonce gaps are left behind, cheapest first. Fails if that would drop a
+    /// transaction paying at least as much as `tx`.
+    fn evictions(
+        &self,
+        tx: &PoolTransaction,
+        mut count: usize,
+        mut bytes: usize,
+        config: &PoolConfig,
+    ) -> Result<Vec<H256>> {
+        if tx.size > config.max_bytes {
+            return Err(Error::PoolFull);
+        }
+
+        let mut queues = self
+            .by_sender
+            .iter()
+            .filter(|(sender, _)| **sender != tx.sender)
+            .map(|(sender, queue)| (*sender, queue.hashes()))
+            .collect::<BTreeMap<_, _>>();
+        let mut tails = queues
+            .iter_mut()
+            .filter_map(|(sender, hashes)| {
+                let tail = &self.by_hash[hashes.next_back()?];
+                Some(Reverse((tail.priority(), Reverse(*sender), tail.hash)))
+            })
+            .collect::<BinaryHeap<_>>();
+
+        let mut evicted = Vec::new();
+        while count > config.max_count || bytes > config.max_bytes {
+            let Reverse((priority, Reverse(sender), hash)) = tails.pop().ok_or(Error::PoolFull)?;
+            if priority >= tx.priority() {
+                return Err(Error::Underpriced);
+            }
+
+            count -= 1;
+            bytes -= self.by_hash[&hash].size;
+            evicted.push(hash);
+            if let Some(next) = queues
+                .get_mut(&sender)
+                .and_then(|hashes| hashes.next_back())
+            {
+                let next = &self.by_hash[next];
+                tails.push(Reverse((next.priority(), Reverse(sender), next.hash)));
+            }
+        }
+        Ok(evicted)
+    }
+
+    /// Removes a transaction from the pool.
+    fn remove(&mut self, hash: &H256) -> Option<Arc<PoolTransaction>> {
+        let tx = self.by_hash.remove(hash)?;
+        self.bytes -= tx.size;
+        if let Some(queue) = self.by_sender.get_mut(&tx.sender) {
+            queue.remove(&tx.nonce);
+            if queue.is_empty() {
+                self.by_sender.remove(&tx.sender);
+            }
+        }
+        Some(tx)
+    }
+
+    /// Returns ready transactions in nonce order per sender, interleaving
+    /// senders by the priority of their next transaction. Ties are broken by
+    /// sender address so the order is deterministic.
+    fn ready(&self) -> Vec<Arc<PoolTransaction>> {
+        let mut queues = self
+            .by_sender
+            .iter()
+            .map(|(sender, queue)| {
+                let txs = queue
+                    .ready
+                    .values()
+                    .map(|hash| self.by_hash[hash].clone())
+                    .collect::<VecDeque<_>>();
+                (*sender, txs)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let mut heads = queues
+            .iter()
+            .filter_map(|(sender, txs)| Some((txs.front()?.priority(), Reverse(*sender))))
+            .collect::<BinaryHeap<_>>();
+
+        let mut ready = Vec::new();
+        while let Some((_, Reverse(sender))) = heads.pop() {
+            let txs = queues.get_mut(&sender).expect("sender has a queued head");
+            ready.extend(txs.pop_front());
+            if let Some(next) = txs.front() {
+                heads.push((next.priority(), Reverse(sender)));
+            }
+        }
+        ready
     }
+
+    fn status(&self) -> PoolStatus {
+        self.by_sender
+            .values()
+            .fold(PoolStatus::default(), |status, queue| PoolStatus {
+                ready: status.ready + queue.ready.len(),
+                future: status.future + queue.future.len(),
+            })
+    }
+}
+
+/// In-memory transaction pool keyed by transaction hash.
+///
+/// Transactions whose nonce is ahead of the sender's state nonce are parked in
+/// the future queue until the gap is filled.
+pub struct BasicPool<S> {
+    config: PoolConfig,
+    state: Arc<S>,
+    pool: RwLock<Pool>,
+    events: broadcast::Sender<PoolEvent>,
+    journal: Option<Mutex<Journal>>,
 }
 
-impl crate::TransactionPool for BasicPool {
-    fn submit_one(&self, tx: TransactionV2) -> anyhow::Result<()> {
+/// Number of events buffered for subscribers before the slowest one lags.
+const EVENT_CAPACITY: usize = 1024;
+
+impl<S: StateReader> BasicPool<S> {
+    /// Creates the pool, restoring the transactions of the configured journal.
+    pub fn new(config: PoolConfig, state: Arc<S>) -> Self {
+        let journal = config.journal.clone().map(Journal::new);
+        let mut pool = Self {
+            config,
+            state,
+            pool: RwLock::new(Pool::default()),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            journal: None,
+        };
+        if let Some(journal) = journal {
+            pool.restore(journal);
+        }
+        pool
+    }
+
+    /// Replays the journaled transactions through the admission checks and
+    /// starts journaling new ones.
+    fn restore(&mut self, journal: Journal) {
+        match journal.load() {
+            Ok(txs) => {
+                let total = txs.len();
+                let imported = txs
+                    .into_iter()
+                    .filter(|tx| self.submit_one(tx.clone()).is_ok())
+                    .count();
+                info!(
+                    "restored {} of {} transactions from {:?}",
+                    imported,
+                    total,
+                    journal.path()
+                );
+            }
+            Err(e) => warn!("failed to load txpool journal {:?}: {}", journal.path(), e),
+        }
+
+        self.journal = Some(Mutex::new(journal));
+        self.rejournal();
+    }
+
+    /// Rewrites the journal with the current pool content, dropping the
+    /// transactions that have left the pool.
+    pub fn rejournal(&self) {
+        let journal = match &self.journal {
+            Some(journal) => journal,
+            None => return,
+        };
+
+        let pool = self.pool.read().unwrap();
+        let txs = pool
+            .by_sender
+            .values()
+            .flat_map(SenderQueue::hashes)
+            .map(|hash| &pool.by_hash[hash].transaction);
+        let mut journal = journal.lock().unwrap();
+        if let Err(e) = journal.rotate(txs) {
+            warn!(
+                "failed to rotate txpool journal {:?}: {}",
+                journal.path(),
+                e
+            );
+        }
+    }
+
+    fn notify(&self, events: Vec<PoolEvent>) {
+        for event in events {
+            // Sending only fails when nobody is subscribed.
+            let _ = self.events.send(event);
+        }
+    }
+
+    /// Imports a verified transaction and journals it.
+    fn submit(&self, tx: PoolTransaction) -> Result<()> {
+        let state_nonce = self.state.nonce(&tx.sender);
+
+        // Events are published under the lock so subscribers see them in the
+        // order the pool changed.
+        let hash = tx.hash;
+        let mut pool = self.pool.write().unwrap();
+        let events = pool.import(tx, state_nonce, &self.config)?;
+        self.notify(events);
+
+        if let Some(journal) = &self.journal {
+            let mut journal = journal.lock().unwrap();
+            if let Err(e) = journal.insert(&pool.by_hash[&hash].transaction) {
+                warn!("failed to journal transaction {:?}: {}", hash, e);
+            }
+        }
         Ok(())
     }
+}
+
+impl<S: StateReader> TransactionPool for BasicPool<S> {
+    fn submit_one(&self, tx: TransactionV2) -> Result<()> {
+        self.submit(PoolTransaction::new(tx, &self.config)?)
+    }
+
+    fn submit_proto(&self, tx: &proto::Transaction) -> Result<()> {
+        let transaction = TransactionV2::decode(&tx.data)
+            .map_err(|e| Error::InvalidEncoding(format!("{:?}", e)))?;
+        let mut pooled = PoolTransaction::new(transaction, &self.config)?;
+        pooled.valid_until_block = tx.valid_until_block;
+        self.submit(pooled)
+    }
+
+    fn ready(&self) -> Vec<Arc<PoolTransaction>> {
+        self.pool.read().unwrap().ready()
+    }
+
+    fn transaction(&self, hash: &H256) -> Option<Arc<PoolTransaction>> {
+        self.pool.read().unwrap().by_hash.get(hash).cloned()
+    }
+
+    fn status(&self) -> PoolStatus {
+        self.pool.read().unwrap().status()
+    }
+
+    fn pending_nonce(&self, address: &H160) -> U256 {
+        let state_nonce = self.state.nonce(address);
+        let pool = self.pool.read().unwrap();
+        let last = pool
+            .by_sender
+            .get(address)
+            .and_then(|queue| queue.ready.keys().next_back());
+        match last {
+            // The queue may still hold transactions of a block the pool
+            // wasn't maintained on yet.
+            Some(last) => state_nonce.max(*last + 1),
+            None => state_nonce,
+        }
+    }
+
+    fn maintain(&self, block: &proto::Block) {
+        let included = block
+            .body
+            .transactions
+            .iter()
+            .filter(|tx| tx.tx_hash.len() == H256::len_bytes())
+            .map(|tx| H256::from_slice(&tx.tx_hash))
+            .collect::<Vec<_>>();
+
+        let mut pool = self.pool.write().unwrap();
+        let events = pool.maintain(block.header.height, &included, &*self.state);
+        self.notify(events);
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<PoolEvent> {
+        self.events.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethereum::{
+        EIP1559Transaction, EIP1559TransactionMessage, LegacyTransaction, LegacyTransactionMessage,
+        TransactionAction, TransactionSignature,
+    };
+
+    #[derive(Default)]
+    struct State(RwLock<HashMap<H160, U256>>);
+
+    impl StateReader for State {
+        fn nonce(&self, address: &H160) -> U256 {
+            self.0
+                .read()
+                .unwrap()
+                .get(address)
+                .cloned()
+                .unwrap_or_default()
+        }
+    }
+
+    fn pool() -> BasicPool<State> {
+        pool_with(PoolConfig::default())
+    }
+
+    fn pool_with(config: PoolConfig) -> BasicPool<State> {
+        BasicPool::new(config, Arc::new(State::default()))
+    }
+
+    fn block(height: u64, txs: &[&TransactionV2]) -> proto::Block {
+        let mut block = proto::Block::new();
+        block.header.mut_or_insert_default().height = height;
+        block.body.mut_or_insert_default().transactions = txs
+            .iter()
+            .map(|tx| {
+                let mut signed = proto::SignedTransaction::new();
+                signed.tx_hash = tx.hash().as_bytes().to_vec();
+                signed
+            })
+            .collect();
+        block
+    }
+
+    fn secret(seed: u8) -> libsecp256k1::SecretKey {
+        libsecp256k1::SecretKey::parse(&[seed; 32]).unwrap()
+    }
+
+    fn address(secret: &libsecp256k1::SecretKey) -> H160 {
+        let public = libsecp256k1::PublicKey::from_secret_key(secret);
+        H160::from(H256::from(hashing::keccak_256(&public.serialize()[1..65])))
+    }
+
+    fn sign(secret: &libsecp256k1::SecretKey, hash: H256) -> (u8, H256, H256) {
+        let (signature, recid) = libsecp256k1::sign(&libsecp256k1::Message::parse(&hash.0), secret);
+        let rs = signature.serialize();
+        (
+            recid.serialize(),
+            H256::from_slice(&rs[0..32]),
+            H256::from_slice(&rs[32..64]),
+        )
+    }
+
+    fn legacy(secret: &libsecp256k1::SecretKey, nonce: u64, chain_id: u64) -> TransactionV2 {
+        priced_legacy(secret, nonce, chain_id, 1)
+    }
+
+    fn priced_legacy(
+        secret: &libsecp256k1::SecretKey,
+        nonce: u64,
+        chain_id: u64,
+        gas_price: impl Into<U256>,
+    ) -> TransactionV2 {
+        let message = LegacyTransactionMessage {
+            nonce: U256::from(nonce),
+            gas_price: gas_price.into(),
+            gas_limit: U256::from(21000),
+            action: TransactionAction::Call(H160::repeat_byte(0xaa)),
+            value: U256::zero(),
+            input: vec![],
+            chain_id: Some(chain_id),
+        };
+        let (recid, r, s) = sign(secret, message.hash());
+        TransactionV2::Legacy(LegacyTransaction {
+            nonce: message.nonce,
+            gas_price: message.gas_price,
+            gas_limit: message.gas_limit,
+            action: message.action,
+            value: message.value,
+            input: message.input,
+            signature: TransactionSignature::new(chain_id * 2 + 35 + recid as u64, r, s).unwrap(),
+        })
+    }
+
+    fn eip1559(secret: &libsecp256k1::SecretKey, nonce: u64, chain_id: u64) -> TransactionV2 {
+        priced_eip1559(secret, nonce, chain_id, 1, 2)
+    }
+
+    fn priced_eip1559(
+        secret: &libsecp256k1::SecretKey,
+        nonce: u64,
+        chain_id: u64,
+        max_priority_fee_per_gas: u64,
+        max_fee_per_gas: u64,
+    ) -> TransactionV2 {
+        let message = EIP1559TransactionMessage {
+            chain_id,
+            nonce: U256::from(nonce),
+            max_priority_fee_per_gas: U256::from(max_priority_fee_per_gas),
+            max_fee_per_gas: U256::from(max_fee_per_gas),
+            gas_limit: U256::from(21000),
+            action: TransactionAction::Create,
+            value: U256::zero(),
+            input: vec![0x60, 0x00],
+            access_list: vec![],
+        };
+        let (recid, r, s) = sign(secret, message.hash());
+        TransactionV2::EIP1559(EIP1559Transaction {
+            chain_id,
+            nonce: message.nonce,
+            max_priority_fee_per_gas: message.max_priority_fee_per_gas,
+            max_fee_per_gas: message.max_fee_per_gas,
+            gas_limit: message.gas_limit,
+            action: message.action,
+            value: message.value,
+            input: message.input,
+            access_list: message.access_list,
+            odd_y_parity: recid != 0,
+            r,
+            s,
+        })
+    }
+
+    #[test]
+    fn ready_is_nonce_ordered_per_sender() {
+        let pool = pool();
+        let (alice, bob) = (secret(1), secret(2));
+
+        for tx in [
+            legacy(&alice, 2, 1),
+            legacy(&bob, 0, 1),
+            legacy(&alice, 0, 1),
+            legacy(&alice, 1, 1),
+        ] {
+            pool.submit_one(tx).unwrap();
+        }
+
+        let ready = pool.ready();
+        assert_eq!(ready.len(), 4);
+        let nonces = ready
+            .iter()
+            .filter(|tx| tx.sender == address(&alice))
+            .map(|tx| tx.nonce.as_u64())
+            .collect::<Vec<_>>();
+        assert_eq!(nonces, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn rejects_duplicates() {
+        let pool = pool();
+        let tx = legacy(&secret(1), 0, 1);
+
+        pool.submit_one(tx.clone()).unwrap();
+        assert!(matches!(
+            pool.submit_one(tx.clone()),
+            Err(Error::AlreadyImported(_))
+        ));
+        assert_eq!(pool.transaction(&tx.hash()).unwrap().transaction, tx);
+    }
+
+    #[test]
+    fn recovers_sender_of_typed_transactions() {
+        let pool = pool();
+        let tx = eip1559(&secret(3), 0, 1);
+
+        pool.submit_one(tx.clone()).unwrap();
+        assert_eq!(
+            pool.transaction(&tx.hash()).unwrap().sender,
+            address(&secret(3))
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_chain_id() {
+        let pool = pool();
+
+        for tx in [legacy(&secret(1), 0, 5), eip1559(&secret(1), 0, 5)] {
+            assert!(matches!(
+                pool.submit_one(tx),
+                Err(Error::InvalidChainId {
+                    expected: 1,
+                    got: Some(5)
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_signature() {
+        let pool = pool();
+        let mut tx = eip1559(&secret(1), 0, 1);
+        if let TransactionV2::EIP1559(t) = &mut tx {
+            t.s = H256::zero();
+        }
+
+        assert!(matches!(
+            pool.submit_one(tx),
+            Err(Error::InvalidSignature(_))
+        ));
+    }
+
+    #[test]
+    fn parks_nonce_gaps_in_future_queue() {
+        let pool = pool();
+        let alice = secret(1);
+
+        pool.submit_one(legacy(&alice, 0, 1)).unwrap();
+        pool.submit_one(legacy(&alice, 2, 1)).unwrap();
+        pool.submit_one(legacy(&alice, 3, 1)).unwrap();
+        assert_eq!(
+            pool.status(),
+            PoolStatus {
+                ready: 1,
+                future: 2
+            }
+        );
+        assert_eq!(pool.pending_nonce(&address(&alice)), U256::one());
+
+        pool.submit_one(legacy(&alice, 1, 1)).unwrap();
+        assert_eq!(
+            pool.status(),
+            PoolStatus {
+                ready: 4,
+                future: 0
+            }
+        );
+        let nonces = pool
+            .ready()
+            .iter()
+            .map(|tx| tx.nonce.as_u64())
+            .collect::<Vec<_>>();
+        assert_eq!(nonces, vec![0, 1, 2, 3]);
+        assert_eq!(pool.pending_nonce(&address(&alice)), 4.into());
+        assert_eq!(pool.pending_nonce(&address(&secret(2))), U256::zero());
+    }
+
+    #[test]
+    fn starts_ready_queue_at_state_nonce() {
+        let pool = pool();
+        let alice = secret(1);
+        pool.state
+            .0
+            .write()
+            .unwrap()
+            .insert(address(&alice), U256::from(5));
+
+        assert!(matches!(
+            pool.submit_one(legacy(&alice, 4, 1)),
+            Err(Error::StaleNonce { .. })
+        ));
+        pool.submit_one(legacy(&alice, 5, 1)).unwrap();
+        assert_eq!(
+            pool.status(),
+            PoolStatus {
+                ready: 1,
+                future: 0
+            }
+        );
+    }
+
+    #[test]
+    fn replaces_only_with_bumped_fee() {
+        let pool = pool();
+        let alice = secret(1);
+        let original = priced_legacy(&alice, 0, 1, 100);
+        pool.submit_one(original.clone()).unwrap();
+
+        assert!(matches!(
+            pool.submit_one(priced_legacy(&alice, 0, 1, 109)),
+            Err(Error::ReplacementUnderpriced(hash)) if hash == original.hash()
+        ));
+
+        let replacement = priced_legacy(&alice, 0, 1, 110);
+        pool.submit_one(replacement.clone()).unwrap();
+        assert!(pool.transaction(&original.hash()).is_none());
+        assert_eq!(pool.ready()[0].hash, replacement.hash());
+        assert_eq!(
+            pool.status(),
+            PoolStatus {
+                ready: 1,
+                future: 0
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_replacement_of_maximal_fee() {
+        let pool = pool();
+        let alice = secret(1);
+        let original = priced_legacy(&alice, 0, 1, U256::MAX - 1);
+        pool.submit_one(original.clone()).unwrap();
+
+        // The bumped fee overflows 256 bits, which no replacement can pay.
+        assert!(matches!(
+            pool.submit_one(priced_legacy(&alice, 0, 1, U256::MAX)),
+            Err(Error::ReplacementUnderpriced(hash)) if hash == original.hash()
+        ));
+        assert_eq!(pool.ready()[0].hash, original.hash());
+    }
+
+    #[test]
+    fn orders_ready_by_effective_tip() {
+        let pool = pool();
+        let (alice, bob, carol) = (secret(1), secret(2), secret(3));
+
+        pool.submit_one(priced_legacy(&alice, 0, 1, 5)).unwrap();
+        pool.submit_one(priced_legacy(&alice, 1, 1, 50)).unwrap();
+        pool.submit_one(priced_legacy(&bob, 0, 1, 20)).unwrap();
+        // The tip is capped by the fee cap.
+        pool.submit_one(priced_eip1559(&carol, 0, 1, 100, 10))
+            .unwrap();
+
+        let order = pool
+            .ready()
+            .iter()
+            .map(|tx| (tx.sender, tx.nonce.as_u64()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            vec![
+                (address(&bob), 0),
+                (address(&carol), 0),
+                (address(&alice), 0),
+                (address(&alice), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn enforces_sender_quota() {
+        let pool = pool_with(PoolConfig {
+            max_per_sender: 2,
+            ..Default::default()
+        });
+        let alice = secret(1);
+
+        pool.submit_one(priced_legacy(&alice, 0, 1, 10)).unwrap();
+        pool.submit_one(priced_legacy(&alice, 1, 1, 10)).unwrap();
+        assert!(matches!(
+            pool.submit_one(priced_legacy(&alice, 2, 1, 10)),
+            Err(Error::SenderQuotaExceeded { limit: 2, .. })
+        ));
+        // Replacements do not take another slot.
+        pool.submit_one(priced_legacy(&alice, 1, 1, 20)).unwrap();
+        pool.submit_one(priced_legacy(&secret(2), 0, 1, 10))
+            .unwrap();
+    }
+
+    #[test]
+    fn evicts_cheapest_tail_when_full() {
+        let pool = pool_with(PoolConfig {
+            max_count: 3,
+            ..Default::default()
+        });
+        let (alice, bob, carol) = (secret(1), secret(2), secret(3));
+
+        pool.submit_one(priced_legacy(&alice, 0, 1, 50)).unwrap();
+        let tail = priced_legacy(&alice, 1, 1, 1);
+        pool.submit_one(tail.clone()).unwrap();
+        pool.submit_one(priced_legacy(&bob, 0, 1, 10)).unwrap();
+
+        pool.submit_one(priced_legacy(&carol, 0, 1, 5)).unwrap();
+        assert!(pool.transaction(&tail.hash()).is_none());
+        assert_eq!(
+            pool.status(),
+            PoolStatus {
+                ready: 3,
+                future: 0
+            }
+        );
+
+        assert!(matches!(
+            pool.submit_one(priced_legacy(&secret(4), 0, 1, 5)),
+            Err(Error::Underpriced)
+        ));
+    }
+
+    #[test]
+    fn rejects_when_nothing_can_be_evicted() {
+        let alice = secret(1);
+        let tx = legacy(&alice, 0, 1);
+        let size = tx.encode().len();
+
+        let pool = pool_with(PoolConfig {
+            max_count: 1,
+            ..Default::default()
+        });
+        pool.submit_one(tx.clone()).unwrap();
+        assert!(matches!(
+            pool.submit_one(priced_legacy(&alice, 1, 1, 100)),
+            Err(Error::PoolFull)
+        ));
+
+        let pool = pool_with(PoolConfig {
+            max_bytes: size - 1,
+            ..Default::default()
+        });
+        assert!(matches!(pool.submit_one(tx), Err(Error::PoolFull)));
+    }
+
+    #[test]
+    fn publishes_pool_events() {
+        let pool = pool_with(PoolConfig {
+            max_count: 3,
+            ..Default::default()
+        });
+        let mut events = pool.subscribe();
+        let (alice, bob) = (secret(1), secret(2));
+
+        let (first, second) = (priced_legacy(&alice, 0, 1, 5), legacy(&alice, 1, 1));
+        pool.submit_one(second.clone()).unwrap();
+        pool.submit_one(first.clone()).unwrap();
+        let replacement = priced_legacy(&alice, 0, 1, 10);
+        pool.submit_one(replacement.clone()).unwrap();
+        let cheap = priced_legacy(&bob, 0, 1, 2);
+        pool.submit_one(cheap.clone()).unwrap();
+        let rich = priced_legacy(&secret(3), 0, 1, 100);
+        pool.submit_one(rich.clone()).unwrap();
+
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            received.push(event);
+        }
+        assert_eq!(
+            received,
+            vec![
+                PoolEvent::Imported(second.hash()),
+                PoolEvent::Imported(first.hash()),
+                PoolEvent::Ready(first.hash()),
+                PoolEvent::Ready(second.hash()),
+                PoolEvent::Replaced {
+                    old: first.hash(),
+                    new: replacement.hash()
+                },
+                PoolEvent::Ready(replacement.hash()),
+                PoolEvent::Imported(cheap.hash()),
+                PoolEvent::Ready(cheap.hash()),
+                PoolEvent::Dropped(second.hash()),
+                PoolEvent::Imported(rich.hash()),
+                PoolEvent::Ready(rich.hash()),
+            ]
+        );
+    }
+
+    #[test]
+    fn maintain_prunes_included_and_stale() {
+        let pool = pool();
+        let (alice, bob) = (secret(1), secret(2));
+        let txs = (0..3)
+            .map(|nonce| legacy(&alice, nonce, 1))
+            .collect::<Vec<_>>();
+        for tx in &txs {
+            pool.submit_one(tx.clone()).unwrap();
+        }
+        pool.submit_one(legacy(&bob, 0, 1)).unwrap();
+        let mut events = pool.subscribe();
+
+        // The block also consumed nonce 1 with a transaction the pool never saw.
+        pool.state
+            .0
+            .write()
+            .unwrap()
+            .insert(address(&alice), U256::from(2));
+        pool.maintain(&block(1, &[&txs[0]]));
+
+        assert_eq!(
+            events.try_recv().unwrap(),
+            PoolEvent::Included {
+                hash: txs[0].hash(),
+                height: 1
+            }
+        );
+        assert_eq!(
+            events.try_recv().unwrap(),
+            PoolEvent::Dropped(txs[1].hash())
+        );
+        assert!(events.try_recv().is_err());
+        assert_eq!(
+            pool.status(),
+            PoolStatus {
+                ready: 2,
+                future: 0
+            }
+        );
+        assert!(pool.transaction(&txs[2].hash()).is_some());
+    }
+
+    #[test]
+    fn maintain_expires_transactions() {
+        let pool = pool_with(PoolConfig {
+            tx_lifetime: Some(2),
+            ..Default::default()
+        });
+        let alice = secret(1);
+        let first = legacy(&alice, 0, 1);
+        pool.submit_one(first.clone()).unwrap();
+        assert_eq!(
+            pool.transaction(&first.hash()).unwrap().valid_until_block,
+            2
+        );
+
+        pool.maintain(&block(1, &[]));
+        let second = legacy(&alice, 1, 1);
+        pool.submit_one(second.clone()).unwrap();
+        assert_eq!(pool.status().ready, 2);
+
+        pool.maintain(&block(2, &[]));
+        assert!(pool.transaction(&first.hash()).is_none());
+        // The nonce gap left behind moves the later transaction back to the
+        // future queue.
+        assert_eq!(
+            pool.status(),
+            PoolStatus {
+                ready: 0,
+                future: 1
+            }
+        );
+    }
+
+    #[test]
+    fn keeps_transactions_without_lifetime() {
+        let pool = pool();
+        let alice = secret(1);
+        let tx = legacy(&alice, 0, 1);
+        pool.submit_one(tx.clone()).unwrap();
+        assert_eq!(pool.transaction(&tx.hash()).unwrap().valid_until_block, 0);
+
+        pool.maintain(&block(1000, &[]));
+        assert!(pool.transaction(&tx.hash()).is_some());
+    }
+
+    #[test]
+    fn honors_valid_until_block_of_proto_transactions() {
+        let pool = pool_with(PoolConfig {
+            tx_lifetime: Some(2),
+            ..Default::default()
+        });
+        let alice = secret(1);
+        let proto = |tx: &TransactionV2, valid_until_block| {
+            let mut proto = proto::Transaction::new();
+            proto.data = tx.encode().to_vec();
+            proto.valid_until_block = valid_until_block;
+            proto
+        };
+
+        let first = legacy(&alice, 0, 1);
+        pool.submit_proto(&proto(&first, 5)).unwrap();
+        assert_eq!(
+            pool.transaction(&first.hash()).unwrap().valid_until_block,
+            5
+        );
+        let second = legacy(&alice, 1, 1);
+        pool.submit_proto(&proto(&second, 0)).unwrap();
+        assert_eq!(
+            pool.transaction(&second.hash()).unwrap().valid_until_block,
+            2
+        );
+
+        pool.maintain(&block(3, &[]));
+        assert!(pool.transaction(&second.hash()).is_none());
+        assert!(pool.transaction(&first.hash()).is_some());
+        assert!(matches!(
+            pool.submit_proto(&proto(&legacy(&alice, 1, 1), 3)),
+            Err(Error::Expired {
+                valid_until_block: 3,
+                height: 3
+            })
+        ));
+        assert!(matches!(
+            pool.submit_proto(&proto::Transaction::new()),
+            Err(Error::InvalidEncoding(_))
+        ));
+    }
+
+    #[test]
+    fn restores_journaled_transactions() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PoolConfig {
+            journal: Some(dir.path().join("transactions.rlp")),
+            ..Default::default()
+        };
+        let state = Arc::new(State::default());
+        let (alice, bob) = (secret(1), secret(2));
+
+        let pool = BasicPool::new(config.clone(), state.clone());
+        let included = legacy(&alice, 0, 1);
+        let pending = [legacy(&alice, 1, 1), legacy(&bob, 3, 1)];
+        for tx in [&included, &pending[0], &pending[1]] {
+            pool.submit_one(tx.clone()).unwrap();
+        }
+        drop(pool);
+
+        state
+            .0
+            .write()
+            .unwrap()
+            .insert(address(&alice), U256::one());
+        let pool = BasicPool::new(config.clone(), state.clone());
+        assert!(pool.transaction(&included.hash()).is_none());
+        for tx in &pending {
+            assert!(pool.transaction(&tx.hash()).is_some());
+        }
+        assert_eq!(
+            pool.status(),
+            PoolStatus {
+                ready: 1,
+                future: 1
+            }
+        );
 
-    fn ready(&self) -> Vec<TransactionV2> {
-        todo!()
+        // The restored pool compacted the journal down to its content.
+        let journal = Journal::new(config.journal.unwrap()).load().unwrap();
+        assert_eq!(journal, pending.to_vec());
     }
 }
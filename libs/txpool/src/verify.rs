@@ -0,0 +1,74 @@
+use ethereum::{
+    EIP1559TransactionMessage, EIP2930TransactionMessage, LegacyTransactionMessage, TransactionV2,
+};
+use ethereum_types::{H160, H256};
+use hashing::keccak_256;
+
+use crate::error::{Error, Result};
+
+/// Checks that `tx` is signed for `chain_id`.
+///
+/// Legacy transactions signed without EIP-155 replay protection carry no chain id
+/// and are only accepted when `allow_unprotected` is set.
+pub fn check_chain_id(tx: &TransactionV2, chain_id: u64, allow_unprotected: bool) -> Result<()> {
+    let got = match tx {
+        TransactionV2::Legacy(t) => match t.signature.chain_id() {
+            None if allow_unprotected => return Ok(()),
+            id => id,
+        },
+        TransactionV2::EIP2930(t) => Some(t.chain_id),
+        TransactionV2::EIP1559(t) => Some(t.chain_id),
+    };
+
+    if got != Some(chain_id) {
+        return Err(Error::InvalidChainId {
+            expected: chain_id,
+            got,
+        });
+    }
+
+    Ok(())
+}
+
+/// Recovers the sender address from the secp256k1 signature of `tx`.
+pub fn recover_sender(tx: &TransactionV2) -> Result<H160> {
+    let mut sig = [0u8; 64];
+    let (hash, recid) = match tx {
+        TransactionV2::Legacy(t) => {
+            sig[0..32].copy_from_slice(t.signature.r().as_bytes());
+            sig[32..64].copy_from_slice(t.signature.s().as_bytes());
+            let hash = LegacyTransactionMessage::from(t.clone()).hash();
+            (hash, t.signature.standard_v())
+        }
+        TransactionV2::EIP2930(t) => {
+            sig[0..32].copy_from_slice(t.r.as_bytes());
+            sig[32..64].copy_from_slice(t.s.as_bytes());
+            let hash = EIP2930TransactionMessage::from(t.clone()).hash();
+            (hash, t.odd_y_parity as u8)
+        }
+        TransactionV2::EIP1559(t) => {
+            sig[0..32].copy_from_slice(t.r.as_bytes());
+            sig[32..64].copy_from_slice(t.s.as_bytes());
+            let hash = EIP1559TransactionMessage::from(t.clone()).hash();
+            (hash, t.odd_y_parity as u8)
+        }
+    };
+
+    let signature = libsecp256k1::Signature::parse_standard(&sig)
+        .map_err(|_| Error::InvalidSignature("r or s out of range"))?;
+    if signature.r.is_zero() || signature.s.is_zero() {
+        return Err(Error::InvalidSignature("r or s is zero"));
+    }
+    if signature.s.is_high() {
+        return Err(Error::InvalidSignature("s value is not canonical"));
+    }
+    let recid = libsecp256k1::RecoveryId::parse(recid)
+        .map_err(|_| Error::InvalidSignature("invalid recovery id"))?;
+    let message = libsecp256k1::Message::parse(&hash.0);
+    let public = libsecp256k1::recover(&message, &signature, &recid)
+        .map_err(|_| Error::InvalidSignature("fail to recover signer"))?;
+
+    let mut res = [0u8; 64];
+    res.copy_from_slice(&public.serialize()[1..65]);
+    Ok(H160::from(H256::from(keccak_256(&res))))
+}